@@ -0,0 +1,13 @@
+-- パスワード履歴テーブル
+CREATE TABLE password_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    password_id INTEGER NOT NULL,
+    identifier_ulid TEXT NOT NULL,
+    encrypted_value BLOB NOT NULL,
+    nonce BLOB NOT NULL,
+    set_at DATETIME,
+    replaced_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(identifier_ulid) REFERENCES identifiers(ulid) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_password_history_identifier_ulid ON password_history(identifier_ulid);
@@ -0,0 +1,12 @@
+-- アカウントの変更履歴テーブル（削除後も復元できるよう外部キーは設定しない）
+CREATE TABLE account_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account_ulid TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    encrypted_value BLOB,
+    nonce BLOB,
+    undone INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_account_revisions_account_ulid ON account_revisions(account_ulid);
@@ -0,0 +1,4 @@
+-- ゴミ箱（論理削除）用の削除日時
+ALTER TABLE accounts ADD COLUMN deleted_at DATETIME;
+
+CREATE INDEX idx_accounts_deleted_at ON accounts(deleted_at);
@@ -0,0 +1,26 @@
+-- 監査ログテーブル（秘密情報は保存しない）
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY,
+    operation TEXT NOT NULL,
+    account_ulid TEXT,
+    interface TEXT NOT NULL,
+    succeeded INTEGER NOT NULL,
+    created_at DATETIME NOT NULL,
+    prev_hash TEXT NOT NULL,
+    hash TEXT NOT NULL
+);
+
+CREATE INDEX idx_audit_log_account_ulid ON audit_log(account_ulid);
+
+-- 追記のみ許可
+CREATE TRIGGER audit_log_no_update
+BEFORE UPDATE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
+
+CREATE TRIGGER audit_log_no_delete
+BEFORE DELETE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
@@ -0,0 +1,2 @@
+-- 楽観的排他制御用のバージョン（変更のたびに加算）
+ALTER TABLE accounts ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
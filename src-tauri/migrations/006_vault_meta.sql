@@ -0,0 +1,5 @@
+-- 保管庫全体の設定値（暗号化キーの照合用の値など）
+CREATE TABLE IF NOT EXISTS vault_meta (
+    name TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
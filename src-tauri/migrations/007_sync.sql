@@ -0,0 +1,26 @@
+-- 同期相手ごとに、最後に取り込んだ相手側のアカウントの状態（三方向マージの基準、暗号化して保存）
+CREATE TABLE sync_bases (
+    peer_device_id TEXT NOT NULL,
+    account_ulid TEXT NOT NULL,
+    encrypted_value TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    PRIMARY KEY (peer_device_id, account_ulid)
+);
+
+-- 完全に削除したアカウント（他の端末にも削除を伝えるために残す）
+CREATE TABLE sync_tombstones (
+    account_ulid TEXT PRIMARY KEY,
+    deleted_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 自動で統合できなかった変更（相手側の値を暗号化して保存し、手動で解決するまで残す）
+CREATE TABLE sync_conflicts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account_ulid TEXT NOT NULL,
+    peer_device_id TEXT NOT NULL,
+    field TEXT NOT NULL,
+    encrypted_value TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(account_ulid, field)
+);
@@ -0,0 +1,5 @@
+-- 同期サーバーごとの、前回取得した変更セットの位置（DBを復元した場合は位置も一緒に戻る）
+CREATE TABLE sync_server_cursors (
+    server_url TEXT PRIMARY KEY,
+    revision INTEGER NOT NULL DEFAULT 0
+);
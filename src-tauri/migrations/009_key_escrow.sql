@@ -0,0 +1,7 @@
+-- 管理者の公開鍵ごとに、保管庫のキーを age 形式で暗号化した控え（鍵をなくした場合の復旧用）
+CREATE TABLE key_escrows (
+    recipient TEXT PRIMARY KEY,
+    wrapped_key TEXT NOT NULL,
+    key_fingerprint TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
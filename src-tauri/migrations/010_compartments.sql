@@ -0,0 +1,12 @@
+-- 別のパスフレーズで暗号化するカテゴリ（key_check はコンパートメントのキーで暗号化した照合用の値）
+CREATE TABLE compartments (
+    category_id INTEGER PRIMARY KEY,
+    salt TEXT NOT NULL,
+    key_check TEXT NOT NULL,
+    relock_minutes INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(category_id) REFERENCES categories(id) ON DELETE CASCADE
+);
+
+-- スナップショットを暗号化したコンパートメント（保管庫のキーの場合は NULL）
+ALTER TABLE account_revisions ADD COLUMN compartment_id INTEGER;
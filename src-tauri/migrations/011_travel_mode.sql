@@ -0,0 +1,8 @@
+-- トラベルモード中に端末から外したアカウントを含むDBのスナップショット（トラベルモードのキーで暗号化、1行のみ）
+CREATE TABLE travel_seal (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    salt TEXT NOT NULL,
+    sealed BLOB NOT NULL,
+    safe_categories TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
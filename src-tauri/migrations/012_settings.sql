@@ -0,0 +1,6 @@
+-- 保管庫ごとの設定（値はJSON、このバージョンが知らない項目も削除せずに残す）
+CREATE TABLE settings (
+    name TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
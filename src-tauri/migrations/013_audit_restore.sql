@@ -0,0 +1,3 @@
+-- バックアップから復元した記録に、復元前の最新の記録（ID とハッシュ）を残す
+-- （復元で記録が巻き戻ったことを、チェーンの中で確認できるようにする）
+ALTER TABLE audit_log ADD COLUMN replaced_head TEXT;
@@ -0,0 +1,18 @@
+-- 同じアカウントの同じ項目でも、同期相手ごとに競合を残す
+-- （相手の端末が異なる競合で、先に取り込んだ端末の値を上書きしないようにする）
+CREATE TABLE sync_conflicts_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account_ulid TEXT NOT NULL,
+    peer_device_id TEXT NOT NULL,
+    field TEXT NOT NULL,
+    encrypted_value TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(account_ulid, peer_device_id, field)
+);
+
+INSERT INTO sync_conflicts_new (id, account_ulid, peer_device_id, field, encrypted_value, nonce, created_at)
+SELECT id, account_ulid, peer_device_id, field, encrypted_value, nonce, created_at FROM sync_conflicts;
+
+DROP TABLE sync_conflicts;
+ALTER TABLE sync_conflicts_new RENAME TO sync_conflicts;
@@ -0,0 +1,31 @@
+-- 同期サーバーのデータベース（暗号化された変更セットのみを保存し、平文は扱わない）
+
+-- 保管庫（vault_id はキーの識別子、auth_verifier は保管庫のキーから派生した値のハッシュ）
+CREATE TABLE server_vaults (
+    vault_id TEXT PRIMARY KEY,
+    auth_verifier TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 保管庫ごとに登録された端末の署名用の公開鍵
+CREATE TABLE server_devices (
+    vault_id TEXT NOT NULL,
+    device_id TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (vault_id, device_id),
+    FOREIGN KEY (vault_id) REFERENCES server_vaults(vault_id)
+);
+
+-- 端末ごとの最新の変更セット（revision は取得済みの位置を表す、全体で増え続ける番号）
+CREATE TABLE server_change_sets (
+    vault_id TEXT NOT NULL,
+    device_id TEXT NOT NULL,
+    revision INTEGER NOT NULL,
+    blob BLOB NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (vault_id, device_id),
+    FOREIGN KEY (vault_id, device_id) REFERENCES server_devices(vault_id, device_id)
+);
+
+CREATE INDEX idx_server_change_sets_revision ON server_change_sets (vault_id, revision);
@@ -0,0 +1,2 @@
+-- 端末ごとに最後に受け付けたリクエストの番号（これより大きい番号のリクエストのみ受け付け、再送を拒否する）
+ALTER TABLE server_devices ADD COLUMN last_nonce INTEGER NOT NULL DEFAULT 0;
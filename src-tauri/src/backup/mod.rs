@@ -0,0 +1,342 @@
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+use crate::crypto::{
+    get_encryption_key, key_fingerprint, open_archive, remove_file_securely, run_crypto,
+    seal_archive,
+};
+use crate::database::{check_schema_compatible, create_pool};
+use crate::error::{AppError, Result};
+use crate::models::BackupInfo;
+use crate::repository;
+use crate::settings::load_vault_settings;
+use crate::vault::{current_vault_dir, current_vault_id};
+
+const BACKUP_DIR: &str = "backups";
+pub const BACKUP_EXTENSION: &str = "jbak";
+const SNAPSHOT_FILE: &str = "snapshot.sqlite";
+const RESTORE_FILE: &str = "restore.sqlite";
+
+// バックアップファイルの形式: ヘッダー（識別子・キーの識別子・作成日時）+ Nonce + 暗号文
+const ARCHIVE_MAGIC: &[u8; 8] = b"JASMBAK1";
+const FINGERPRINT_LEN: usize = 16;
+const CREATED_AT_LEN: usize = 19;
+const HEADER_LEN: usize = ARCHIVE_MAGIC.len() + FINGERPRINT_LEN + CREATED_AT_LEN;
+
+fn backup_locks() -> &'static Mutex<HashMap<String, Arc<AsyncMutex<()>>>> {
+    static BACKUP_LOCKS: OnceLock<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>> = OnceLock::new();
+    BACKUP_LOCKS.get_or_init(Default::default)
+}
+
+// スナップショットや復元用のファイルを同時に書き込まないよう、保管庫ごとにバックアップと復元を直列化する
+// （別の保管庫のバックアップは待たない）
+async fn lock_backups() -> Result<OwnedMutexGuard<()>> {
+    let lock = backup_locks()
+        .lock()
+        .unwrap()
+        .entry(current_vault_id()?)
+        .or_default()
+        .clone();
+
+    Ok(lock.lock_owned().await)
+}
+
+// バックアップの保存先（処理中の保管庫のディレクトリ）
+fn get_backup_dir() -> Result<PathBuf> {
+    Ok(current_vault_dir()?.join(BACKUP_DIR))
+}
+
+struct ArchiveHeader {
+    fingerprint: [u8; FINGERPRINT_LEN],
+    created_at: String,
+}
+
+impl ArchiveHeader {
+    fn to_bytes(&self) -> Vec<u8> {
+        [
+            ARCHIVE_MAGIC.as_slice(),
+            &self.fingerprint,
+            self.created_at.as_bytes(),
+        ]
+        .concat()
+    }
+
+    // バックアップファイルでない場合や、ヘッダーが壊れている場合はTampered
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_LEN || !bytes.starts_with(ARCHIVE_MAGIC) {
+            return Err(AppError::Tampered);
+        }
+        let (fingerprint, created_at) =
+            bytes[ARCHIVE_MAGIC.len()..HEADER_LEN].split_at(FINGERPRINT_LEN);
+
+        Ok(ArchiveHeader {
+            fingerprint: fingerprint.try_into().map_err(|_| AppError::Tampered)?,
+            created_at: String::from_utf8(created_at.to_vec()).map_err(|_| AppError::Tampered)?,
+        })
+    }
+}
+
+fn read_backup_info(path: &Path) -> Result<BackupInfo> {
+    let mut header = [0u8; HEADER_LEN];
+    std::fs::File::open(path)?.read_exact(&mut header)?;
+    let header = ArchiveHeader::parse(&header)?;
+
+    Ok(BackupInfo {
+        file_name: path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        created_at: header.created_at,
+        size: std::fs::metadata(path)?.len(),
+    })
+}
+
+// 保管庫のバックアップを新しい順に取得（読み取れないファイルは除く）
+pub fn list_backups() -> Result<Vec<BackupInfo>> {
+    let backup_dir = get_backup_dir()?;
+    if !backup_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(backup_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some(BACKUP_EXTENSION) {
+            continue;
+        }
+        if let Ok(backup) = read_backup_info(&path) {
+            backups.push(backup);
+        }
+    }
+
+    // ファイル名は ULID のため、名前順が作成順になる
+    backups.sort_by(|a, b| b.file_name.cmp(&a.file_name));
+
+    Ok(backups)
+}
+
+// 一覧にあるバックアップのみ指定できるようにする（パスの指定による外部ファイルの読み込みを防ぐ）
+fn find_backup_path(file_name: &str) -> Result<PathBuf> {
+    let backup = list_backups()?
+        .into_iter()
+        .find(|backup| backup.file_name == file_name)
+        .ok_or_else(|| AppError::NotFound("Backup".to_string()))?;
+
+    Ok(get_backup_dir()?.join(backup.file_name))
+}
+
+// このアプリが作成するバックアップのファイル名（backup-<ULID>.jbak）か確認
+pub fn is_backup_file_name(file_name: &str) -> bool {
+    file_name
+        .strip_prefix("backup-")
+        .and_then(|name| name.strip_suffix(&format!(".{}", BACKUP_EXTENSION)))
+        .is_some_and(|ulid| ulid::Ulid::from_string(ulid).is_ok())
+}
+
+// 暗号化済みのバックアップファイルをそのまま読み込む（リモートへのアップロードに使う）
+pub fn read_backup(file_name: &str) -> Result<Vec<u8>> {
+    Ok(std::fs::read(find_backup_path(file_name)?)?)
+}
+
+// リモートから取得したバックアップを保管庫のバックアップに加える
+// （バックアップの形式でないファイルは保存しない、復号の確認は復元時に行う）
+pub fn import_backup(file_name: &str, archive: &[u8]) -> Result<()> {
+    if !is_backup_file_name(file_name) {
+        return Err(AppError::NotFound("Backup".to_string()));
+    }
+    ArchiveHeader::parse(archive)?;
+
+    let backup_dir = get_backup_dir()?;
+    std::fs::create_dir_all(&backup_dir)?;
+
+    let backup_path = backup_dir.join(file_name);
+    let temp_path = backup_path.with_extension("tmp");
+    std::fs::write(&temp_path, archive)?;
+    std::fs::rename(&temp_path, &backup_path)?;
+
+    Ok(())
+}
+
+// DBの整合性のとれたスナップショットを書き出して読み込む
+// （VACUUM INTO は WAL に残っている変更も含めて書き出す、平文のファイルは読み込んだらすぐに削除する）
+pub async fn take_snapshot(sqlite_pool: &SqlitePool, snapshot_path: &Path) -> Result<Vec<u8>> {
+    remove_file_securely(snapshot_path)?;
+    sqlx::query("VACUUM INTO ?")
+        .bind(snapshot_path.to_string_lossy().as_ref())
+        .execute(sqlite_pool)
+        .await?;
+
+    let snapshot = std::fs::read(snapshot_path);
+    remove_file_securely(snapshot_path)?;
+    Ok(snapshot?)
+}
+
+// スナップショットを保管庫のキーで暗号化し、バックアップファイルの内容（ヘッダー + 暗号文）を作成
+pub async fn seal_snapshot(sqlite_pool: &SqlitePool, snapshot_path: &Path) -> Result<Vec<u8>> {
+    let key = get_encryption_key()?;
+    let snapshot = take_snapshot(sqlite_pool, snapshot_path).await?;
+
+    let created_at: String = sqlx::query_scalar("SELECT datetime('now')")
+        .fetch_one(sqlite_pool)
+        .await?;
+    let header = ArchiveHeader {
+        fingerprint: key_fingerprint(&key),
+        created_at,
+    };
+
+    let header_bytes = header.to_bytes();
+    let sealed = run_crypto(move || seal_archive(&key, &header_bytes, &snapshot)).await?;
+
+    Ok([header.to_bytes(), sealed].concat())
+}
+
+// DBの整合性のとれたスナップショットを暗号化してバックアップを作成
+pub async fn create_backup(sqlite_pool: &SqlitePool) -> Result<BackupInfo> {
+    // 保管庫と一致しないキーで暗号化すると復元できないため、先に照合する
+    repository::key_check::verify_encryption_key(sqlite_pool).await?;
+
+    let _guard = lock_backups().await?;
+
+    let backup_dir = get_backup_dir()?;
+    std::fs::create_dir_all(&backup_dir)?;
+
+    let archive = seal_snapshot(sqlite_pool, &backup_dir.join(SNAPSHOT_FILE)).await?;
+
+    // 書き込み途中のファイルが一覧に含まれないよう、書き終えてから名前を変更する
+    let file_name = format!("backup-{}.{}", ulid::Ulid::new(), BACKUP_EXTENSION);
+    let backup_path = backup_dir.join(&file_name);
+    let temp_path = backup_path.with_extension("tmp");
+    std::fs::write(&temp_path, archive)?;
+    std::fs::rename(&temp_path, &backup_path)?;
+
+    rotate_backups(load_vault_settings(sqlite_pool).await?.backup.keep)?;
+
+    read_backup_info(&backup_path)
+}
+
+// 保管庫のバックアップをすべて削除（トラベルモードで、外したアカウントを保管庫のキーで復元できないようにする）
+pub async fn remove_all_backups() -> Result<()> {
+    let _guard = lock_backups().await?;
+
+    let backup_dir = get_backup_dir()?;
+    for backup in list_backups()? {
+        remove_file_securely(&backup_dir.join(backup.file_name))?;
+    }
+
+    Ok(())
+}
+
+// 保持件数を超えた古いバックアップを削除（0は無制限）
+fn rotate_backups(keep: u32) -> Result<()> {
+    if keep == 0 {
+        return Ok(());
+    }
+
+    let backup_dir = get_backup_dir()?;
+    for backup in list_backups()?.into_iter().skip(keep as usize) {
+        std::fs::remove_file(backup_dir.join(backup.file_name))?;
+    }
+
+    Ok(())
+}
+
+// 前回のバックアップから一定数の変更があった場合か、変更があって一定時間が経った場合にバックアップ
+// （どちらも0の場合は自動でバックアップしない）
+pub async fn run_scheduled_backup(sqlite_pool: &SqlitePool) -> Result<Option<BackupInfo>> {
+    let settings = load_vault_settings(sqlite_pool).await?.backup;
+    let every_changes = settings.every_changes;
+    let interval_hours = settings.interval_hours;
+    if every_changes == 0 && interval_hours == 0 {
+        return Ok(None);
+    }
+
+    let due = match list_backups()?.first() {
+        None => repository::read::has_accounts(sqlite_pool).await?,
+        Some(latest) => {
+            let changes: u32 = sqlx::query_scalar(
+                r#"
+                SELECT COUNT(*) FROM account_revisions WHERE created_at > ?
+                "#,
+            )
+            .bind(&latest.created_at)
+            .fetch_one(sqlite_pool)
+            .await?;
+
+            let interval_elapsed: bool = sqlx::query_scalar(
+                r#"
+                SELECT datetime(?, '+' || ? || ' hours') <= datetime('now')
+                "#,
+            )
+            .bind(&latest.created_at)
+            .bind(interval_hours)
+            .fetch_one(sqlite_pool)
+            .await?;
+
+            (every_changes > 0 && changes >= every_changes)
+                || (interval_hours > 0 && changes > 0 && interval_elapsed)
+        }
+    };
+
+    if !due {
+        return Ok(None);
+    }
+    create_backup(sqlite_pool).await.map(Some)
+}
+
+// バックアップを復号し、整合性・スキーマ・キーを確認したDBファイルを書き出す
+// （保管庫の入れ替えは、呼び出し側で保管庫を閉じてから行う）
+pub async fn prepare_restore(file_name: &str) -> Result<PathBuf> {
+    let _guard = lock_backups().await?;
+
+    let archive = read_backup(file_name)?;
+    let header = ArchiveHeader::parse(&archive)?;
+
+    // 現在のキーで作成されたバックアップか確認
+    let key = get_encryption_key()?;
+    if header.fingerprint != key_fingerprint(&key) {
+        return Err(AppError::WrongKey);
+    }
+    let snapshot = run_crypto(move || {
+        let (header, sealed) = archive.split_at(HEADER_LEN);
+        open_archive(&key, header, sealed)
+    })
+    .await?;
+
+    let restore_path = get_backup_dir()?.join(RESTORE_FILE);
+    remove_file_securely(&restore_path)?;
+    std::fs::write(&restore_path, snapshot)?;
+
+    let verified = verify_restored_database(&restore_path).await;
+    if verified.is_err() {
+        remove_file_securely(&restore_path).ok();
+    }
+    verified.map(|()| restore_path)
+}
+
+async fn verify_restored_database(restore_path: &Path) -> Result<()> {
+    let database_url = format!(
+        "sqlite://{}",
+        restore_path.to_string_lossy().replace('\\', "/")
+    );
+    let sqlite_pool = create_pool(&database_url).await?;
+
+    let result = async {
+        let integrity: String = sqlx::query_scalar("PRAGMA integrity_check")
+            .fetch_one(&sqlite_pool)
+            .await?;
+        if integrity != "ok" {
+            return Err(AppError::Tampered);
+        }
+        check_schema_compatible(&sqlite_pool).await?;
+        repository::key_check::verify_encryption_key(&sqlite_pool).await
+    }
+    .await;
+
+    sqlite_pool.close().await;
+
+    result
+}
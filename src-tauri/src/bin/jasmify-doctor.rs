@@ -0,0 +1,8 @@
+// 保管庫の点検と修復を行うツール（アプリを終了してから実行する）
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = local_password_manager_lib::run_doctor(&args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,8 @@
+// 管理者用のツール（預けられた保管庫のキーを管理者の秘密鍵で復号する）
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = local_password_manager_lib::run_escrow_admin(&args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
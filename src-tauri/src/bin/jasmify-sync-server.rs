@@ -0,0 +1,7 @@
+// 同期サーバー（暗号化された変更セットの保存と受け渡しのみを行う）
+fn main() {
+    if let Err(e) = local_password_manager_lib::run_sync_server() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
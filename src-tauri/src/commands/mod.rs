@@ -1,38 +1,162 @@
-use tauri::async_runtime::block_on;
-use tauri::State;
+use sqlx::SqlitePool;
+use std::path::PathBuf;
+use tauri::{AppHandle, State};
+use tracing::instrument;
 
 use crate::{
-    models::{self, AccountInfo, AccountSummary, FormData, PasswordInfo, SearchCriteria},
-    repository,
+    backup, compartment,
+    crypto::get_encryption_key,
+    database, doctor,
+    error::{AppError, Result},
+    escrow,
+    models::{
+        self, AccountInfo, AccountRevision, AccountSummary, AppSettings, AuditLogEntry,
+        AuditLogFilter, AuditLogVerification, BackupInfo, CompartmentInfo, DataLocation,
+        DoctorReport, FormData, KeyEscrowInfo, KeySource, MigrationReport, PasswordHistory,
+        PasswordInfo, RemoteBackupInfo, ScrubReport, SearchCriteria, StartupError, SyncConflict,
+        SyncReport, TrashedAccount, TravelModeInfo, VaultInfo, VaultSettings,
+    },
+    paths::{get_data_dir, get_data_dir_source},
+    remote,
+    repository::{
+        self,
+        audit::{append_audit_log, AuditInterface},
+        key_check::verify_encryption_key,
+    },
+    settings, share,
+    startup::StartupState,
+    sync, travel,
+    vault::{OpenVault, VaultManager, DEFAULT_VAULT_ID},
 };
 
-#[tauri::command]
-pub fn insert_form_data(
-    sqlite_pool: State<'_, sqlx::SqlitePool>,
-    form_data: models::FormData,
-) -> Result<(), String> {
-    if let Err(e) = block_on(repository::insert::insert_new_account(
-        &sqlite_pool,
-        form_data,
-    )) {
-        return Err(e.to_string());
+// 監査ログに操作を記録（パスワードや識別子などの秘密情報は渡さない）
+// 操作は記録の前に確定しているため、記録に失敗しても操作の結果は変えずにログに残す
+async fn record_audit(
+    sqlite_pool: &SqlitePool,
+    operation: &str,
+    account_ulid: Option<&str>,
+    succeeded: bool,
+) {
+    if let Err(e) = append_audit_log(
+        sqlite_pool,
+        operation,
+        account_ulid,
+        AuditInterface::Gui,
+        succeeded,
+    )
+    .await
+    {
+        tracing::error!(operation, error = %e, "Recording the audit log failed");
+    }
+}
+
+// 復元したDBに、復元前の最新の記録を残して復元を記録する（記録の失敗は record_audit と同じく扱う）
+async fn record_restore(sqlite_pool: &SqlitePool, operation: &str) {
+    if let Err(e) =
+        repository::audit::append_restore_record(sqlite_pool, operation, AuditInterface::Gui).await
+    {
+        tracing::error!(operation, error = %e, "Recording the restore in the audit log failed");
+    }
+}
+
+// 保管庫の操作は、対象の保管庫（開いていない場合は現在の保管庫）の監査ログに記録
+async fn record_vault_audit(
+    vaults: &VaultManager,
+    operation: &str,
+    vault_id: Option<&str>,
+    succeeded: bool,
+) {
+    let vault = match vault_id.and_then(|vault_id| vaults.get(vault_id)) {
+        Some(vault) => vault,
+        None => match vaults.current() {
+            Ok(vault) => vault,
+            Err(_) => return,
+        },
     };
+    vault
+        .scope(async { record_audit(&vault.pool, operation, None, succeeded).await })
+        .await;
+}
 
-    Ok(())
+// 復号に失敗した場合、キーの違いによるものかデータの破損によるものかを区別して返す
+async fn classify_error(sqlite_pool: &SqlitePool, e: AppError) -> AppError {
+    match e {
+        AppError::Tampered => match verify_encryption_key(sqlite_pool).await {
+            Err(AppError::WrongKey) => AppError::WrongKey,
+            _ => AppError::Tampered,
+        },
+        e => e,
+    }
 }
 
-#[tauri::command]
-pub fn get_account_summary(
-    sqlite_pool: State<'_, sqlx::SqlitePool>,
-) -> Result<Vec<AccountSummary>, String> {
-    let summary = match block_on(repository::read::get_account_summary(&sqlite_pool)) {
-        Ok(data) => data,
-        Err(e) => {
-            return Err(e.to_string());
+// コマンドの監査ログに記録する内容
+struct Audit {
+    operation: &'static str,
+    // 対象のアカウント（複数の場合はアカウントごとに記録し、ない場合は保管庫に対する操作として記録）
+    account_ulids: Vec<String>,
+    // 削除や上書きをするコマンドは、成功した場合に WAL を空にし、変更前の内容が WAL に残らないようにする
+    truncate_wal: bool,
+}
+
+impl Audit {
+    fn new(operation: &'static str) -> Self {
+        Audit {
+            operation,
+            account_ulids: Vec::new(),
+            truncate_wal: false,
         }
-    };
+    }
+
+    fn overwriting(operation: &'static str) -> Self {
+        Audit {
+            truncate_wal: true,
+            ..Audit::new(operation)
+        }
+    }
+
+    fn account(mut self, account_ulid: &str) -> Self {
+        self.account_ulids.push(account_ulid.to_string());
+        self
+    }
+}
+
+// 現在の保管庫を対象としてコマンドを実行し、結果を監査ログに記録する
+// 記録できない状態（キーがない場合）では、変更を行う前にエラーにする
+async fn run_command<T>(
+    vaults: &VaultManager,
+    mut audit: Audit,
+    command: impl AsyncFnOnce(&OpenVault, &mut Audit) -> Result<T>,
+) -> Result<T> {
+    let vault = vaults.current()?;
+    vault
+        .scope(async {
+            let sqlite_pool = &vault.pool;
+            get_encryption_key()?;
 
-    let account_summary: Vec<AccountSummary> = summary
+            let result = command(&vault, &mut audit).await;
+            if result.is_err() || audit.account_ulids.is_empty() {
+                record_audit(sqlite_pool, audit.operation, None, result.is_ok()).await;
+            } else {
+                for account_ulid in &audit.account_ulids {
+                    record_audit(sqlite_pool, audit.operation, Some(account_ulid), true).await;
+                }
+            }
+
+            match result {
+                Ok(value) => {
+                    if audit.truncate_wal {
+                        database::truncate_wal(sqlite_pool).await?;
+                    }
+                    Ok(value)
+                }
+                Err(e) => Err(classify_error(sqlite_pool, e).await),
+            }
+        })
+        .await
+}
+
+fn to_account_summaries(summary: Vec<AccountSummary>) -> Vec<AccountSummary> {
+    summary
         .into_iter()
         .map(|data| AccountSummary {
             account_ulid: data.account_ulid,
@@ -40,87 +164,852 @@ pub fn get_account_summary(
             identifier: data.identifier,
             identifier_ulid: data.identifier_ulid,
             category_name: data.category_name,
+            version: data.version,
         })
-        .collect();
+        .collect()
+}
 
-    Ok(account_summary)
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn insert_form_data(
+    vaults: State<'_, VaultManager>,
+    form_data: models::FormData,
+) -> Result<()> {
+    run_command(
+        &vaults,
+        Audit::new("insert_form_data"),
+        async |vault, audit| {
+            let account_ulid =
+                repository::insert::insert_new_account(&vault.pool, form_data).await?;
+            audit.account_ulids.push(account_ulid);
+            Ok(())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_account_summary(vaults: State<'_, VaultManager>) -> Result<Vec<AccountSummary>> {
+    run_command(
+        &vaults,
+        Audit::new("get_account_summary"),
+        async |vault, _| {
+            let summary = repository::read::get_account_summary(&vault.pool).await?;
+            Ok(to_account_summaries(summary))
+        },
+    )
+    .await
 }
 
 #[tauri::command]
-pub fn get_search_results(
-    sqlite_pool: State<'_, sqlx::SqlitePool>,
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_search_results(
+    vaults: State<'_, VaultManager>,
     search_criteria: SearchCriteria,
-) -> Result<Vec<AccountSummary>, String> {
-    let summary = match block_on(repository::read::get_search_results(
-        &sqlite_pool,
-        search_criteria,
-    )) {
-        Ok(data) => data,
-        Err(e) => {
-            return Err(e.to_string());
-        }
-    };
+) -> Result<Vec<AccountSummary>> {
+    run_command(
+        &vaults,
+        Audit::new("get_search_results"),
+        async |vault, _| {
+            let summary =
+                repository::read::get_search_results(&vault.pool, search_criteria).await?;
+            Ok(to_account_summaries(summary))
+        },
+    )
+    .await
+}
 
-    let search_results: Vec<AccountSummary> = summary
-        .into_iter()
-        .map(|data| AccountSummary {
-            account_ulid: data.account_ulid,
-            account_name: data.account_name,
-            identifier: data.identifier,
-            identifier_ulid: data.identifier_ulid,
-            category_name: data.category_name,
-        })
-        .collect();
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_password_info(
+    vaults: State<'_, VaultManager>,
+    identifier_ulid: String,
+) -> Result<Vec<PasswordInfo>> {
+    run_command(
+        &vaults,
+        Audit::new("get_password_info"),
+        async |vault, audit| {
+            let sqlite_pool = &vault.pool;
+            audit
+                .account_ulids
+                .extend(repository::read::get_account_ulid(sqlite_pool, &identifier_ulid).await?);
+            repository::read::get_password_info(sqlite_pool, identifier_ulid).await
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn update_account_info(
+    vaults: State<'_, VaultManager>,
+    form_data: FormData,
+    account_info: AccountInfo,
+) -> Result<()> {
+    let audit = Audit::overwriting("update_account_info").account(&account_info.account_ulid);
+    run_command(&vaults, audit, async |vault, _| {
+        repository::update::update_account_info(&vault.pool, form_data, account_info).await
+    })
+    .await
+}
 
-    Ok(search_results)
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn delete_account(vaults: State<'_, VaultManager>, account_ulid: String) -> Result<()> {
+    let audit = Audit::overwriting("delete_account").account(&account_ulid);
+    run_command(&vaults, audit, async |vault, _| {
+        repository::delete::delete_account(&vault.pool, &account_ulid).await
+    })
+    .await
 }
 
 #[tauri::command]
-pub fn get_password_info(
-    sqlite_pool: State<'_, sqlx::SqlitePool>,
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_password_history(
+    vaults: State<'_, VaultManager>,
     identifier_ulid: String,
-) -> Result<Vec<PasswordInfo>, String> {
-    let password_info = match block_on(repository::read::get_password_info(
-        &sqlite_pool,
-        identifier_ulid,
-    )) {
-        Ok(data) => data,
-        Err(e) => {
-            return Err(e.to_string());
-        }
-    };
+) -> Result<Vec<PasswordHistory>> {
+    run_command(
+        &vaults,
+        Audit::new("get_password_history"),
+        async |vault, audit| {
+            let sqlite_pool = &vault.pool;
+            audit
+                .account_ulids
+                .extend(repository::read::get_account_ulid(sqlite_pool, &identifier_ulid).await?);
+            repository::history::get_password_history(sqlite_pool, identifier_ulid).await
+        },
+    )
+    .await
+}
 
-    Ok(password_info)
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn reveal_password_history(
+    vaults: State<'_, VaultManager>,
+    history_id: u32,
+) -> Result<String> {
+    run_command(
+        &vaults,
+        Audit::new("reveal_password_history"),
+        async |vault, audit| {
+            let sqlite_pool = &vault.pool;
+            audit.account_ulids.extend(
+                repository::history::get_password_history_account_ulid(sqlite_pool, history_id)
+                    .await?,
+            );
+            repository::history::reveal_password_history(sqlite_pool, history_id).await
+        },
+    )
+    .await
 }
 
 #[tauri::command]
-pub fn update_account_info(
-    sqlite_pool: State<'_, sqlx::SqlitePool>,
-    form_data: FormData,
-    account_info: AccountInfo,
-) -> Result<(), String> {
-    if let Err(e) = block_on(repository::update::update_account_info(
-        &sqlite_pool,
-        form_data,
-        account_info,
-    )) {
-        return Err(e.to_string());
-    }
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn restore_password_history(
+    vaults: State<'_, VaultManager>,
+    history_id: u32,
+) -> Result<()> {
+    run_command(
+        &vaults,
+        Audit::overwriting("restore_password_history"),
+        async |vault, audit| {
+            let account_ulid =
+                repository::history::restore_password_history(&vault.pool, history_id).await?;
+            audit.account_ulids.push(account_ulid);
+            Ok(())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn list_revisions(
+    vaults: State<'_, VaultManager>,
+    account_ulid: String,
+) -> Result<Vec<AccountRevision>> {
+    let audit = Audit::new("list_revisions").account(&account_ulid);
+    run_command(&vaults, audit, async |vault, _| {
+        repository::revision::list_revisions(&vault.pool, account_ulid).await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn revert_account_to_revision(
+    vaults: State<'_, VaultManager>,
+    revision_id: u32,
+) -> Result<()> {
+    run_command(
+        &vaults,
+        Audit::overwriting("revert_account_to_revision"),
+        async |vault, audit| {
+            let account_ulid =
+                repository::revision::revert_account_to_revision(&vault.pool, revision_id).await?;
+            audit.account_ulids.push(account_ulid);
+            Ok(())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn undo_last_change(vaults: State<'_, VaultManager>) -> Result<()> {
+    run_command(
+        &vaults,
+        Audit::overwriting("undo_last_change"),
+        async |vault, audit| {
+            let account_ulid = repository::revision::undo_last_change(&vault.pool).await?;
+            audit.account_ulids.push(account_ulid);
+            Ok(())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_trashed_accounts(vaults: State<'_, VaultManager>) -> Result<Vec<TrashedAccount>> {
+    run_command(
+        &vaults,
+        Audit::new("get_trashed_accounts"),
+        async |vault, _| repository::trash::get_trashed_accounts(&vault.pool).await,
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn restore_account(vaults: State<'_, VaultManager>, account_ulid: String) -> Result<()> {
+    let audit = Audit::new("restore_account").account(&account_ulid);
+    run_command(&vaults, audit, async |vault, _| {
+        repository::trash::restore_account(&vault.pool, &account_ulid).await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn empty_trash(vaults: State<'_, VaultManager>) -> Result<()> {
+    run_command(
+        &vaults,
+        Audit::overwriting("empty_trash"),
+        async |vault, _| repository::trash::empty_trash(&vault.pool).await,
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_audit_log(
+    vaults: State<'_, VaultManager>,
+    audit_log_filter: AuditLogFilter,
+) -> Result<Vec<AuditLogEntry>> {
+    run_command(&vaults, Audit::new("get_audit_log"), async |vault, _| {
+        repository::audit::get_audit_log(&vault.pool, audit_log_filter).await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn verify_audit_log(vaults: State<'_, VaultManager>) -> Result<AuditLogVerification> {
+    // 検証結果に今回の記録が影響しないよう、検証後に記録する
+    run_command(&vaults, Audit::new("verify_audit_log"), async |vault, _| {
+        repository::audit::verify_audit_log(&vault.pool).await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_migration_report(vaults: State<'_, VaultManager>) -> Result<MigrationReport> {
+    run_command(
+        &vaults,
+        Audit::new("get_migration_report"),
+        async |vault, _| Ok(vault.migration_report.clone()),
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_data_location(vaults: State<'_, VaultManager>) -> Result<DataLocation> {
+    run_command(
+        &vaults,
+        Audit::new("get_data_location"),
+        async |vault, _| {
+            // 以前の保存場所の保管庫は、現在の保管庫が空の場合のみ移行を提案する
+            let legacy_vault = match repository::read::has_accounts(&vault.pool).await? {
+                false if vault.id == DEFAULT_VAULT_ID => database::find_legacy_vault(),
+                _ => None,
+            };
+
+            Ok(DataLocation {
+                data_dir: get_data_dir().to_string_lossy().into_owned(),
+                source: get_data_dir_source().as_str().to_string(),
+                legacy_vault: legacy_vault.map(|path| path.to_string_lossy().into_owned()),
+            })
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn migrate_legacy_vault(app: AppHandle, vaults: State<'_, VaultManager>) -> Result<()> {
+    let legacy_vault = run_command(
+        &vaults,
+        Audit::new("migrate_legacy_vault"),
+        async |vault, _| {
+            match repository::read::has_accounts(&vault.pool).await? {
+                false if vault.id == DEFAULT_VAULT_ID => database::find_legacy_vault(),
+                _ => None,
+            }
+            .ok_or_else(|| AppError::NotFound("Vault to migrate".to_string()))
+        },
+    )
+    .await?;
+
+    // 開いているDBを閉じてからファイルを入れ替え、移行した保管庫で起動し直す
+    let vault = vaults.current()?;
+    vault.pool.close().await;
+    vault
+        .scope(async { database::import_legacy_vault(&legacy_vault) })
+        .await?;
+    app.restart();
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn list_vaults(vaults: State<'_, VaultManager>) -> Result<Vec<VaultInfo>> {
+    let result = vaults.list().await;
+    record_vault_audit(&vaults, "list_vaults", None, result.is_ok()).await;
+
+    result
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn create_vault(
+    vaults: State<'_, VaultManager>,
+    name: String,
+    key_source: KeySource,
+) -> Result<String> {
+    let result = vaults.create(&name, key_source).await;
+    record_vault_audit(&vaults, "create_vault", None, result.is_ok()).await;
+
+    result
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn open_vault(vaults: State<'_, VaultManager>, vault_id: String) -> Result<()> {
+    let result = vaults.open(&vault_id).await;
+    record_vault_audit(&vaults, "open_vault", Some(&vault_id), result.is_ok()).await;
+
+    result.map(|_| ())
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn close_vault(vaults: State<'_, VaultManager>, vault_id: String) -> Result<()> {
+    // 閉じた後は記録できないため、閉じる前に記録する
+    record_vault_audit(&vaults, "close_vault", Some(&vault_id), true).await;
+
+    vaults.close(&vault_id).await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn rename_vault(
+    vaults: State<'_, VaultManager>,
+    vault_id: String,
+    name: String,
+) -> Result<()> {
+    let result = vaults.rename(&vault_id, &name).await;
+    record_vault_audit(&vaults, "rename_vault", Some(&vault_id), result.is_ok()).await;
+
+    result
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn create_backup(vaults: State<'_, VaultManager>) -> Result<BackupInfo> {
+    run_command(&vaults, Audit::new("create_backup"), async |vault, _| {
+        backup::create_backup(&vault.pool).await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn list_backups(vaults: State<'_, VaultManager>) -> Result<Vec<BackupInfo>> {
+    run_command(&vaults, Audit::new("list_backups"), async |_, _| {
+        backup::list_backups()
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn restore_backup(vaults: State<'_, VaultManager>, file_name: String) -> Result<()> {
+    restore_vault(&vaults, "restore_backup", &file_name, false).await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn push_backups(vaults: State<'_, VaultManager>) -> Result<Vec<String>> {
+    run_command(&vaults, Audit::new("push_backups"), async |vault, _| {
+        remote::push_backups(&vault.pool).await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn list_remote_backups(vaults: State<'_, VaultManager>) -> Result<Vec<RemoteBackupInfo>> {
+    run_command(&vaults, Audit::new("list_remote_backups"), async |_, _| {
+        remote::list_remote_backups().await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn restore_remote_backup(
+    vaults: State<'_, VaultManager>,
+    file_name: String,
+) -> Result<()> {
+    restore_vault(&vaults, "restore_remote_backup", &file_name, true).await
+}
+
+// バックアップから保管庫を復元（リモートの場合は、取り込んでから同じ手順で復元する）
+async fn restore_vault(
+    vaults: &VaultManager,
+    operation: &str,
+    file_name: &str,
+    from_remote: bool,
+) -> Result<()> {
+    let vault = vaults.current()?;
+    let restored = vault
+        .scope(async {
+            let sqlite_pool = &vault.pool;
+            let result: Result<PathBuf> = async {
+                if from_remote {
+                    remote::download_backup(file_name).await?;
+                }
+                // 復号・整合性・キーの確認が済んでから、復元前の状態もバックアップしておく
+                let restored = backup::prepare_restore(file_name).await?;
+                backup::create_backup(sqlite_pool).await?;
+                Ok(restored)
+            }
+            .await;
+            if result.is_err() {
+                record_audit(sqlite_pool, operation, None, false).await;
+            }
+
+            result
+        })
+        .await?;
+
+    // 開いているDBを閉じてからファイルを入れ替え、復元したDBで開き直す
+    let vault = vaults
+        .reopen_with(&vault.id, || database::replace_database(&restored))
+        .await?;
+    vault.scope(record_restore(&vault.pool, operation)).await;
 
     Ok(())
 }
 
 #[tauri::command]
-pub fn delete_account(
-    sqlite_pool: State<'_, sqlx::SqlitePool>,
-    account_ulid: String,
-) -> Result<(), String> {
-    if let Err(e) = block_on(repository::delete::delete_account(
-        &sqlite_pool,
-        &account_ulid,
-    )) {
-        return Err(e.to_string());
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn sync_vault(vaults: State<'_, VaultManager>) -> Result<SyncReport> {
+    run_command(&vaults, Audit::new("sync_vault"), async |vault, _| {
+        sync::sync_vault(&vault.pool).await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn list_sync_conflicts(vaults: State<'_, VaultManager>) -> Result<Vec<SyncConflict>> {
+    run_command(
+        &vaults,
+        Audit::new("list_sync_conflicts"),
+        async |vault, _| repository::sync::list_sync_conflicts(&vault.pool).await,
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn resolve_sync_conflict(
+    vaults: State<'_, VaultManager>,
+    conflict_id: u32,
+    keep_remote: bool,
+) -> Result<()> {
+    run_command(
+        &vaults,
+        Audit::overwriting("resolve_sync_conflict"),
+        async |vault, _| {
+            repository::sync::resolve_sync_conflict(&vault.pool, conflict_id, keep_remote).await
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_share_public_key(vaults: State<'_, VaultManager>) -> Result<String> {
+    run_command(
+        &vaults,
+        Audit::new("get_share_public_key"),
+        async |vault, _| share::get_share_public_key(&vault.pool).await,
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn export_shared_accounts(
+    vaults: State<'_, VaultManager>,
+    account_ulids: Vec<String>,
+    recipients: Vec<String>,
+) -> Result<String> {
+    // 共有したアカウントごとに記録する
+    run_command(
+        &vaults,
+        Audit::new("export_shared_account"),
+        async |vault, audit| {
+            let shared =
+                share::export_shared_accounts(&vault.pool, &account_ulids, &recipients).await?;
+            audit.account_ulids.extend(account_ulids.iter().cloned());
+            Ok(shared)
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn import_shared_accounts(
+    vaults: State<'_, VaultManager>,
+    shared: String,
+) -> Result<Vec<String>> {
+    run_command(
+        &vaults,
+        Audit::new("import_shared_account"),
+        async |vault, audit| {
+            let account_ulids = share::import_shared_accounts(&vault.pool, &shared).await?;
+            audit.account_ulids.extend(account_ulids.iter().cloned());
+            Ok(account_ulids)
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn list_key_escrows(vaults: State<'_, VaultManager>) -> Result<Vec<KeyEscrowInfo>> {
+    run_command(&vaults, Audit::new("list_key_escrows"), async |vault, _| {
+        repository::escrow::list_key_escrows(&vault.pool).await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn set_key_escrow(
+    vaults: State<'_, VaultManager>,
+    recipients: Vec<String>,
+) -> Result<Vec<KeyEscrowInfo>> {
+    run_command(&vaults, Audit::new("set_key_escrow"), async |vault, _| {
+        escrow::set_key_escrow(&vault.pool, &recipients).await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn list_compartments(vaults: State<'_, VaultManager>) -> Result<Vec<CompartmentInfo>> {
+    run_command(
+        &vaults,
+        Audit::new("list_compartments"),
+        async |vault, _| compartment::list_compartments(&vault.pool).await,
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn create_compartment(
+    vaults: State<'_, VaultManager>,
+    category_name: String,
+    passphrase: String,
+    relock_minutes: u32,
+) -> Result<()> {
+    run_command(
+        &vaults,
+        Audit::overwriting("create_compartment"),
+        async |vault, _| {
+            compartment::create_compartment(
+                &vault.pool,
+                &category_name,
+                &passphrase,
+                relock_minutes,
+            )
+            .await
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn unlock_compartment(
+    vaults: State<'_, VaultManager>,
+    category_name: String,
+    passphrase: String,
+) -> Result<()> {
+    run_command(
+        &vaults,
+        Audit::new("unlock_compartment"),
+        async |vault, _| {
+            compartment::unlock_compartment(&vault.pool, &category_name, &passphrase).await
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn lock_compartment(
+    vaults: State<'_, VaultManager>,
+    category_name: String,
+) -> Result<()> {
+    run_command(&vaults, Audit::new("lock_compartment"), async |vault, _| {
+        compartment::lock_compartment(&vault.pool, &category_name).await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn remove_compartment(
+    vaults: State<'_, VaultManager>,
+    category_name: String,
+    passphrase: String,
+) -> Result<()> {
+    run_command(
+        &vaults,
+        Audit::overwriting("remove_compartment"),
+        async |vault, _| {
+            compartment::remove_compartment(&vault.pool, &category_name, &passphrase).await
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_travel_mode(vaults: State<'_, VaultManager>) -> Result<TravelModeInfo> {
+    run_command(&vaults, Audit::new("get_travel_mode"), async |vault, _| {
+        travel::get_travel_mode(&vault.pool).await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn enable_travel_mode(
+    vaults: State<'_, VaultManager>,
+    safe_categories: Vec<String>,
+    passphrase: String,
+) -> Result<TravelModeInfo> {
+    run_command(
+        &vaults,
+        Audit::new("enable_travel_mode"),
+        async |vault, _| {
+            travel::enable_travel_mode(&vault.pool, &safe_categories, &passphrase).await
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn disable_travel_mode(
+    vaults: State<'_, VaultManager>,
+    passphrase: String,
+) -> Result<TravelModeInfo> {
+    run_command(
+        &vaults,
+        Audit::new("disable_travel_mode"),
+        async |vault, _| travel::disable_travel_mode(&vault.pool, &passphrase).await,
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn scrub_vault(vaults: State<'_, VaultManager>) -> Result<ScrubReport> {
+    run_command(&vaults, Audit::new("scrub_vault"), async |vault, _| {
+        database::scrub_database(&vault.pool).await
+    })
+    .await
+}
+
+// 保管庫の点検（repair が false の場合は問題を報告するだけで修復しない）
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn check_vault(vaults: State<'_, VaultManager>, repair: bool) -> Result<DoctorReport> {
+    run_command(&vaults, Audit::new("check_vault"), async |vault, _| {
+        doctor::check_vault(&vault.pool, repair).await
+    })
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_startup_error(
+    startup: State<'_, StartupState>,
+    vaults: State<'_, VaultManager>,
+) -> Result<Option<StartupError>> {
+    // 復旧画面で保管庫を開けた場合（キーも一致する場合）は、起動時のエラーを消す
+    if let Ok(vault) = vaults.current() {
+        if vault
+            .scope(verify_encryption_key(&vault.pool))
+            .await
+            .is_ok()
+        {
+            startup.clear();
+        }
     }
+    Ok(startup.error())
+}
+
+// 開けなかった保管庫のバックアップの一覧（復旧画面用）
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn list_vault_backups(
+    vaults: State<'_, VaultManager>,
+    vault_id: String,
+) -> Result<Vec<BackupInfo>> {
+    let result = vaults
+        .scope_vault(&vault_id, async { backup::list_backups() })
+        .await;
+    record_vault_audit(
+        &vaults,
+        "list_vault_backups",
+        Some(&vault_id),
+        result.is_ok(),
+    )
+    .await;
+
+    result
+}
+
+// 開けなかった保管庫をバックアップから復元する（開けなかったDBは名前を変えて残す）
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn restore_vault_backup(
+    vaults: State<'_, VaultManager>,
+    vault_id: String,
+    file_name: String,
+) -> Result<()> {
+    if vaults.is_open(&vault_id) {
+        vaults.open(&vault_id).await?;
+        return restore_vault(&vaults, "restore_vault_backup", &file_name, false).await;
+    }
+
+    let result = async {
+        let restored = vaults
+            .scope_vault(&vault_id, backup::prepare_restore(&file_name))
+            .await?;
+        vaults
+            .open_replacing(&vault_id, || {
+                database::set_aside_database()?;
+                database::replace_database(&restored)
+            })
+            .await
+    }
+    .await;
+    let vault = match result {
+        Ok(vault) => vault,
+        Err(e) => {
+            record_vault_audit(&vaults, "restore_vault_backup", Some(&vault_id), false).await;
+            return Err(e);
+        }
+    };
+    vault
+        .scope(record_restore(&vault.pool, "restore_vault_backup"))
+        .await;
 
     Ok(())
 }
+
+// キーを入力し直して保管庫を開く（復旧画面用）
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn set_vault_key(
+    vaults: State<'_, VaultManager>,
+    vault_id: String,
+    key: String,
+) -> Result<()> {
+    let result = vaults.replace_key(&vault_id, &key).await;
+    record_vault_audit(&vaults, "set_vault_key", Some(&vault_id), result.is_ok()).await;
+
+    result.map(|_| ())
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_vault_settings(vaults: State<'_, VaultManager>) -> Result<VaultSettings> {
+    run_command(
+        &vaults,
+        Audit::new("get_vault_settings"),
+        async |vault, _| settings::load_vault_settings(&vault.pool).await,
+    )
+    .await
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn update_vault_settings(
+    vaults: State<'_, VaultManager>,
+    vault_settings: VaultSettings,
+) -> Result<VaultSettings> {
+    run_command(
+        &vaults,
+        Audit::new("update_vault_settings"),
+        async |vault, _| settings::save_vault_settings(&vault.pool, &vault_settings).await,
+    )
+    .await
+}
+
+// アプリ全体の設定（保管庫を開いていなくても使えるよう、データディレクトリに保存する）
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn get_app_settings(vaults: State<'_, VaultManager>) -> Result<AppSettings> {
+    let result = settings::load_app_settings();
+    record_vault_audit(&vaults, "get_app_settings", None, result.is_ok()).await;
+
+    result
+}
+
+#[tauri::command]
+#[instrument(skip_all, err(level = "warn"))]
+pub async fn update_app_settings(
+    vaults: State<'_, VaultManager>,
+    app_settings: AppSettings,
+) -> Result<AppSettings> {
+    let result = settings::save_app_settings(&app_settings);
+    record_vault_audit(&vaults, "update_app_settings", None, result.is_ok()).await;
+
+    result
+}
@@ -0,0 +1,318 @@
+use aes_gcm::{Aes256Gcm, Key};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::crypto::{
+    decrypt_password, derive_compartment_key, encrypt_password, generate_salt, get_encryption_key,
+    run_crypto,
+};
+use crate::error::{AppError, Result};
+use crate::models::{CompartmentInfo, FieldError};
+use crate::repository;
+use crate::repository::compartment::CompartmentRow;
+use crate::repository::key_check::verify_encryption_key;
+use crate::validation::validate_passphrase;
+use crate::vault::current_vault_id;
+
+const KEY_CHECK_VALUE: &str = "jasmify-compartment-check";
+const RELOCK_MINUTES_MAX: u32 = 24 * 60;
+
+// ロックを解除したコンパートメントのキー（保管庫のIDとカテゴリのIDごと、メモリ上にのみ保持する）
+struct UnlockedKey {
+    key: Key<Aes256Gcm>,
+    relock_at: Instant,
+    generation: u64,
+}
+
+#[derive(Default)]
+struct UnlockedKeys {
+    keys: HashMap<(String, i64), UnlockedKey>,
+    next_generation: u64,
+}
+
+fn unlocked_keys() -> &'static Mutex<UnlockedKeys> {
+    static UNLOCKED_KEYS: OnceLock<Mutex<UnlockedKeys>> = OnceLock::new();
+    UNLOCKED_KEYS.get_or_init(Default::default)
+}
+
+fn field_error(field: &str, message: &str) -> AppError {
+    AppError::Validation(vec![FieldError {
+        field: field.to_string(),
+        index: None,
+        message: message.to_string(),
+    }])
+}
+
+// ロックが解除されていて、自動でロックされる時刻を過ぎていないキー
+// （対象の保管庫が分からない場合は、ロック中として扱う）
+fn unlocked_key(compartment_id: i64) -> Option<(Key<Aes256Gcm>, Instant)> {
+    let vault_id = current_vault_id().ok()?;
+    let mut unlocked = unlocked_keys().lock().unwrap();
+    let slot = (vault_id, compartment_id);
+    match unlocked.keys.get(&slot) {
+        Some(entry) if entry.relock_at > Instant::now() => Some((entry.key, entry.relock_at)),
+        Some(_) => {
+            unlocked.keys.remove(&slot);
+            None
+        }
+        None => None,
+    }
+}
+
+// ロックを解除し、指定した時間が経ったら自動でロックする
+// （ロックを解除し直した場合は、前回の解除のタイマーではロックしない）
+fn store_unlocked_key(compartment_id: i64, key: Key<Aes256Gcm>, relock_minutes: u32) -> Result<()> {
+    let vault_id = current_vault_id()?;
+    let relock_after = Duration::from_secs(u64::from(relock_minutes) * 60);
+
+    let generation = {
+        let mut unlocked = unlocked_keys().lock().unwrap();
+        unlocked.next_generation += 1;
+        let generation = unlocked.next_generation;
+        unlocked.keys.insert(
+            (vault_id.clone(), compartment_id),
+            UnlockedKey {
+                key,
+                relock_at: Instant::now() + relock_after,
+                generation,
+            },
+        );
+        generation
+    };
+
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(relock_after).await;
+        let mut unlocked = unlocked_keys().lock().unwrap();
+        let slot = (vault_id, compartment_id);
+        if unlocked
+            .keys
+            .get(&slot)
+            .is_some_and(|entry| entry.generation == generation)
+        {
+            unlocked.keys.remove(&slot);
+        }
+    });
+
+    Ok(())
+}
+
+fn remove_unlocked_key(compartment_id: i64) -> Result<()> {
+    let vault_id = current_vault_id()?;
+    unlocked_keys()
+        .lock()
+        .unwrap()
+        .keys
+        .remove(&(vault_id, compartment_id));
+
+    Ok(())
+}
+
+// 保管庫を閉じたときや入れ替えたときに、その保管庫のコンパートメントをすべてロック
+pub fn lock_vault_compartments(vault_id: &str) {
+    unlocked_keys()
+        .lock()
+        .unwrap()
+        .keys
+        .retain(|(locked_vault_id, _), _| locked_vault_id != vault_id);
+}
+
+// 一覧や検索で表示しないアカウント（ロック中のコンパートメントのアカウント）
+pub fn is_hidden(compartment_id: Option<i64>) -> bool {
+    compartment_id.is_some_and(|compartment_id| unlocked_key(compartment_id).is_none())
+}
+
+// 暗号化に使うキー（コンパートメントの場合はロックを解除したキー、ロック中の場合はCompartmentLocked）
+pub async fn compartment_key(
+    conn: &mut SqliteConnection,
+    compartment_id: Option<i64>,
+) -> Result<Key<Aes256Gcm>> {
+    let Some(compartment_id) = compartment_id else {
+        return get_encryption_key();
+    };
+
+    match unlocked_key(compartment_id) {
+        Some((key, _)) => Ok(key),
+        None => {
+            let category_name = repository::compartment::get_category_name(conn, compartment_id)
+                .await?
+                .unwrap_or_default();
+            Err(AppError::CompartmentLocked(category_name))
+        }
+    }
+}
+
+pub async fn account_key(
+    conn: &mut SqliteConnection,
+    account_ulid: &str,
+) -> Result<Key<Aes256Gcm>> {
+    let compartment_id =
+        repository::compartment::get_account_compartment(conn, account_ulid).await?;
+    compartment_key(conn, compartment_id).await
+}
+
+pub async fn identifier_key(
+    conn: &mut SqliteConnection,
+    identifier_ulid: &str,
+) -> Result<Key<Aes256Gcm>> {
+    let compartment_id =
+        repository::compartment::get_identifier_compartment(conn, identifier_ulid).await?;
+    compartment_key(conn, compartment_id).await
+}
+
+// パスフレーズからキーを導出し、照合用の値と一致するか確認
+async fn derive_and_check(
+    compartment: &CompartmentRow,
+    passphrase: &str,
+) -> Result<Key<Aes256Gcm>> {
+    let vault_key = get_encryption_key()?;
+    let salt = hex::decode(&compartment.salt).map_err(|_| AppError::Tampered)?;
+    let passphrase = passphrase.to_string();
+    let key = run_crypto(move || derive_compartment_key(&vault_key, &passphrase, &salt)).await?;
+
+    let (encrypted_value, nonce) = compartment
+        .key_check
+        .split_once(':')
+        .ok_or_else(|| AppError::Internal("Invalid compartment key check value".to_string()))?;
+    match decrypt_password(&key, encrypted_value, nonce) {
+        Ok(value) if value == KEY_CHECK_VALUE => Ok(key),
+        _ => Err(field_error("passphrase", "Incorrect passphrase")),
+    }
+}
+
+async fn find_compartment(sqlite_pool: &SqlitePool, category_name: &str) -> Result<CompartmentRow> {
+    let mut conn = sqlite_pool.acquire().await?;
+    repository::compartment::get_compartment_by_name(&mut conn, category_name)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Compartment".to_string()))
+}
+
+pub async fn list_compartments(sqlite_pool: &SqlitePool) -> Result<Vec<CompartmentInfo>> {
+    let compartments = repository::compartment::list_compartments(sqlite_pool).await?;
+    let now = Instant::now();
+
+    Ok(compartments
+        .into_iter()
+        .map(|compartment| CompartmentInfo {
+            relocks_in_secs: unlocked_key(compartment.category_id)
+                .map(|(_, relock_at)| relock_at.saturating_duration_since(now).as_secs()),
+            category_name: compartment.category_name,
+            account_count: compartment.account_count,
+            relock_minutes: compartment.relock_minutes,
+        })
+        .collect())
+}
+
+// カテゴリをコンパートメントにし、所属するアカウントをコンパートメントのキーで暗号化し直す
+pub async fn create_compartment(
+    sqlite_pool: &SqlitePool,
+    category_name: &str,
+    passphrase: &str,
+    relock_minutes: u32,
+) -> Result<()> {
+    validate_passphrase(passphrase)?;
+    if relock_minutes == 0 || relock_minutes > RELOCK_MINUTES_MAX {
+        return Err(field_error(
+            "relockMinutes",
+            &format!("Must be between 1 and {}", RELOCK_MINUTES_MAX),
+        ));
+    }
+    // 保管庫と一致しないキーで暗号化し直さないよう、先に照合する
+    verify_encryption_key(sqlite_pool).await?;
+
+    let vault_key = get_encryption_key()?;
+    let salt = generate_salt();
+    let key = {
+        let passphrase = passphrase.to_string();
+        run_crypto(move || derive_compartment_key(&vault_key, &passphrase, &salt)).await?
+    };
+    let (encrypted_value, nonce) = encrypt_password(&key, KEY_CHECK_VALUE)?;
+
+    let mut tx = sqlite_pool.begin().await?;
+    let category_id = repository::compartment::get_category_id(&mut tx, category_name)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Category".to_string()))?;
+    if repository::compartment::get_compartment_by_name(&mut tx, category_name)
+        .await?
+        .is_some()
+    {
+        return Err(field_error(
+            "categoryName",
+            "This category is already a compartment",
+        ));
+    }
+
+    repository::compartment::insert_compartment(
+        &mut tx,
+        category_id,
+        &hex::encode(salt),
+        &format!("{}:{}", encrypted_value, nonce),
+        relock_minutes,
+    )
+    .await?;
+    for account_ulid in
+        repository::compartment::get_category_account_ulids(&mut tx, category_id).await?
+    {
+        repository::compartment::rekey_account(
+            &mut tx,
+            &account_ulid,
+            &vault_key,
+            &key,
+            Some(category_id),
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    // 作成した直後は続けて操作できるよう、ロックを解除した状態にする
+    store_unlocked_key(category_id, key, relock_minutes)?;
+
+    Ok(())
+}
+
+pub async fn unlock_compartment(
+    sqlite_pool: &SqlitePool,
+    category_name: &str,
+    passphrase: &str,
+) -> Result<()> {
+    let compartment = find_compartment(sqlite_pool, category_name).await?;
+    let key = derive_and_check(&compartment, passphrase).await?;
+    store_unlocked_key(compartment.category_id, key, compartment.relock_minutes)?;
+
+    Ok(())
+}
+
+pub async fn lock_compartment(sqlite_pool: &SqlitePool, category_name: &str) -> Result<()> {
+    let compartment = find_compartment(sqlite_pool, category_name).await?;
+    remove_unlocked_key(compartment.category_id)?;
+
+    Ok(())
+}
+
+// コンパートメントを通常のカテゴリに戻す（パスフレーズを確認し、保管庫のキーで暗号化し直す）
+pub async fn remove_compartment(
+    sqlite_pool: &SqlitePool,
+    category_name: &str,
+    passphrase: &str,
+) -> Result<()> {
+    let compartment = find_compartment(sqlite_pool, category_name).await?;
+    let key = derive_and_check(&compartment, passphrase).await?;
+    let vault_key = get_encryption_key()?;
+
+    let mut tx = sqlite_pool.begin().await?;
+    for account_ulid in
+        repository::compartment::get_category_account_ulids(&mut tx, compartment.category_id)
+            .await?
+    {
+        repository::compartment::rekey_account(&mut tx, &account_ulid, &key, &vault_key, None)
+            .await?;
+    }
+    repository::compartment::delete_compartment(&mut tx, compartment.category_id).await?;
+    tx.commit().await?;
+
+    remove_unlocked_key(compartment.category_id)?;
+
+    Ok(())
+}
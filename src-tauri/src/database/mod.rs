@@ -1,17 +1,186 @@
 use sqlx::{
+    migrate::Migrator,
     sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
     SqlitePool,
 };
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::Result;
+use crate::backup::{seal_snapshot, BACKUP_EXTENSION};
+use crate::crypto::{
+    get_key_file_path, remove_dir_securely, remove_file_securely, write_file_atomically, KEY_FILE,
+};
+use crate::error::{AppError, Result};
+use crate::models::{AppliedMigration, MigrationReport, ScrubReport};
+use crate::paths::find_legacy_data_dir;
+use crate::vault::current_vault_dir;
+
+const DATABASE_DIR: &str = "DB";
+const DATABASE_FILE: &str = "db.sqlite";
+const MIGRATION_BACKUP_DIR: &str = "migration_backups";
+const MIGRATION_BACKUP_PREFIX: &str = "db-v";
+const MIGRATION_BACKUP_KEEP: usize = 3;
+const MIGRATION_SNAPSHOT_FILE: &str = "snapshot.sqlite";
+const WAL_CHECKPOINT_ATTEMPTS: u32 = 5;
+const WAL_CHECKPOINT_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+// DBディレクトリのパスを取得（処理中の保管庫のディレクトリ）
+pub fn get_database_dir() -> Result<PathBuf> {
+    Ok(current_vault_dir()?.join(DATABASE_DIR))
+}
+
+// 保管庫のDBファイルのパスを取得（処理中の保管庫）
+pub fn get_database_path() -> Result<PathBuf> {
+    Ok(get_database_dir()?.join(DATABASE_FILE))
+}
+
+// 以前の保存場所（カレントディレクトリなど）に残っている保管庫を探す
+pub fn find_legacy_vault() -> Option<PathBuf> {
+    find_legacy_data_dir(&Path::new(DATABASE_DIR).join(DATABASE_FILE))
+}
+
+// 以前の保存場所の保管庫を現在のデータディレクトリに複製する（元のファイルは残す）
+// 現在のDBは日時を付けた名前に変更して退避する
+// （現在のキーファイルは平文のキーが残らないよう安全に削除する、移行は現在の保管庫が空の場合のみ行う）
+pub fn import_legacy_vault(legacy_dir: &Path) -> Result<()> {
+    let database_dir = get_database_dir()?;
+    let key_file_path = get_key_file_path()?;
+    let timestamp = unix_timestamp()?;
+
+    if database_dir.exists() {
+        std::fs::rename(
+            &database_dir,
+            current_vault_dir()?.join(format!("{}.replaced-{}", DATABASE_DIR, timestamp)),
+        )?;
+    }
+    copy_dir_all(&legacy_dir.join(DATABASE_DIR), &database_dir)?;
+
+    let legacy_key_file_path = legacy_dir.join(KEY_FILE);
+    if legacy_key_file_path.exists() {
+        let legacy_key = std::fs::read(&legacy_key_file_path)?;
+        remove_file_securely(&key_file_path)?;
+        write_file_atomically(&key_file_path, &legacy_key)?;
+    }
+
+    Ok(())
+}
+
+// 入力し直したキーをキーファイルに書き込む
+// （既存のキーファイルは保管庫と一致しないキーのため、平文のキーが残らないよう安全に削除する）
+pub fn replace_key_file(hex_key: &str) -> Result<()> {
+    let key_file_path = get_key_file_path()?;
+    remove_file_securely(&key_file_path)?;
+    write_file_atomically(&key_file_path, hex_key.trim().as_bytes())?;
+
+    Ok(())
+}
+
+// 開けなくなったDBを、日時を付けた名前に変更して残す（復元で置き換える前に、後から調べられるようにする）
+// WAL も同じ名前に -wal を付けて残し、DBと一緒に開けるようにする
+pub fn set_aside_database() -> Result<()> {
+    let database_path = get_database_path()?;
+    let set_aside_path =
+        database_path.with_file_name(format!("{}.replaced-{}", DATABASE_FILE, unix_timestamp()?));
+
+    for suffix in ["", "-wal"] {
+        let mut path = database_path.clone().into_os_string();
+        path.push(suffix);
+        let mut set_aside = set_aside_path.clone().into_os_string();
+        set_aside.push(suffix);
+        if Path::new(&path).exists() {
+            std::fs::rename(&path, &set_aside)?;
+        }
+    }
+
+    Ok(())
+}
+
+// 保管庫のキーだけで以前の内容を読み取れるDBの複製をすべて安全に削除する
+// （マイグレーション前のバックアップと、移行や復元の際に退避したDB）
+pub fn remove_database_copies() -> Result<()> {
+    let database_dir = get_database_dir()?;
+    remove_dir_securely(&database_dir.join(MIGRATION_BACKUP_DIR))?;
+    remove_set_aside(&database_dir, &format!("{}.replaced-", DATABASE_FILE))?;
+    remove_set_aside(
+        &current_vault_dir()?,
+        &format!("{}.replaced-", DATABASE_DIR),
+    )?;
+
+    Ok(())
+}
+
+fn remove_set_aside(dir: &Path, prefix: &str) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with(prefix) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            remove_dir_securely(&entry.path())?;
+        } else {
+            remove_file_securely(&entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+// 閉じたDBを、復元用に書き出したDBファイルで置き換える
+// （古いDBの WAL が新しいDBに適用されないよう、WAL と共有メモリのファイルも削除する）
+pub fn replace_database(restored: &Path) -> Result<()> {
+    let database_path = get_database_path()?;
+
+    for suffix in ["-wal", "-shm"] {
+        let mut path = database_path.clone().into_os_string();
+        path.push(suffix);
+        remove_file_securely(Path::new(&path))?;
+    }
+    std::fs::rename(restored, database_path)?;
+
+    Ok(())
+}
+
+// バックアップなどのファイル名に付ける日時
+fn unix_timestamp() -> Result<u64> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .as_secs())
+}
+
+fn copy_dir_all(source: &Path, destination: &Path) -> Result<()> {
+    std::fs::create_dir_all(destination)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let destination = destination.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &destination)?;
+        } else {
+            std::fs::copy(entry.path(), destination)?;
+        }
+    }
+    Ok(())
+}
+
+// 保管庫のDBを読み書きする接続の設定
+fn writable_options(connection_options: SqliteConnectOptions) -> SqliteConnectOptions {
+    connection_options
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(SqliteSynchronous::Normal)
+        // 削除や更新で不要になった内容を、空き領域に残さないようゼロで上書きする
+        .pragma("secure_delete", "ON")
+}
 
 //Create SQLite Connection Pool
 pub async fn create_pool(database_url: &str) -> Result<SqlitePool> {
-    let connection_options = SqliteConnectOptions::from_str(database_url)?
-        .create_if_missing(true)
-        .journal_mode(SqliteJournalMode::Wal)
-        .synchronous(SqliteSynchronous::Normal);
+    let connection_options =
+        writable_options(SqliteConnectOptions::from_str(database_url)?.create_if_missing(true));
 
     let sqlite_pool = SqlitePoolOptions::new()
         .connect_with(connection_options)
@@ -20,43 +189,378 @@ pub async fn create_pool(database_url: &str) -> Result<SqlitePool> {
     Ok(sqlite_pool)
 }
 
-//Database Migration
-async fn migrate(pool: &SqlitePool) -> Result<()> {
-    sqlx::migrate!("./migrations").run(pool).await?;
+// WAL の内容をDBに書き戻して空にする（削除や上書きの前の内容が WAL に残らないようにする）
+// 読み取り中の接続があると空にできない（busy）ため、少し待って再試行し、それでも空にできなければエラー
+pub async fn truncate_wal(sqlite_pool: &SqlitePool) -> Result<()> {
+    for _ in 0..WAL_CHECKPOINT_ATTEMPTS {
+        let (busy, _, _): (i64, i64, i64) = sqlx::query_as("PRAGMA wal_checkpoint(TRUNCATE)")
+            .fetch_one(sqlite_pool)
+            .await?;
+        if busy == 0 {
+            return Ok(());
+        }
+        tokio::time::sleep(WAL_CHECKPOINT_RETRY_DELAY).await;
+    }
+
+    Err(AppError::Storage(
+        "The WAL could not be emptied because the database is busy".to_string(),
+    ))
+}
+
+// DBを作り直して空き領域をなくし、WAL を空にする
+pub async fn scrub_database(sqlite_pool: &SqlitePool) -> Result<ScrubReport> {
+    let database_path = get_database_path()?;
+    let free_pages: u64 = sqlx::query_scalar("PRAGMA freelist_count")
+        .fetch_one(sqlite_pool)
+        .await?;
+    let size_before = std::fs::metadata(&database_path)?.len();
+
+    sqlx::query("VACUUM").execute(sqlite_pool).await?;
+    truncate_wal(sqlite_pool).await?;
+
+    Ok(ScrubReport {
+        free_pages,
+        size_before,
+        size_after: std::fs::metadata(&database_path)?.len(),
+    })
+}
+
+fn existing_database_path(vault_dir: &Path) -> Result<PathBuf> {
+    let database_path = vault_dir.join(DATABASE_DIR).join(DATABASE_FILE);
+    if !database_path.exists() {
+        return Err(AppError::NotFound("Vault database".to_string()));
+    }
+
+    Ok(database_path)
+}
+
+// 保管庫のDBを読み取り専用で開く（アプリの外から保管庫を調べる管理用のツールで使う）
+pub async fn open_read_only(vault_dir: &Path) -> Result<SqlitePool> {
+    let connection_options = SqliteConnectOptions::new()
+        .filename(existing_database_path(vault_dir)?)
+        .read_only(true);
+    let sqlite_pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(connection_options)
+        .await?;
+
+    Ok(sqlite_pool)
+}
+
+// 既存の保管庫のDBを書き込めるように開く（管理用のツールで修復する場合に使う）
+pub async fn open_writable(vault_dir: &Path) -> Result<SqlitePool> {
+    let connection_options =
+        writable_options(SqliteConnectOptions::new().filename(existing_database_path(vault_dir)?));
+    let sqlite_pool = SqlitePoolOptions::new()
+        .connect_with(connection_options)
+        .await?;
+
+    Ok(sqlite_pool)
+}
+
+// 適用済みのマイグレーションのバージョンを取得（新規のDBの場合は空）
+async fn get_applied_versions(pool: &SqlitePool) -> Result<Vec<i64>> {
+    let table_exists: bool = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'
+        )
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if !table_exists {
+        return Ok(Vec::new());
+    }
+
+    let versions = sqlx::query_scalar(
+        r#"
+        SELECT version FROM _sqlx_migrations WHERE success = 1 ORDER BY version
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(versions)
+}
+
+// スキーマを変更する前にDBを丸ごと暗号化して保存しておく（形式はバックアップと同じで、WAL の内容も含まれる）
+async fn backup_before_migration(
+    pool: &SqlitePool,
+    database_dir: &Path,
+    current_version: i64,
+) -> Result<PathBuf> {
+    let backup_dir = database_dir.join(MIGRATION_BACKUP_DIR);
+    std::fs::create_dir_all(&backup_dir)?;
+
+    let archive = seal_snapshot(pool, &backup_dir.join(MIGRATION_SNAPSHOT_FILE)).await?;
+
+    let timestamp = unix_timestamp()?;
+    let backup_path = backup_dir.join(format!(
+        "{}{:03}-{}.{}",
+        MIGRATION_BACKUP_PREFIX, current_version, timestamp, BACKUP_EXTENSION
+    ));
+    write_file_atomically(&backup_path, &archive)?;
+
+    rotate_migration_backups(&backup_dir)?;
+
+    Ok(backup_path)
+}
+
+// マイグレーション前のバックアップは新しいものから一定数だけ残す
+// （以前のバージョンが作成した暗号化していない複製は、残さずに安全に削除する）
+fn rotate_migration_backups(backup_dir: &Path) -> Result<()> {
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(backup_dir)? {
+        let path = entry?.path();
+        let file_name = path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if !file_name.starts_with(MIGRATION_BACKUP_PREFIX) {
+            continue;
+        }
+        if path.extension().and_then(|extension| extension.to_str()) == Some(BACKUP_EXTENSION) {
+            backups.push(file_name);
+        } else {
+            remove_file_securely(&path)?;
+        }
+    }
+
+    // ファイル名はバージョンと作成時刻の順のため、名前順が作成順になる
+    backups.sort_by(|a, b| b.cmp(a));
+    for file_name in backups.into_iter().skip(MIGRATION_BACKUP_KEEP) {
+        remove_file_securely(&backup_dir.join(file_name))?;
+    }
 
     Ok(())
 }
 
-//Database Setup
-pub fn setup_database() -> Result<SqlitePool> {
-    // main関数はasync fnではないので、asyncな関数を呼ぶのにblock_on関数を使う
-    use tauri::async_runtime::block_on;
+// 新しいバージョンのアプリでスキーマが更新されたDBは、壊さないよう開かない
+fn check_applied_versions(applied_versions: &[i64]) -> Result<()> {
+    match applied_versions
+        .iter()
+        .find(|version| !MIGRATOR.version_exists(**version))
+    {
+        Some(&version) => Err(AppError::IncompatibleSchema(version)),
+        None => Ok(()),
+    }
+}
+
+// このバージョンのアプリで開けるスキーマか確認（バックアップからの復元前などに使う）
+pub async fn check_schema_compatible(pool: &SqlitePool) -> Result<()> {
+    check_applied_versions(&get_applied_versions(pool).await?)
+}
+
+//Database Migration
+pub async fn migrate(pool: &SqlitePool, database_dir: &Path) -> Result<MigrationReport> {
+    let applied_versions = get_applied_versions(pool).await?;
+    check_applied_versions(&applied_versions)?;
 
-    const DATABASE_DIR: &str = "DB";
-    const DATABASE_FILE: &str = "db.sqlite";
+    let pending: Vec<AppliedMigration> = MIGRATOR
+        .iter()
+        .filter(|migration| {
+            !migration.migration_type.is_down_migration()
+                && !applied_versions.contains(&migration.version)
+        })
+        .map(|migration| AppliedMigration {
+            version: migration.version,
+            description: migration.description.to_string(),
+        })
+        .collect();
 
-    let home_dir = std::env::current_dir().expect("Cannot access the current directory");
-    let database_dir = home_dir.join(DATABASE_DIR);
-    let database_file = database_dir.join(DATABASE_FILE);
+    if pending.is_empty() {
+        return Ok(MigrationReport::default());
+    }
+
+    // 新規のDBはバックアップ不要
+    let backup_path = match applied_versions.last() {
+        Some(&current_version) => {
+            Some(backup_before_migration(pool, database_dir, current_version).await?)
+        }
+        None => None,
+    };
+
+    MIGRATOR.run(pool).await?;
+
+    Ok(MigrationReport {
+        applied: pending,
+        backup_path: backup_path.map(|path| path.to_string_lossy().into_owned()),
+    })
+}
+
+// テスト用のマイグレーション済みのDB（メモリ上のDBは接続ごとに別になるため、接続は1つ）
+#[cfg(test)]
+pub async fn test_pool() -> SqlitePool {
+    let connection_options = SqliteConnectOptions::from_str("sqlite::memory:")
+        .unwrap()
+        .foreign_keys(true);
+    let sqlite_pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(connection_options)
+        .await
+        .unwrap();
+    MIGRATOR.run(&sqlite_pool).await.unwrap();
+
+    sqlite_pool
+}
+
+//Database Setup
+pub async fn setup_database() -> Result<(SqlitePool, MigrationReport)> {
+    let database_dir = get_database_dir()?;
 
     let db_dir_exist = std::fs::metadata(&database_dir).is_ok();
-    let db_exist = std::fs::metadata(&database_file).is_ok();
 
     if !db_dir_exist {
-        std::fs::create_dir(&database_dir)?;
+        std::fs::create_dir_all(&database_dir)?;
     }
 
-    let database_dir_string = dunce::canonicalize(&database_dir)
-        .unwrap()
+    let database_dir_string = dunce::canonicalize(&database_dir)?
         .to_string_lossy()
         .replace('\\', "/");
     let database_url = format!("sqlite://{}/{}", database_dir_string, DATABASE_FILE);
 
-    let sqlite_pool = block_on(create_pool(&database_url))?;
+    let sqlite_pool = create_pool(&database_url).await?;
+
+    // 既存のDBにも未適用のマイグレーションがあれば起動のたびに適用する
+    let migration_report = migrate(&sqlite_pool, &database_dir).await?;
+
+    Ok((sqlite_pool, migration_report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::with_test_vault;
+
+    #[tokio::test]
+    async fn test_replace_key_file_leaves_no_previous_key() {
+        let new_key = "ab".repeat(32);
+
+        with_test_vault(|vault_dir| async move {
+            replace_key_file(&format!("{}\n", new_key)).unwrap();
 
-    if !db_exist {
-        block_on(migrate(&sqlite_pool))?;
+            // 以前のキーファイルは別の名前でも残さない
+            assert_eq!(
+                std::fs::read_to_string(vault_dir.join(KEY_FILE)).unwrap(),
+                new_key
+            );
+            let file_names: Vec<_> = std::fs::read_dir(&vault_dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect();
+            assert_eq!(file_names, [KEY_FILE]);
+        })
+        .await;
     }
 
-    Ok(sqlite_pool)
+    #[tokio::test]
+    async fn test_migration_backups_are_sealed_and_capped() {
+        with_test_vault(|_| async {
+            let (sqlite_pool, _) = setup_database().await.unwrap();
+            let database_dir = get_database_dir().unwrap();
+            let backup_dir = database_dir.join(MIGRATION_BACKUP_DIR);
+            std::fs::create_dir_all(&backup_dir).unwrap();
+            // 以前のバージョンが作成した暗号化していない複製
+            std::fs::write(backup_dir.join("db-v001-1.sqlite"), "SQLite format 3").unwrap();
+
+            for version in 2..=6 {
+                backup_before_migration(&sqlite_pool, &database_dir, version)
+                    .await
+                    .unwrap();
+            }
+            sqlite_pool.close().await;
+
+            // 新しいものから保持件数だけ残り、どれも平文のDBではなくバックアップの形式であることを確認
+            let mut file_names: Vec<String> = std::fs::read_dir(&backup_dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            file_names.sort();
+            assert_eq!(file_names.len(), MIGRATION_BACKUP_KEEP);
+            for (file_name, version) in file_names.iter().zip(4..) {
+                assert!(file_name.starts_with(&format!("db-v{:03}-", version)));
+                assert!(std::fs::read(backup_dir.join(file_name))
+                    .unwrap()
+                    .starts_with(b"JASMBAK1"));
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_remove_database_copies_keeps_live_database() {
+        with_test_vault(|vault_dir| async move {
+            let (sqlite_pool, _) = setup_database().await.unwrap();
+            sqlite_pool.close().await;
+            let database_dir = get_database_dir().unwrap();
+            let copies = [
+                database_dir
+                    .join(MIGRATION_BACKUP_DIR)
+                    .join("db-v013-1.jbak"),
+                database_dir.join("db.sqlite.replaced-1"),
+                database_dir.join("db.sqlite.replaced-1-wal"),
+                vault_dir.join("DB.replaced-1").join("db.sqlite"),
+                vault_dir
+                    .join("DB.replaced-1")
+                    .join(MIGRATION_BACKUP_DIR)
+                    .join("db-v012-1.sqlite"),
+            ];
+            for copy in &copies {
+                std::fs::create_dir_all(copy.parent().unwrap()).unwrap();
+                std::fs::write(copy, "copy").unwrap();
+            }
+
+            remove_database_copies().unwrap();
+
+            // 退避したDBとマイグレーション前のバックアップだけが削除されることを確認
+            assert!(copies.iter().all(|copy| !copy.exists()));
+            assert!(!database_dir.join(MIGRATION_BACKUP_DIR).exists());
+            assert!(!vault_dir.join("DB.replaced-1").exists());
+            assert!(get_database_path().unwrap().exists());
+            assert!(vault_dir.join(KEY_FILE).exists());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_truncate_wal_waits_for_readers() {
+        let dir = std::env::temp_dir().join(format!("truncate_wal_{}", ulid::Ulid::new()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let database_url = format!("sqlite://{}/{}", dir.to_string_lossy(), DATABASE_FILE);
+        let sqlite_pool = create_pool(&database_url).await.unwrap();
+        let reader_pool = create_pool(&database_url).await.unwrap();
+
+        sqlx::query("CREATE TABLE items (value TEXT)")
+            .execute(&sqlite_pool)
+            .await
+            .unwrap();
+        truncate_wal(&sqlite_pool)
+            .await
+            .expect("WAL を空にできませんでした");
+
+        // 読み取り中の接続があるうちは WAL を空にできずエラーになる
+        let mut reader = reader_pool.begin().await.unwrap();
+        sqlx::query("SELECT COUNT(*) FROM items")
+            .fetch_one(&mut *reader)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO items (value) VALUES ('secret')")
+            .execute(&sqlite_pool)
+            .await
+            .unwrap();
+        let result = truncate_wal(&sqlite_pool).await;
+        assert!(matches!(result, Err(AppError::Storage(_))));
+
+        // 読み取りが終われば空にできる
+        reader.commit().await.unwrap();
+        truncate_wal(&sqlite_pool)
+            .await
+            .expect("WAL を空にできませんでした");
+
+        sqlite_pool.close().await;
+        reader_pool.close().await;
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
@@ -0,0 +1,95 @@
+use anyhow::bail;
+use std::path::Path;
+
+use super::check_vault;
+use crate::database::{check_schema_compatible, open_read_only, open_writable};
+use crate::models::{DoctorIssueKind, DoctorReport};
+use crate::repository::audit::{append_audit_log, AuditInterface};
+use crate::vault::lock::lock_vault_dir;
+use crate::vault::scope_dir;
+
+const USAGE: &str = "Usage:
+  jasmify-doctor <vault dir>
+  jasmify-doctor <vault dir> --repair";
+
+// 保管庫の点検（--repair がない場合は問題を表示するだけで、保管庫には書き込まない）
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    let (vault_dir, repair) = match args {
+        [vault_dir] => (Path::new(vault_dir), false),
+        [vault_dir, flag] if flag == "--repair" => (Path::new(vault_dir), true),
+        _ => bail!(USAGE),
+    };
+
+    let report = scope_dir(vault_dir, async {
+        // アプリで開いている間は、点検の途中で変更されたり修復と書き込みが混ざったりするため実行しない
+        // （DBを開くと WAL などのファイルが作られるため、開く前にロックする）
+        let _lock = lock_vault_dir(vault_dir).await?;
+        let pool = if repair {
+            open_writable(vault_dir).await?
+        } else {
+            open_read_only(vault_dir).await?
+        };
+        let result = async {
+            check_schema_compatible(&pool).await?;
+            check_vault(&pool, repair).await
+        }
+        .await;
+        // 修復で保管庫を変更した場合は、アプリでの点検と同じ操作名で監査ログに記録する
+        // （修復は確定しているため、記録に失敗しても結果は変えずに表示だけする）
+        if let Ok(report) = &result {
+            if !report.repaired.is_empty() {
+                if let Err(e) =
+                    append_audit_log(&pool, "check_vault", None, AuditInterface::Cli, true).await
+                {
+                    eprintln!("Recording the repair in the audit log failed: {}", e);
+                }
+            }
+        }
+        pool.close().await;
+        result
+    })
+    .await?;
+
+    print_report(&report);
+
+    if repair
+        && report
+            .issues
+            .iter()
+            .any(|issue| issue.kind == DoctorIssueKind::Integrity)
+    {
+        eprintln!("The database failed the integrity check, so nothing was repaired. Restore a backup instead.");
+    }
+    if !report.issues.is_empty() {
+        bail!("{} problem(s) found", report.issues.len());
+    }
+
+    Ok(())
+}
+
+fn print_report(report: &DoctorReport) {
+    for issue in &report.repaired {
+        println!(
+            "repaired  {:?} {}: {}",
+            issue.kind, issue.target, issue.detail
+        );
+    }
+    for issue in &report.issues {
+        let note = if issue.repairable {
+            " (repairable with --repair)"
+        } else {
+            ""
+        };
+        println!(
+            "found     {:?} {}: {}{}",
+            issue.kind, issue.target, issue.detail, note
+        );
+    }
+    println!(
+        "Checked {} password(s), skipped {} in locked compartments",
+        report.checked_passwords, report.skipped_passwords
+    );
+    if let Some(backup_file_name) = &report.backup_file_name {
+        println!("Backup before repair: {}", backup_file_name);
+    }
+}
@@ -0,0 +1,417 @@
+pub mod cli;
+
+use aes_gcm::{Aes256Gcm, Key};
+use sqlx::SqlitePool;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use crate::backup;
+use crate::compartment::compartment_key;
+use crate::crypto::{decrypt_password, run_crypto};
+use crate::database::truncate_wal;
+use crate::error::{AppError, Result};
+use crate::models::{DoctorIssue, DoctorIssueKind, DoctorReport};
+use crate::repository;
+use crate::repository::key_check::verify_encryption_key;
+
+// 参照先がない行を、データを失わずに修復できるテーブル
+// （識別子とパスワードは参照先を作り直して残し、関連付けだけを削除する）
+const REPAIRABLE_TABLES: [&str; 4] = [
+    "identifiers",
+    "passwords",
+    "password_history",
+    "account_categories",
+];
+// カテゴリのないアカウントを関連付けるカテゴリ
+const RECOVERED_CATEGORY: &str = "Recovered";
+// 参照先がない識別子やパスワードのために作り直すアカウントの名前
+const RECOVERED_ACCOUNT: &str = "Recovered";
+
+struct Findings {
+    issues: Vec<DoctorIssue>,
+    checked_passwords: u32,
+    skipped_passwords: u32,
+}
+
+fn issue(kind: DoctorIssueKind, target: String, detail: String, repairable: bool) -> DoctorIssue {
+    DoctorIssue {
+        kind,
+        target,
+        detail,
+        repairable,
+    }
+}
+
+// 保管庫を点検し、repair の場合は自動で修復できる問題を修復する
+// （DB自体が壊れている場合は書き込むと悪化する可能性があるため修復しない、バックアップから復元する）
+pub async fn check_vault(sqlite_pool: &SqlitePool, repair: bool) -> Result<DoctorReport> {
+    // キーが違う場合にすべてのパスワードを問題として報告しないよう、先に照合する
+    verify_encryption_key(sqlite_pool).await?;
+
+    let findings = find_issues(sqlite_pool).await?;
+    let corrupted = findings
+        .issues
+        .iter()
+        .any(|issue| issue.kind == DoctorIssueKind::Integrity);
+    if !repair || corrupted || !findings.issues.iter().any(|issue| issue.repairable) {
+        return Ok(DoctorReport {
+            issues: findings.issues,
+            repaired: Vec::new(),
+            checked_passwords: findings.checked_passwords,
+            skipped_passwords: findings.skipped_passwords,
+            backup_file_name: None,
+        });
+    }
+
+    // 修復で変更した内容を戻せるよう、先にバックアップする
+    let backup = backup::create_backup(sqlite_pool).await?;
+    repair_issues(sqlite_pool).await?;
+    truncate_wal(sqlite_pool).await?;
+
+    let remaining = find_issues(sqlite_pool).await?;
+    Ok(DoctorReport {
+        issues: remaining.issues,
+        repaired: findings
+            .issues
+            .into_iter()
+            .filter(|issue| issue.repairable)
+            .collect(),
+        checked_passwords: remaining.checked_passwords,
+        skipped_passwords: remaining.skipped_passwords,
+        backup_file_name: Some(backup.file_name),
+    })
+}
+
+async fn find_issues(sqlite_pool: &SqlitePool) -> Result<Findings> {
+    let mut issues = Vec::new();
+
+    for message in repository::doctor::integrity_check(sqlite_pool).await? {
+        issues.push(issue(
+            DoctorIssueKind::Integrity,
+            "database".to_string(),
+            message,
+            false,
+        ));
+    }
+
+    let password_rows = repository::doctor::get_password_check_rows(sqlite_pool).await?;
+    // 読み取り専用で開いた場合は接続が1つのため、プールを使う前に返す
+    let (violations, keys) = {
+        let mut conn = sqlite_pool.acquire().await?;
+        let violations = repository::doctor::foreign_key_check(&mut conn).await?;
+
+        // ロック中のコンパートメントのパスワードは確認できないため None にする
+        let mut keys: HashMap<Option<i64>, Option<Key<Aes256Gcm>>> = HashMap::new();
+        for row in &password_rows {
+            if let Entry::Vacant(entry) = keys.entry(row.compartment_id) {
+                entry.insert(match compartment_key(&mut conn, row.compartment_id).await {
+                    Ok(key) => Some(key),
+                    Err(AppError::CompartmentLocked(_)) => None,
+                    Err(e) => return Err(e),
+                });
+            }
+        }
+        (violations, keys)
+    };
+
+    for violation in violations {
+        let kind = if violation.table == "identifiers" {
+            DoctorIssueKind::OrphanIdentifier
+        } else {
+            DoctorIssueKind::ForeignKey
+        };
+        let repairable =
+            violation.rowid.is_some() && REPAIRABLE_TABLES.contains(&violation.table.as_str());
+        let target = match violation.rowid {
+            Some(rowid) => format!("{}:{}", violation.table, rowid),
+            None => violation.table.clone(),
+        };
+        issues.push(issue(
+            kind,
+            target,
+            format!("Refers to a missing row in {}", violation.parent),
+            repairable,
+        ));
+    }
+
+    let (undecryptable, checked_passwords, skipped_passwords) = run_crypto(move || {
+        let mut undecryptable = Vec::new();
+        let mut checked = 0;
+        let mut skipped = 0;
+        for row in password_rows {
+            let Some(key) = keys.get(&row.compartment_id).copied().flatten() else {
+                skipped += 1;
+                continue;
+            };
+            checked += 1;
+            if decrypt_password(&key, &row.encrypted_value, &row.nonce).is_err() {
+                undecryptable.push(row);
+            }
+        }
+        Ok((undecryptable, checked, skipped))
+    })
+    .await?;
+    for row in undecryptable {
+        issues.push(issue(
+            DoctorIssueKind::UndecryptablePassword,
+            format!("passwords:{}", row.id),
+            match row.account_ulid {
+                Some(account_ulid) => format!(
+                    "A password of account {} cannot be decrypted with the current key",
+                    account_ulid
+                ),
+                None => "Cannot be decrypted with the current key".to_string(),
+            },
+            false,
+        ));
+    }
+
+    let accounts_without_identifier = {
+        let mut conn = sqlite_pool.acquire().await?;
+        repository::doctor::find_accounts_without_identifier(&mut conn).await?
+    };
+    // 変更履歴のスナップショットがあれば作り直せる
+    for (account_ulid, account_name, has_snapshot) in accounts_without_identifier {
+        issues.push(issue(
+            DoctorIssueKind::AccountWithoutIdentifier,
+            account_ulid,
+            format!("\"{}\" has no identifier or password", account_name),
+            has_snapshot,
+        ));
+    }
+    for (account_ulid, account_name) in
+        repository::doctor::find_accounts_without_category(sqlite_pool).await?
+    {
+        issues.push(issue(
+            DoctorIssueKind::AccountWithoutCategory,
+            account_ulid,
+            format!("\"{}\" has no category", account_name),
+            true,
+        ));
+    }
+    for (account_ulid, link_count) in
+        repository::doctor::find_duplicate_category_links(sqlite_pool).await?
+    {
+        issues.push(issue(
+            DoctorIssueKind::DuplicateCategoryLink,
+            account_ulid,
+            format!("Linked to {} categories", link_count),
+            true,
+        ));
+    }
+    for category_name in repository::doctor::find_orphan_categories(sqlite_pool).await? {
+        issues.push(issue(
+            DoctorIssueKind::OrphanCategory,
+            category_name,
+            "Not used by any account".to_string(),
+            true,
+        ));
+    }
+
+    Ok(Findings {
+        issues,
+        checked_passwords,
+        skipped_passwords,
+    })
+}
+
+// 修復は順番に意味がある（識別子を作り直してから、参照先がない行やカテゴリのないアカウントを判断する）
+// 識別子・パスワード・変更履歴は秘密の情報を含むため、修復でも削除しない
+async fn repair_issues(sqlite_pool: &SqlitePool) -> Result<()> {
+    let mut tx = sqlite_pool.begin().await?;
+
+    // 識別子が失われたアカウントは、変更履歴から作り直せれば残っているパスワードも戻る
+    for (account_ulid, _, has_snapshot) in
+        repository::doctor::find_accounts_without_identifier(&mut tx).await?
+    {
+        if has_snapshot {
+            repository::revision::rebuild_account_from_latest_revision(&mut tx, &account_ulid)
+                .await?;
+        }
+    }
+
+    // 識別子やアカウントを作り直し、参照先がない行がなくなるまで繰り返す
+    loop {
+        let violations: Vec<_> = repository::doctor::foreign_key_check(&mut tx)
+            .await?
+            .into_iter()
+            .filter(|violation| REPAIRABLE_TABLES.contains(&violation.table.as_str()))
+            .filter_map(|violation| Some((violation.table, violation.rowid?)))
+            .collect();
+        if violations.is_empty() {
+            break;
+        }
+        for (table, rowid) in violations {
+            match table.as_str() {
+                "identifiers" => {
+                    repository::doctor::recover_identifier_account(
+                        &mut tx,
+                        rowid,
+                        RECOVERED_ACCOUNT,
+                    )
+                    .await?
+                }
+                "account_categories" => {
+                    repository::doctor::delete_category_link(&mut tx, rowid).await?
+                }
+                _ => {
+                    repository::doctor::recover_password_identifier(
+                        &mut tx,
+                        &table,
+                        rowid,
+                        RECOVERED_ACCOUNT,
+                    )
+                    .await?
+                }
+            }
+        }
+    }
+
+    repository::doctor::remove_duplicate_category_links(&mut tx).await?;
+    repository::doctor::link_accounts_without_category(&mut tx, RECOVERED_CATEGORY).await?;
+    repository::delete::delete_unused_category(&mut tx).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_pool;
+    use crate::models::FormData;
+    use crate::repository::insert::insert_new_account;
+    use crate::repository::read::get_account_info;
+    use crate::vault::with_test_vault;
+
+    async fn count(sqlite_pool: &SqlitePool, table: &str) -> i64 {
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(sqlite_pool)
+            .await
+            .unwrap()
+    }
+
+    async fn account_passwords(sqlite_pool: &SqlitePool, account_ulid: &str) -> Vec<String> {
+        let mut tx = sqlite_pool.begin().await.unwrap();
+        get_account_info(&mut tx, account_ulid)
+            .await
+            .unwrap()
+            .unwrap()
+            .passwords
+            .into_iter()
+            .map(|password| password.password_raw)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_repair_keeps_identifiers_and_passwords() {
+        with_test_vault(|_| async {
+            let sqlite_pool = test_pool().await;
+            let mut account_ulids = Vec::new();
+            for name in ["Mail", "Bank"] {
+                let form_data = FormData {
+                    account_name: name.to_string(),
+                    identifier: "user".to_string(),
+                    passwords: vec![format!("{}-old", name), format!("{}-new", name)],
+                    category_name: "Web".to_string(),
+                };
+                account_ulids.push(insert_new_account(&sqlite_pool, form_data).await.unwrap());
+            }
+            let revisions = count(&sqlite_pool, "account_revisions").await;
+
+            // 外部キーを無効にして、アカウントと識別子を失った状態にする（接続は1つ）
+            sqlx::query(
+                r#"
+                PRAGMA foreign_keys = OFF;
+                DELETE FROM accounts WHERE account_name = 'Mail';
+                DELETE FROM identifiers
+                WHERE account_ulid = (SELECT ulid FROM accounts WHERE account_name = 'Bank');
+                PRAGMA foreign_keys = ON;
+                "#,
+            )
+            .execute(&sqlite_pool)
+            .await
+            .unwrap();
+
+            repair_issues(&sqlite_pool).await.unwrap();
+
+            // パスワードと変更履歴は削除されない
+            assert_eq!(count(&sqlite_pool, "passwords").await, 4);
+            assert!(count(&sqlite_pool, "account_revisions").await >= revisions);
+            let findings = find_issues(&sqlite_pool).await.unwrap();
+            assert!(findings.issues.is_empty());
+
+            // アカウントを失った識別子は、同じULIDの Recovered のアカウントに移る
+            assert_eq!(
+                account_passwords(&sqlite_pool, &account_ulids[0]).await,
+                ["Mail-old", "Mail-new"]
+            );
+            let mut tx = sqlite_pool.begin().await.unwrap();
+            let recovered = get_account_info(&mut tx, &account_ulids[0])
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(recovered.account_name, RECOVERED_ACCOUNT);
+            assert_eq!(recovered.category_name, RECOVERED_CATEGORY);
+
+            // 識別子を失ったアカウントは変更履歴から作り直し、残っていたパスワードを使う
+            let rebuilt = get_account_info(&mut tx, &account_ulids[1])
+                .await
+                .unwrap()
+                .unwrap();
+            drop(tx);
+            assert_eq!(rebuilt.account_name, "Bank");
+            assert_eq!(rebuilt.identifier, "user");
+            assert_eq!(rebuilt.category_name, "Web");
+            assert_eq!(
+                account_passwords(&sqlite_pool, &account_ulids[1]).await,
+                ["Bank-old", "Bank-new"]
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_cli_repair_is_audited() {
+        with_test_vault(|vault_dir| async move {
+            let (sqlite_pool, _) = crate::database::setup_database().await.unwrap();
+            let form_data = FormData {
+                account_name: "Mail".to_string(),
+                identifier: "user".to_string(),
+                passwords: vec!["secret".to_string()],
+                category_name: "Web".to_string(),
+            };
+            insert_new_account(&sqlite_pool, form_data).await.unwrap();
+            sqlx::query(
+                r#"
+                PRAGMA foreign_keys = OFF;
+                DELETE FROM accounts WHERE account_name = 'Mail';
+                PRAGMA foreign_keys = ON;
+                "#,
+            )
+            .execute(&sqlite_pool)
+            .await
+            .unwrap();
+            sqlite_pool.close().await;
+
+            let args = [
+                vault_dir.to_string_lossy().into_owned(),
+                "--repair".to_string(),
+            ];
+            cli::run(&args).await.unwrap();
+
+            // ツールで修復した場合は、ツールからの操作として監査ログに残ることを確認
+            let sqlite_pool = crate::database::open_read_only(&vault_dir).await.unwrap();
+            let interfaces: Vec<String> = sqlx::query_scalar(
+                "SELECT interface FROM audit_log WHERE operation = 'check_vault'",
+            )
+            .fetch_all(&sqlite_pool)
+            .await
+            .unwrap();
+            sqlite_pool.close().await;
+            assert_eq!(interfaces, ["cli"]);
+        })
+        .await;
+    }
+}
@@ -0,0 +1,121 @@
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Serialize;
+use serde_json::json;
+use thiserror::Error;
+
+use crate::models::{AccountInfo, FieldError};
+
+pub type Result<T, E = AppError> = std::result::Result<T, E>;
+
+// 画面には code・message・details の形で返す（code は画面側の分岐に使うため変更しない）
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("The vault is locked because no encryption key is available")]
+    Locked,
+    #[error("The encryption key does not match this vault")]
+    WrongKey,
+    #[error("Encrypted data has been tampered with or is corrupted")]
+    Tampered,
+    #[error("The compartment {0} is locked")]
+    CompartmentLocked(String),
+    #[error("This is not available while travel mode is on")]
+    TravelMode,
+    #[error("The vault is in use by another instance of the app or another tool")]
+    VaultInUse,
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("The account has been changed since it was loaded")]
+    Conflict { current: Option<Box<AccountInfo>> },
+    #[error("Invalid input")]
+    Validation(Vec<FieldError>),
+    #[error("Storage error: {0}")]
+    Storage(String),
+    #[error("Remote storage error: {0}")]
+    Remote(String),
+    #[error("The database was updated by a newer version of the app (migration {0})")]
+    IncompatibleSchema(i64),
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Locked => "locked",
+            AppError::WrongKey => "wrong_key",
+            AppError::Tampered => "tampered",
+            AppError::CompartmentLocked(_) => "compartment_locked",
+            AppError::TravelMode => "travel_mode",
+            AppError::VaultInUse => "vault_in_use",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict { .. } => "conflict",
+            AppError::Validation(_) => "validation",
+            AppError::Storage(_) => "storage",
+            AppError::Remote(_) => "remote",
+            AppError::IncompatibleSchema(_) => "incompatible_schema",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            AppError::NotFound(resource) => Some(json!({ "resource": resource })),
+            AppError::CompartmentLocked(category_name) => {
+                Some(json!({ "categoryName": category_name }))
+            }
+            AppError::Conflict { current } => Some(json!({ "current": current })),
+            AppError::Validation(errors) => Some(json!({ "errors": errors })),
+            AppError::IncompatibleSchema(version) => Some(json!({ "version": version })),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("details", &self.details())?;
+        state.end()
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("Record".to_string()),
+            e => AppError::Storage(e.to_string()),
+        }
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for AppError {
+    fn from(e: sqlx::migrate::MigrateError) -> Self {
+        AppError::Storage(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::Storage(e.to_string())
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        AppError::Remote(e.to_string())
+    }
+}
+
+// 解析エラーのメッセージには入力値の一部が含まれることがあるため（パスワードなど）、種類と位置だけを残す
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::Internal(format!(
+            "Invalid JSON ({:?} error at line {}, column {})",
+            e.classify(),
+            e.line(),
+            e.column()
+        ))
+    }
+}
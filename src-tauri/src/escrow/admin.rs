@@ -0,0 +1,98 @@
+use age::secrecy::ExposeSecret;
+use age::x25519::Identity;
+use anyhow::{bail, Context};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::recover_key;
+use crate::database::open_read_only;
+use crate::error::AppError;
+
+const USAGE: &str = "Usage:
+  jasmify-escrow-admin keygen <identity file>
+  jasmify-escrow-admin recover <identity file> <vault dir> [<key file>]";
+
+// 管理者用のツール（管理者の鍵の作成と、預けられたキーによる保管庫の復旧）
+pub async fn run(args: &[String]) -> anyhow::Result<()> {
+    match args {
+        [command, identity_path] if command == "keygen" => keygen(Path::new(identity_path)),
+        [command, identity_path, vault_dir] if command == "recover" => {
+            let hex_key = recover(Path::new(identity_path), Path::new(vault_dir)).await?;
+            println!("{}", hex_key);
+            Ok(())
+        }
+        [command, identity_path, vault_dir, key_path] if command == "recover" => {
+            let hex_key = recover(Path::new(identity_path), Path::new(vault_dir)).await?;
+            write_secret_file(Path::new(key_path), &hex_key)?;
+            eprintln!("Recovered key written to {}", key_path);
+            Ok(())
+        }
+        _ => bail!(USAGE),
+    }
+}
+
+// 既存のファイルは上書きせず、所有者だけが読めるファイルを作成
+fn write_secret_file(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Could not create {}", path.display()))?;
+    file.write_all(contents.as_bytes())?;
+
+    Ok(())
+}
+
+// 管理者の鍵を作成し、公開鍵を表示（公開鍵は各端末の JASMIFY_ESCROW_RECIPIENTS などに設定する）
+fn keygen(identity_path: &Path) -> anyhow::Result<()> {
+    let identity = Identity::generate();
+    let public_key = identity.to_public().to_string();
+
+    write_secret_file(
+        identity_path,
+        &format!(
+            "# public key: {}\n{}\n",
+            public_key,
+            identity.to_string().expose_secret()
+        ),
+    )?;
+    println!("{}", public_key);
+
+    Ok(())
+}
+
+// age の鍵ファイルの形式（# で始まる行はコメント）から秘密鍵を読み込む
+fn read_identity(identity_path: &Path) -> anyhow::Result<Identity> {
+    let contents = std::fs::read_to_string(identity_path)
+        .with_context(|| format!("Could not read {}", identity_path.display()))?;
+
+    match contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Identity::from_str)
+    {
+        Some(Ok(identity)) => Ok(identity),
+        _ => bail!("{} is not an age identity file", identity_path.display()),
+    }
+}
+
+async fn recover(identity_path: &Path, vault_dir: &Path) -> anyhow::Result<String> {
+    let identity = read_identity(identity_path)?;
+    let pool = open_read_only(vault_dir).await?;
+    let result = recover_key(&pool, &identity).await;
+    pool.close().await;
+
+    match result {
+        Err(AppError::WrongKey) => bail!(
+            "No key in this vault is escrowed to {}",
+            identity.to_public()
+        ),
+        result => Ok(result?),
+    }
+}
@@ -0,0 +1,196 @@
+pub mod admin;
+
+use aes_gcm::{Aes256Gcm, Key};
+use age::x25519::{Identity, Recipient};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use std::env;
+
+use crate::crypto::{decode_key, get_encryption_key, key_fingerprint};
+use crate::error::{AppError, Result};
+use crate::models::KeyEscrowInfo;
+use crate::repository;
+use crate::repository::key_check::verify_encryption_key;
+use crate::share::{decrypt_shared, encrypt_for_recipients, parse_recipients};
+
+pub const ESCROW_RECIPIENTS_ENV_VAR: &str = "JASMIFY_ESCROW_RECIPIENTS";
+
+// 空白またはカンマ区切りの公開鍵の一覧を分割
+fn split_recipients(value: &str) -> Vec<String> {
+    value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|recipient| !recipient.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// 保管庫のキーを1つの公開鍵に暗号化（キーファイルと同じHEX形式で暗号化する）
+fn wrap_key(key: &Key<Aes256Gcm>, recipient: &Recipient) -> Result<String> {
+    encrypt_for_recipients(std::slice::from_ref(recipient), hex::encode(key).as_bytes())
+}
+
+// 暗号化したキーを保存（登録済みの公開鍵はそのまま残す）
+async fn insert_key_escrows(
+    tx: &mut Transaction<'_, Sqlite>,
+    recipients: &[Recipient],
+) -> Result<()> {
+    let key = get_encryption_key()?;
+    let fingerprint = hex::encode(key_fingerprint(&key));
+
+    for recipient in recipients {
+        repository::escrow::insert_key_escrow(
+            tx,
+            &recipient.to_string(),
+            &wrap_key(&key, recipient)?,
+            &fingerprint,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+// キーを預ける管理者の公開鍵を置き換える（空の場合は預けるのをやめる）
+pub async fn set_key_escrow(
+    sqlite_pool: &SqlitePool,
+    recipients: &[String],
+) -> Result<Vec<KeyEscrowInfo>> {
+    let recipients = if recipients.is_empty() {
+        Vec::new()
+    } else {
+        parse_recipients(recipients)?
+    };
+    // 保管庫と一致しないキーを預けないよう、先に照合する
+    verify_encryption_key(sqlite_pool).await?;
+
+    let mut tx = sqlite_pool.begin().await?;
+    repository::escrow::delete_key_escrows(&mut tx).await?;
+    insert_key_escrows(&mut tx, &recipients).await?;
+    tx.commit().await?;
+
+    repository::escrow::list_key_escrows(sqlite_pool).await
+}
+
+// 環境変数で指定された管理者の公開鍵に、まだ預けていなければキーを預ける（会社で配布する端末向け）
+pub async fn enroll_from_env(sqlite_pool: &SqlitePool) -> Result<()> {
+    let Some(value) = env::var(ESCROW_RECIPIENTS_ENV_VAR)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+    else {
+        return Ok(());
+    };
+
+    let recipients = parse_recipients(&split_recipients(&value))?;
+    verify_encryption_key(sqlite_pool).await?;
+
+    let mut tx = sqlite_pool.begin().await?;
+    insert_key_escrows(&mut tx, &recipients).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// 管理者の秘密鍵で預けられたキーを復号し、キーファイルと同じHEX形式で返す
+pub async fn recover_key(sqlite_pool: &SqlitePool, identity: &Identity) -> Result<String> {
+    let wrapped_keys = repository::escrow::list_wrapped_keys(sqlite_pool).await?;
+    if wrapped_keys.is_empty() {
+        return Err(AppError::NotFound("Key escrow".to_string()));
+    }
+
+    for (wrapped_key, fingerprint) in wrapped_keys {
+        let hex_key = match decrypt_shared(identity, &wrapped_key)? {
+            Some(hex_key) => String::from_utf8(hex_key).map_err(|_| AppError::Tampered)?,
+            // 他の管理者宛ての控え
+            None => continue,
+        };
+
+        // 控えに記録された識別子と一致しない場合や、保管庫のデータを復号できない場合は、控えが差し替えられている
+        // （識別子は控えと同じ行にあり一緒に差し替えられるため、保管庫の照合用の値でも確認する）
+        let key = decode_key(&hex_key).map_err(|_| AppError::Tampered)?;
+        if hex::encode(key_fingerprint(&key)) != fingerprint
+            || !repository::key_check::key_matches(sqlite_pool, &key).await?
+        {
+            return Err(AppError::Tampered);
+        }
+
+        return Ok(hex_key.trim().to_string());
+    }
+
+    Err(AppError::WrongKey)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_pool;
+    use crate::vault::with_test_vault;
+
+    #[test]
+    fn test_wrapped_key_opens_only_for_recipient() {
+        let admin = Identity::generate();
+        let other = Identity::generate();
+        let key = Key::<Aes256Gcm>::from([9u8; 32]);
+
+        let wrapped_key = wrap_key(&key, &admin.to_public()).unwrap();
+
+        let hex_key = decrypt_shared(&admin, &wrapped_key).unwrap().unwrap();
+        assert_eq!(
+            decode_key(&String::from_utf8(hex_key).unwrap()).unwrap(),
+            key
+        );
+        assert_eq!(decrypt_shared(&other, &wrapped_key).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_recover_key_rejects_replaced_escrow() {
+        with_test_vault(|_| async {
+            let sqlite_pool = test_pool().await;
+            repository::key_check::create_key_check_if_missing(&sqlite_pool)
+                .await
+                .unwrap();
+            let admin = Identity::generate();
+            let escrow = |key: Key<Aes256Gcm>| {
+                let sqlite_pool = sqlite_pool.clone();
+                let recipient = admin.to_public();
+                async move {
+                    let mut tx = sqlite_pool.begin().await.unwrap();
+                    repository::escrow::delete_key_escrows(&mut tx)
+                        .await
+                        .unwrap();
+                    repository::escrow::insert_key_escrow(
+                        &mut tx,
+                        &recipient.to_string(),
+                        &wrap_key(&key, &recipient).unwrap(),
+                        &hex::encode(key_fingerprint(&key)),
+                    )
+                    .await
+                    .unwrap();
+                    tx.commit().await.unwrap();
+                }
+            };
+
+            let key = get_encryption_key().unwrap();
+            escrow(key).await;
+            assert_eq!(
+                recover_key(&sqlite_pool, &admin).await.unwrap(),
+                hex::encode(key)
+            );
+
+            // 別のキーと、そのキーの識別子に差し替えられた控えは復元しないことを確認
+            escrow(Key::<Aes256Gcm>::from([9u8; 32])).await;
+            assert!(matches!(
+                recover_key(&sqlite_pool, &admin).await,
+                Err(AppError::Tampered)
+            ));
+        })
+        .await;
+    }
+
+    #[test]
+    fn test_split_recipients() {
+        assert_eq!(
+            split_recipients(" age1a, age1b\nage1c "),
+            vec!["age1a", "age1b", "age1c"]
+        );
+        assert!(split_recipients(" , ").is_empty());
+    }
+}
@@ -1,25 +1,46 @@
+mod backup;
 mod commands;
+mod compartment;
 mod crypto;
 mod database;
+mod doctor;
+mod error;
+mod escrow;
+mod logging;
 mod models;
+mod paths;
+mod remote;
 mod repository;
+mod server;
+mod settings;
+mod share;
+mod startup;
+mod sync;
+mod travel;
+mod validation;
+mod vault;
 
 use anyhow::Result;
-use std::env;
 use tauri::Manager;
 
-use crypto::{create_key_file, get_key_file_path, AES_KEY_ENV_VAR};
+use startup::StartupState;
+use vault::VaultManager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() -> Result<()> {
-    let sqlite_pool = database::setup_database()?;
-    let key_file_path = get_key_file_path();
-    // 環境変数とキーファイルが存在しない場合、キーを作成
-    if env::var(AES_KEY_ENV_VAR).is_err() && !key_file_path.exists() {
-        create_key_file(key_file_path);
-    }
+    // 終了するまでログをファイルに書き込む（レベルは環境変数 JASMIFY_LOG_LEVEL で変更できる）
+    let _log_guard = logging::init_logging();
 
     tauri::Builder::default()
+        // 2つ目に起動したアプリは保管庫を開かずに終了し、起動済みのウィンドウを前面に表示する
+        // （保管庫のロックより先に判定するため、最初に登録する）
+        .plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.unminimize();
+                let _ = window.show();
+                let _ = window.set_focus();
+            }
+        }))
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             commands::insert_form_data,
@@ -28,13 +49,90 @@ pub fn run() -> Result<()> {
             commands::get_password_info,
             commands::update_account_info,
             commands::delete_account,
+            commands::get_password_history,
+            commands::reveal_password_history,
+            commands::restore_password_history,
+            commands::list_revisions,
+            commands::revert_account_to_revision,
+            commands::undo_last_change,
+            commands::get_trashed_accounts,
+            commands::restore_account,
+            commands::empty_trash,
+            commands::get_audit_log,
+            commands::verify_audit_log,
+            commands::get_migration_report,
+            commands::get_data_location,
+            commands::migrate_legacy_vault,
+            commands::list_vaults,
+            commands::create_vault,
+            commands::open_vault,
+            commands::close_vault,
+            commands::rename_vault,
+            commands::create_backup,
+            commands::list_backups,
+            commands::restore_backup,
+            commands::push_backups,
+            commands::list_remote_backups,
+            commands::restore_remote_backup,
+            commands::sync_vault,
+            commands::list_sync_conflicts,
+            commands::resolve_sync_conflict,
+            commands::get_share_public_key,
+            commands::export_shared_accounts,
+            commands::import_shared_accounts,
+            commands::list_key_escrows,
+            commands::set_key_escrow,
+            commands::list_compartments,
+            commands::create_compartment,
+            commands::unlock_compartment,
+            commands::lock_compartment,
+            commands::remove_compartment,
+            commands::get_travel_mode,
+            commands::enable_travel_mode,
+            commands::disable_travel_mode,
+            commands::scrub_vault,
+            commands::check_vault,
+            commands::get_startup_error,
+            commands::list_vault_backups,
+            commands::restore_vault_backup,
+            commands::set_vault_key,
+            commands::get_vault_settings,
+            commands::update_vault_settings,
+            commands::get_app_settings,
+            commands::update_app_settings,
         ])
         .setup(|app| {
-            app.manage(sqlite_pool);
+            // 起動時は既定の保管庫を開く（開けない場合は原因をログに残し、復旧画面を表示する）
+            let vaults = VaultManager::default();
+            let startup_error =
+                match tauri::async_runtime::block_on(startup::open_default_vault(&vaults)) {
+                    Ok(()) => None,
+                    Err(e) => Some(startup::report_startup_error(&e)),
+                };
+
+            app.manage(vaults);
+            app.manage(StartupState::new(startup_error));
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .inspect_err(|e| startup::report_fatal_error(e))?
+        .run(|_app, _event| {});
+
+    Ok(())
+}
+
+// 同期サーバー（jasmify-sync-server）を起動
+pub fn run_sync_server() -> Result<()> {
+    let _log_guard = logging::init_logging();
+    tokio::runtime::Runtime::new()?.block_on(server::run_from_env())?;
 
     Ok(())
 }
+
+pub fn run_escrow_admin(args: &[String]) -> Result<()> {
+    tokio::runtime::Runtime::new()?.block_on(escrow::admin::run(args))
+}
+
+pub fn run_doctor(args: &[String]) -> Result<()> {
+    tokio::runtime::Runtime::new()?.block_on(doctor::cli::run(args))
+}
@@ -0,0 +1,265 @@
+use std::fmt;
+use std::path::PathBuf;
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+
+use crate::paths::get_data_dir;
+
+const LOG_DIR: &str = "logs";
+const LOG_FILE_PREFIX: &str = "jasmify";
+const LOG_FILE_SUFFIX: &str = "log";
+// 1日ごとに新しいファイルにし、古いものから削除する
+const LOG_FILES_KEEP: usize = 14;
+pub const LOG_LEVEL_ENV_VAR: &str = "JASMIFY_LOG_LEVEL";
+const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::INFO;
+// 依存クレートのログ（SQL文など）は、このアプリのログレベルに関わらず警告以上のみ
+const DEPENDENCY_LOG_LEVEL: LevelFilter = LevelFilter::WARN;
+
+// ログに出力しない値（パスワード・キー・識別子など）の代わりに表示する
+// 秘密情報を持つ型は Debug でこれを使い、ログやエラーの表示に値が出ないようにする
+pub struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+pub fn get_log_dir() -> PathBuf {
+    get_data_dir().join(LOG_DIR)
+}
+
+// ログの出力を開始する（戻り値を保持している間、ファイルへの書き込みが続く）
+// コマンドとリポジトリの関数は引数を記録しない span で囲み、終了時に所要時間を出力する
+// ファイルに書き込めない場合は標準エラー出力のみ
+pub fn init_logging() -> Option<WorkerGuard> {
+    let level = parse_log_level(std::env::var(LOG_LEVEL_ENV_VAR).ok().as_deref());
+
+    let (file_layer, guard) = match RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(LOG_FILES_KEEP)
+        .build(get_log_dir())
+    {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_span_events(FmtSpan::CLOSE)
+                .with_writer(writer);
+            (Some(layer), Some(guard))
+        }
+        Err(e) => {
+            eprintln!(
+                "Cannot write logs to {}: {}",
+                get_log_dir().to_string_lossy(),
+                e
+            );
+            (None, None)
+        }
+    };
+
+    let initialized = tracing_subscriber::registry()
+        .with(file_layer)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_span_events(FmtSpan::CLOSE)
+                .with_writer(std::io::stderr),
+        )
+        .with(log_filter(level))
+        .try_init();
+    if initialized.is_err() {
+        return None;
+    }
+
+    guard
+}
+
+// 不正な値の場合は既定のレベル（error / warn / info / debug / trace / off）
+fn parse_log_level(value: Option<&str>) -> LevelFilter {
+    match value.map(str::trim).filter(|value| !value.is_empty()) {
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            eprintln!(
+                "Invalid {} \"{}\", using {}",
+                LOG_LEVEL_ENV_VAR, value, DEFAULT_LOG_LEVEL
+            );
+            DEFAULT_LOG_LEVEL
+        }),
+        None => DEFAULT_LOG_LEVEL,
+    }
+}
+
+fn log_filter(level: LevelFilter) -> Targets {
+    Targets::new()
+        .with_default(level.min(DEPENDENCY_LOG_LEVEL))
+        .with_target(env!("CARGO_CRATE_NAME"), level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+    use crate::models::{
+        AccountInfo, AccountSnapshot, AccountSummary, ChangeSet, FormData, PasswordHistory,
+        PasswordInfo, RegisterDeviceRequest, SearchCriteria, SharePayload, SyncAccount,
+        SyncConflict, SyncRecord, TrashedAccount,
+    };
+    use std::collections::HashMap;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    const PASSWORD: &str = "secret-password-123";
+    const IDENTIFIER: &str = "someone@example.com";
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn handle_form_data(form_data: &FormData) -> usize {
+        tracing::trace!("handling form data");
+        form_data.passwords.len()
+    }
+
+    #[test]
+    fn test_parse_log_level() {
+        assert_eq!(parse_log_level(None), DEFAULT_LOG_LEVEL);
+        assert_eq!(parse_log_level(Some("debug")), LevelFilter::DEBUG);
+        assert_eq!(parse_log_level(Some(" TRACE ")), LevelFilter::TRACE);
+        assert_eq!(parse_log_level(Some("off")), LevelFilter::OFF);
+        assert_eq!(parse_log_level(Some("verbose")), DEFAULT_LOG_LEVEL);
+    }
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_span_events(FmtSpan::FULL)
+                .with_writer(move || writer.clone())
+                .with_filter(LevelFilter::TRACE),
+        );
+
+        let form_data = FormData {
+            account_name: "Example".to_string(),
+            identifier: IDENTIFIER.to_string(),
+            passwords: vec![PASSWORD.to_string()],
+            category_name: "Work".to_string(),
+        };
+        let account_info = AccountInfo {
+            account_ulid: "01J0000000000000000000000A".to_string(),
+            account_name: "Example".to_string(),
+            identifier_ulid: "01J0000000000000000000000B".to_string(),
+            identifier: IDENTIFIER.to_string(),
+            passwords: vec![PasswordInfo {
+                id: 1,
+                password_raw: PASSWORD.to_string(),
+            }],
+            category_name: "Work".to_string(),
+            version: 1,
+        };
+        let summary = AccountSummary {
+            account_ulid: "01J0000000000000000000000A".to_string(),
+            account_name: "Example".to_string(),
+            identifier: IDENTIFIER.to_string(),
+            identifier_ulid: "01J0000000000000000000000B".to_string(),
+            category_name: "Work".to_string(),
+            version: 1,
+        };
+        let criteria = SearchCriteria {
+            account_name: String::new(),
+            identifier: IDENTIFIER.to_string(),
+            category_name: String::new(),
+        };
+        let trashed = TrashedAccount {
+            account_ulid: "01J0000000000000000000000A".to_string(),
+            account_name: "Example".to_string(),
+            identifier: IDENTIFIER.to_string(),
+            category_name: "Work".to_string(),
+            deleted_at: "2025-01-01 00:00:00".to_string(),
+        };
+        let conflict = SyncConflict {
+            id: 1,
+            account_ulid: "01J0000000000000000000000A".to_string(),
+            account_name: Some("Example".to_string()),
+            field: "passwords".to_string(),
+            local_value: serde_json::json!([PASSWORD]),
+            remote_value: serde_json::json!(IDENTIFIER),
+            created_at: "2025-01-01 00:00:00".to_string(),
+        };
+        let register = RegisterDeviceRequest {
+            device_id: "device".to_string(),
+            public_key: "public".to_string(),
+            vault_auth: PASSWORD.to_string(),
+        };
+        let history = PasswordHistory {
+            id: 1,
+            password_id: 1,
+            set_at: Some("2025-01-01 00:00:00".to_string()),
+            replaced_at: "2025-01-02 00:00:00".to_string(),
+        };
+        let snapshot = AccountSnapshot {
+            identifier_ulid: "01J0000000000000000000000B".to_string(),
+            form_data: form_data.clone(),
+        };
+        let sync_account = SyncAccount {
+            identifier_ulid: "01J0000000000000000000000B".to_string(),
+            form_data: form_data.clone(),
+            trashed: false,
+        };
+        let record = SyncRecord {
+            account_ulid: "01J0000000000000000000000A".to_string(),
+            state: Some(sync_account.clone()),
+        };
+        let change_set = ChangeSet {
+            device_id: "device".to_string(),
+            records: vec![record.clone()],
+            bases: HashMap::from([("peer".to_string(), vec![record])]),
+        };
+        let share = SharePayload {
+            format: "jasmify-share/1".to_string(),
+            accounts: vec![form_data.clone()],
+        };
+        // 解析エラーのメッセージに入力値が含まれないこと
+        let parse_error: AppError =
+            serde_json::from_str::<Vec<u32>>(&format!("[\"{}\"]", PASSWORD))
+                .unwrap_err()
+                .into();
+        let conflict_error = AppError::Conflict {
+            current: Some(Box::new(account_info.clone())),
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::trace!(?form_data, ?account_info, ?summary, "accounts");
+            tracing::trace!(?history, ?snapshot, ?sync_account, "history");
+            tracing::trace!(?change_set, ?share, "sync");
+            tracing::trace!(?criteria, ?trashed, ?conflict, ?register, "others");
+            tracing::trace!(error = %parse_error, ?conflict_error, "errors");
+            handle_form_data(&form_data);
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("handle_form_data"));
+        assert!(output.contains("SharePayload"));
+        assert!(output.contains("[redacted]"));
+        assert!(!output.contains(PASSWORD), "{}", output);
+        assert!(!output.contains(IDENTIFIER), "{}", output);
+    }
+}
@@ -2,8 +2,8 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
-    if let Err(_e) = local_password_manager_lib::run() {
-        // 致命的なエラーの場合は、アプリケーションを終了
+    if local_password_manager_lib::run().is_err() {
+        // 致命的なエラーの原因はログに記録済みのため、ここではアプリケーションを終了するだけ
         std::process::exit(1);
     }
 }
@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::logging::Redacted;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct FormData {
     pub account_name: String,
@@ -9,7 +13,7 @@ pub struct FormData {
     pub category_name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct AccountInfo {
     pub account_ulid: String,
@@ -18,6 +22,7 @@ pub struct AccountInfo {
     pub identifier: String,
     pub passwords: Vec<PasswordInfo>,
     pub category_name: String,
+    pub version: u32,
 }
 
 pub enum FormDataField {
@@ -70,14 +75,14 @@ pub struct PasswordData {
     pub nonce: Vec<u8>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PasswordInfo {
     pub id: u32,
     pub password_raw: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AccountSummary {
     pub account_ulid: String,
@@ -85,12 +90,472 @@ pub struct AccountSummary {
     pub identifier: String,
     pub identifier_ulid: String,
     pub category_name: String,
+    pub version: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SearchCriteria {
     pub account_name: String,
     pub identifier: String,
     pub category_name: String,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordHistory {
+    pub id: u32,
+    pub password_id: u32,
+    pub set_at: Option<String>,
+    pub replaced_at: String,
+}
+
+// 変更履歴に暗号化して保存するアカウント全体のスナップショット
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountSnapshot {
+    pub identifier_ulid: String,
+    #[serde(flatten)]
+    pub form_data: FormData,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountRevision {
+    pub id: u32,
+    pub account_ulid: String,
+    pub operation: String,
+    pub account_name: Option<String>,
+    pub undone: bool,
+    pub created_at: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashedAccount {
+    pub account_ulid: String,
+    pub account_name: String,
+    pub identifier: String,
+    pub category_name: String,
+    pub deleted_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogEntry {
+    pub id: u32,
+    pub operation: String,
+    pub account_ulid: Option<String>,
+    pub interface: String,
+    pub succeeded: bool,
+    pub created_at: String,
+    pub hash: String,
+    // バックアップから復元した記録の場合、復元前の最新の記録（"ID ハッシュ"）
+    pub replaced_head: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogFilter {
+    pub operation: String,
+    pub account_ulid: String,
+    pub interface: String,
+    pub since: String,
+    pub until: String,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogVerification {
+    pub valid: bool,
+    pub record_count: u32,
+    pub broken_at: Option<u32>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    pub field: String,
+    pub index: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub description: String,
+}
+
+// 起動時に適用したマイグレーションと、適用前に作成したバックアップの場所
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationReport {
+    pub applied: Vec<AppliedMigration>,
+    pub backup_path: Option<String>,
+}
+
+// 保管庫の保存場所と、その場所がどの指定によって決まったか
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataLocation {
+    pub data_dir: String,
+    pub source: String,
+    pub legacy_vault: Option<String>,
+}
+
+// 保管庫ごとの暗号化キーの取得元
+// （Default は従来どおり環境変数 JASMIFY_AES_KEY、なければ保管庫のキーファイル）
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum KeySource {
+    Default,
+    File,
+    Env { name: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultInfo {
+    pub id: String,
+    pub name: String,
+    pub dir: String,
+    pub key_source: KeySource,
+    pub is_open: bool,
+    pub is_current: bool,
+}
+
+// 暗号化したバックアップファイルの情報
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    pub file_name: String,
+    pub created_at: String,
+    pub size: u64,
+}
+
+// リモートの保存先にあるバックアップファイルの情報
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteBackupInfo {
+    pub file_name: String,
+    pub size: u64,
+}
+
+// 同期で交換するアカウントの状態（trashed はゴミ箱にあるかどうか）
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncAccount {
+    pub identifier_ulid: String,
+    #[serde(flatten)]
+    pub form_data: FormData,
+    pub trashed: bool,
+}
+
+// 変更セットに含める各アカウント（state が None の場合は完全に削除済み）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncRecord {
+    pub account_ulid: String,
+    pub state: Option<SyncAccount>,
+}
+
+// 端末ごとに書き出す変更セット（暗号化して交換する）
+// bases は端末IDごとの、その端末から前回取り込んだ状態（相手側がマージの基準に使う）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeSet {
+    pub device_id: String,
+    pub records: Vec<SyncRecord>,
+    pub bases: HashMap<String, Vec<SyncRecord>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    pub peers: u32,
+    pub applied: u32,
+    pub conflicts: u32,
+    // 変更セットが壊れている・読めないため取り込まなかった端末のID
+    pub skipped_peers: Vec<String>,
+}
+
+// 手動で解決が必要な変更（値は項目ごとに形が異なる、deleted の場合はアカウント全体か null）
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncConflict {
+    pub id: u32,
+    pub account_ulid: String,
+    pub account_name: Option<String>,
+    pub field: String,
+    pub local_value: serde_json::Value,
+    pub remote_value: serde_json::Value,
+    pub created_at: String,
+}
+
+// 同期サーバーへの端末の登録（vault_auth は保管庫のキーから派生した値、キーそのものは送らない）
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterDeviceRequest {
+    pub device_id: String,
+    pub public_key: String,
+    pub vault_auth: String,
+}
+
+// 同期サーバーが保存している変更セット（blob は暗号化済みの変更セットのHEX）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredChangeSet {
+    pub device_id: String,
+    pub revision: i64,
+    pub blob: String,
+}
+
+// 前回の取得以降に更新された他の端末の変更セット（revision は次回の取得の起点）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullResponse {
+    pub revision: i64,
+    pub change_sets: Vec<StoredChangeSet>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushResponse {
+    pub revision: i64,
+}
+
+// 他のメンバーと共有するアカウント（age 形式で受け取る人の公開鍵に暗号化する）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharePayload {
+    pub format: String,
+    pub accounts: Vec<FormData>,
+}
+
+// 保管庫のキーを預けている管理者の公開鍵（暗号化したキーそのものは画面に返さない）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyEscrowInfo {
+    pub recipient: String,
+    pub key_fingerprint: String,
+    pub created_at: String,
+}
+
+// 別のパスフレーズで暗号化しているカテゴリ（relocks_in_secs は自動でロックされるまでの秒数、ロック中は None）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompartmentInfo {
+    pub category_name: String,
+    pub account_count: u32,
+    pub relock_minutes: u32,
+    pub relocks_in_secs: Option<u64>,
+}
+
+// トラベルモードの状態（端末に残しているカテゴリと、トラベルモードにした日時）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TravelModeInfo {
+    pub enabled: bool,
+    pub safe_categories: Vec<String>,
+    pub enabled_at: Option<String>,
+}
+
+// DBの整理の結果（free_pages は整理前の空き領域のページ数、サイズはバイト数）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrubReport {
+    pub free_pages: u64,
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+// 保管庫の点検で見つかる問題の種類
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DoctorIssueKind {
+    Integrity,
+    ForeignKey,
+    UndecryptablePassword,
+    OrphanIdentifier,
+    AccountWithoutIdentifier,
+    AccountWithoutCategory,
+    DuplicateCategoryLink,
+    OrphanCategory,
+}
+
+// target は問題のある行（アカウントのULIDやカテゴリ名など）、repairable は自動で修復できるか
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DoctorIssue {
+    pub kind: DoctorIssueKind,
+    pub target: String,
+    pub detail: String,
+    pub repairable: bool,
+}
+
+// 保管庫の点検の結果（skipped_passwords はロック中のコンパートメントのため復号を確認できなかった数）
+// 修復した場合、repaired は修復した問題、issues は修復後に残っている問題、backup_file_name は修復前のバックアップ
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DoctorReport {
+    pub issues: Vec<DoctorIssue>,
+    pub repaired: Vec<DoctorIssue>,
+    pub checked_passwords: u32,
+    pub skipped_passwords: u32,
+    pub backup_file_name: Option<String>,
+}
+
+// 起動時に保管庫を開けなかった原因と、確認する場所（occurred_at はUNIX時刻の秒）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupError {
+    pub code: String,
+    pub message: String,
+    pub suggestion: String,
+    pub data_dir: String,
+    pub vault_dir: String,
+    pub database_path: String,
+    pub key_file_path: String,
+    pub log_path: Option<String>,
+    pub occurred_at: u64,
+}
+
+// 保管庫ごとの設定（DBに項目ごとに保存する、時間や件数の0は無効または無制限を表す）
+// 保存されていない項目は既定値になる
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VaultSettings {
+    pub auto_lock_minutes: u32,
+    pub clipboard_clear_seconds: u32,
+    pub generator: GeneratorSettings,
+    pub backup: BackupSettings,
+    pub trash_retention_days: u32,
+    pub password_history_limit: u32,
+}
+
+// パスワードを生成する際の既定値
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GeneratorSettings {
+    pub length: u32,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+}
+
+// keep と remote_keep は残すバックアップの数、every_changes と interval_hours は自動でバックアップする間隔
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BackupSettings {
+    pub keep: u32,
+    pub every_changes: u32,
+    pub interval_hours: u32,
+    pub remote_keep: u32,
+}
+
+// 保管庫を開く前から使うアプリ全体の設定（データディレクトリの settings.json に保存する）
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AppSettings {
+    pub ui_language: String,
+}
+
+// 秘密情報（パスワード・識別子・保管庫のキーから派生した値）は、ログやエラーの表示に出さない
+// FormData を含む型（AccountSnapshot・SyncAccount・ChangeSet・SharePayload）は、derive した Debug でもこの表示になる
+impl fmt::Debug for FormData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FormData")
+            .field("account_name", &self.account_name)
+            .field("identifier", &Redacted)
+            .field("passwords", &Redacted)
+            .field("category_name", &self.category_name)
+            .finish()
+    }
+}
+
+impl fmt::Debug for AccountInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccountInfo")
+            .field("account_ulid", &self.account_ulid)
+            .field("account_name", &self.account_name)
+            .field("identifier_ulid", &self.identifier_ulid)
+            .field("identifier", &Redacted)
+            .field("passwords", &self.passwords)
+            .field("category_name", &self.category_name)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+impl fmt::Debug for PasswordInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PasswordInfo")
+            .field("id", &self.id)
+            .field("password_raw", &Redacted)
+            .finish()
+    }
+}
+
+impl fmt::Debug for AccountSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccountSummary")
+            .field("account_ulid", &self.account_ulid)
+            .field("account_name", &self.account_name)
+            .field("identifier", &Redacted)
+            .field("identifier_ulid", &self.identifier_ulid)
+            .field("category_name", &self.category_name)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+impl fmt::Debug for SearchCriteria {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SearchCriteria")
+            .field("account_name", &self.account_name)
+            .field("identifier", &Redacted)
+            .field("category_name", &self.category_name)
+            .finish()
+    }
+}
+
+impl fmt::Debug for TrashedAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrashedAccount")
+            .field("account_ulid", &self.account_ulid)
+            .field("account_name", &self.account_name)
+            .field("identifier", &Redacted)
+            .field("category_name", &self.category_name)
+            .field("deleted_at", &self.deleted_at)
+            .finish()
+    }
+}
+
+// 競合した値は項目によってパスワードや識別子のため、値はすべて出さない
+impl fmt::Debug for SyncConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncConflict")
+            .field("id", &self.id)
+            .field("account_ulid", &self.account_ulid)
+            .field("account_name", &self.account_name)
+            .field("field", &self.field)
+            .field("local_value", &Redacted)
+            .field("remote_value", &Redacted)
+            .field("created_at", &self.created_at)
+            .finish()
+    }
+}
+
+impl fmt::Debug for RegisterDeviceRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegisterDeviceRequest")
+            .field("device_id", &self.device_id)
+            .field("public_key", &self.public_key)
+            .field("vault_auth", &Redacted)
+            .finish()
+    }
+}
@@ -0,0 +1,178 @@
+use serde::Deserialize;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// Tauri の app_data_dir と同じ場所になるよう、識別子をディレクトリ名に使う
+const APP_IDENTIFIER: &str = "com.local-password-manager.app";
+const CONFIG_FILE: &str = "config.json";
+const PORTABLE_MARKER_FILE: &str = "portable";
+const PORTABLE_DATA_DIR: &str = "data";
+const DATA_DIR_ARG: &str = "--data-dir";
+const PORTABLE_ARG: &str = "--portable";
+pub const DATA_DIR_ENV_VAR: &str = "JASMIFY_DATA_DIR";
+
+static DATA_DIR: OnceLock<(PathBuf, DataDirSource)> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataDirSource {
+    CommandLine,
+    Environment,
+    Portable,
+    Config,
+    Default,
+}
+
+impl DataDirSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataDirSource::CommandLine => "commandLine",
+            DataDirSource::Environment => "environment",
+            DataDirSource::Portable => "portable",
+            DataDirSource::Config => "config",
+            DataDirSource::Default => "default",
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LocationConfig {
+    data_dir: Option<PathBuf>,
+}
+
+// データディレクトリのパスを取得（DBとキーファイルはこの下に置く）
+pub fn get_data_dir() -> &'static Path {
+    &get_data_dir_with_source().0
+}
+
+pub fn get_data_dir_source() -> DataDirSource {
+    get_data_dir_with_source().1
+}
+
+fn get_data_dir_with_source() -> &'static (PathBuf, DataDirSource) {
+    DATA_DIR.get_or_init(|| {
+        resolve_data_dir(
+            &env::args().skip(1).collect::<Vec<_>>(),
+            env::var_os(DATA_DIR_ENV_VAR).map(PathBuf::from),
+        )
+    })
+}
+
+// コマンドライン引数 > 環境変数 > ポータブルモード > 設定ファイル > OS標準の場所 の順に決定
+fn resolve_data_dir(args: &[String], env_data_dir: Option<PathBuf>) -> (PathBuf, DataDirSource) {
+    if let Some(data_dir) = parse_data_dir_arg(args) {
+        return (data_dir, DataDirSource::CommandLine);
+    }
+    if let Some(data_dir) = env_data_dir.filter(|data_dir| !data_dir.as_os_str().is_empty()) {
+        return (data_dir, DataDirSource::Environment);
+    }
+    if let Some(exe_dir) = get_exe_dir() {
+        if args.iter().any(|arg| arg == PORTABLE_ARG) || exe_dir.join(PORTABLE_MARKER_FILE).exists()
+        {
+            return (exe_dir.join(PORTABLE_DATA_DIR), DataDirSource::Portable);
+        }
+    }
+    if let Some(data_dir) = read_location_config().data_dir {
+        return (data_dir, DataDirSource::Config);
+    }
+
+    // OS標準の場所がない場合はカレントディレクトリ（取得できない場合は実行ファイルの場所）
+    let data_dir = dirs::data_dir()
+        .map(|data_dir| data_dir.join(APP_IDENTIFIER))
+        .or_else(|| env::current_dir().ok())
+        .or_else(get_exe_dir)
+        .unwrap_or_else(|| PathBuf::from("."));
+    (data_dir, DataDirSource::Default)
+}
+
+// `--data-dir <path>` と `--data-dir=<path>` の両方に対応
+fn parse_data_dir_arg(args: &[String]) -> Option<PathBuf> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == DATA_DIR_ARG {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(data_dir) = arg
+            .strip_prefix(DATA_DIR_ARG)
+            .and_then(|a| a.strip_prefix('='))
+        {
+            return (!data_dir.is_empty()).then(|| PathBuf::from(data_dir));
+        }
+    }
+    None
+}
+
+fn get_exe_dir() -> Option<PathBuf> {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+}
+
+// 設定ファイル（XDG_CONFIG_HOME など）からデータディレクトリの指定を読み込む
+fn read_location_config() -> LocationConfig {
+    dirs::config_dir()
+        .map(|config_dir| config_dir.join(APP_IDENTIFIER).join(CONFIG_FILE))
+        .and_then(|config_path| std::fs::read_to_string(config_path).ok())
+        .and_then(|config| serde_json::from_str(&config).ok())
+        .unwrap_or_default()
+}
+
+// 以前のバージョンはカレントディレクトリに保存していたため、その場所に残っている保管庫を探す
+pub fn find_legacy_data_dir(vault_marker: &Path) -> Option<PathBuf> {
+    let data_dir = dunce::canonicalize(get_data_dir()).ok();
+
+    [env::current_dir().ok(), get_exe_dir()]
+        .into_iter()
+        .flatten()
+        .filter(|legacy_dir| legacy_dir.join(vault_marker).exists())
+        .find(|legacy_dir| dunce::canonicalize(legacy_dir).ok() != data_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_resolve_data_dir_prefers_command_line() {
+        let (data_dir, source) = resolve_data_dir(
+            &args(&["--data-dir", "/tmp/cli"]),
+            Some(PathBuf::from("/tmp/env")),
+        );
+
+        assert_eq!(data_dir, PathBuf::from("/tmp/cli"));
+        assert_eq!(source, DataDirSource::CommandLine);
+    }
+
+    #[test]
+    fn test_resolve_data_dir_from_env() {
+        let (data_dir, source) =
+            resolve_data_dir(&args(&["--data-dir="]), Some(PathBuf::from("/tmp/env")));
+
+        // 空のパス指定は無視して環境変数を使うことを確認
+        assert_eq!(data_dir, PathBuf::from("/tmp/env"));
+        assert_eq!(source, DataDirSource::Environment);
+    }
+
+    #[test]
+    fn test_resolve_data_dir_portable() {
+        let (data_dir, source) = resolve_data_dir(&args(&["--portable"]), None);
+
+        assert_eq!(data_dir, get_exe_dir().unwrap().join(PORTABLE_DATA_DIR));
+        assert_eq!(source, DataDirSource::Portable);
+    }
+
+    #[test]
+    fn test_parse_data_dir_arg() {
+        assert_eq!(
+            parse_data_dir_arg(&args(&["--data-dir=/tmp/vault"])),
+            Some(PathBuf::from("/tmp/vault"))
+        );
+        assert_eq!(parse_data_dir_arg(&args(&["--data-dir"])), None);
+        assert_eq!(parse_data_dir_arg(&args(&["--data-directory"])), None);
+    }
+}
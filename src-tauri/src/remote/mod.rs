@@ -0,0 +1,614 @@
+mod s3;
+mod webdav;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use reqwest::{Client, Response, StatusCode, Url};
+use sqlx::SqlitePool;
+use std::env;
+use std::time::Duration;
+
+use crate::backup;
+use crate::error::{AppError, Result};
+use crate::models::RemoteBackupInfo;
+use crate::settings::load_vault_settings;
+use crate::vault::current_vault_id;
+
+use s3::S3Store;
+use webdav::WebDavStore;
+
+pub const REMOTE_BACKUP_KIND_ENV_VAR: &str = "JASMIFY_REMOTE_BACKUP_KIND";
+pub const REMOTE_BACKUP_URL_ENV_VAR: &str = "JASMIFY_REMOTE_BACKUP_URL";
+pub const REMOTE_BACKUP_USERNAME_ENV_VAR: &str = "JASMIFY_REMOTE_BACKUP_USERNAME";
+pub const REMOTE_BACKUP_PASSWORD_ENV_VAR: &str = "JASMIFY_REMOTE_BACKUP_PASSWORD";
+pub const REMOTE_BACKUP_REGION_ENV_VAR: &str = "JASMIFY_REMOTE_BACKUP_REGION";
+const DEFAULT_REMOTE_BACKUP_REGION: &str = "us-east-1";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+// リモートの保存先（保管庫ごとに保存先のURLの下の <保管庫のID>/ に保存する）
+enum RemoteTarget {
+    WebDav(WebDavStore),
+    S3(S3Store),
+}
+
+impl RemoteTarget {
+    // 環境変数から保存先を読み込む（URLが設定されていない場合は None）
+    fn from_env() -> Result<Option<Self>> {
+        let Some(url) = env::var(REMOTE_BACKUP_URL_ENV_VAR)
+            .ok()
+            .filter(|url| !url.is_empty())
+        else {
+            return Ok(None);
+        };
+        let mut url =
+            Url::parse(&url).map_err(|e| invalid_setting(REMOTE_BACKUP_URL_ENV_VAR, e))?;
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+
+        let client = Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+        let username = env::var(REMOTE_BACKUP_USERNAME_ENV_VAR).ok();
+        let password = env::var(REMOTE_BACKUP_PASSWORD_ENV_VAR).ok();
+        let vault_id = current_vault_id()?;
+
+        let kind = env::var(REMOTE_BACKUP_KIND_ENV_VAR).unwrap_or_default();
+        let target = match kind.as_str() {
+            "" | "webdav" => RemoteTarget::WebDav(WebDavStore::new(
+                client, &url, &vault_id, username, password,
+            )?),
+            "s3" => RemoteTarget::S3(S3Store::new(
+                client,
+                &url,
+                &vault_id,
+                username.unwrap_or_default(),
+                password.unwrap_or_default(),
+                env::var(REMOTE_BACKUP_REGION_ENV_VAR)
+                    .unwrap_or_else(|_| DEFAULT_REMOTE_BACKUP_REGION.to_string()),
+            )?),
+            kind => return Err(invalid_setting(REMOTE_BACKUP_KIND_ENV_VAR, kind)),
+        };
+
+        Ok(Some(target))
+    }
+
+    async fn put(&self, file_name: &str, body: Vec<u8>) -> Result<()> {
+        match self {
+            RemoteTarget::WebDav(store) => store.put(file_name, body).await,
+            RemoteTarget::S3(store) => store.put(file_name, body).await,
+        }
+    }
+
+    async fn get(&self, file_name: &str) -> Result<Vec<u8>> {
+        match self {
+            RemoteTarget::WebDav(store) => store.get(file_name).await,
+            RemoteTarget::S3(store) => store.get(file_name).await,
+        }
+    }
+
+    async fn delete(&self, file_name: &str) -> Result<()> {
+        match self {
+            RemoteTarget::WebDav(store) => store.delete(file_name).await,
+            RemoteTarget::S3(store) => store.delete(file_name).await,
+        }
+    }
+
+    // このアプリのバックアップのみを新しい順に返す
+    async fn list(&self) -> Result<Vec<RemoteBackupInfo>> {
+        let entries = match self {
+            RemoteTarget::WebDav(store) => store.list().await?,
+            RemoteTarget::S3(store) => store.list().await?,
+        };
+
+        let mut backups: Vec<RemoteBackupInfo> = entries
+            .into_iter()
+            .filter_map(|(path, size)| {
+                let file_name = path.trim_end_matches('/').rsplit('/').next()?.to_string();
+                backup::is_backup_file_name(&file_name)
+                    .then_some(RemoteBackupInfo { file_name, size })
+            })
+            .collect();
+        backups.sort_by(|a, b| b.file_name.cmp(&a.file_name));
+
+        Ok(backups)
+    }
+}
+
+fn invalid_setting(name: &str, e: impl std::fmt::Display) -> AppError {
+    AppError::Remote(format!("Invalid {}: {}", name, e))
+}
+
+fn require_target() -> Result<RemoteTarget> {
+    RemoteTarget::from_env()?.ok_or_else(|| AppError::NotFound("Remote backup target".to_string()))
+}
+
+pub fn is_configured() -> bool {
+    env::var(REMOTE_BACKUP_URL_ENV_VAR).is_ok_and(|url| !url.is_empty())
+}
+
+// 失敗したレスポンスをエラーに変換（見つからない場合は NotFound）
+async fn check_status(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    if status == StatusCode::NOT_FOUND {
+        return Err(AppError::NotFound("Remote backup".to_string()));
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    Err(AppError::Remote(format!(
+        "{} {}",
+        status,
+        body.chars().take(200).collect::<String>()
+    )))
+}
+
+// リモートにないバックアップをアップロードし、保持件数を超えた古いものを削除
+// （アップロードするのは暗号化済みのバックアップファイルのみ）
+// 保持件数は保管庫の設定（0は無制限）
+pub async fn push_backups(sqlite_pool: &SqlitePool) -> Result<Vec<String>> {
+    let keep = load_vault_settings(sqlite_pool).await?.backup.remote_keep;
+    push_backups_to(&require_target()?, keep).await
+}
+
+async fn push_backups_to(target: &RemoteTarget, keep: u32) -> Result<Vec<String>> {
+    let remote_backups = target.list().await?;
+    let mut local_backups = backup::list_backups()?;
+    // すぐに削除されるものはアップロードしない
+    if keep > 0 {
+        local_backups.truncate(keep as usize);
+    }
+
+    let mut uploaded = Vec::new();
+    for local_backup in local_backups {
+        if remote_backups
+            .iter()
+            .any(|remote_backup| remote_backup.file_name == local_backup.file_name)
+        {
+            continue;
+        }
+        let archive = backup::read_backup(&local_backup.file_name)?;
+        target.put(&local_backup.file_name, archive).await?;
+        uploaded.push(local_backup.file_name);
+    }
+
+    if keep > 0 {
+        for remote_backup in target.list().await?.into_iter().skip(keep as usize) {
+            target.delete(&remote_backup.file_name).await?;
+        }
+    }
+
+    Ok(uploaded)
+}
+
+pub async fn list_remote_backups() -> Result<Vec<RemoteBackupInfo>> {
+    require_target()?.list().await
+}
+
+// リモートのバックアップを保管庫のバックアップに取り込む（復元は取り込んだファイルから行う）
+pub async fn download_backup(file_name: &str) -> Result<()> {
+    download_backup_from(&require_target()?, file_name).await
+}
+
+async fn download_backup_from(target: &RemoteTarget, file_name: &str) -> Result<()> {
+    if !backup::is_backup_file_name(file_name) {
+        return Err(AppError::NotFound("Remote backup".to_string()));
+    }
+    let archive = target.get(file_name).await?;
+
+    backup::import_backup(file_name, &archive)
+}
+
+// WebDAV の PROPFIND と S3 の ListObjectsV2 の応答から、各要素の名前とサイズを取り出す
+fn parse_entries(
+    xml: &str,
+    entry_tag: &str,
+    name_tag: &str,
+    size_tag: &str,
+) -> Result<Vec<(String, u64)>> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut entries = Vec::new();
+    let mut current_tag = Vec::new();
+    let mut name = None;
+    let mut size = 0;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                current_tag = e.local_name().as_ref().to_vec();
+                if current_tag == entry_tag.as_bytes() {
+                    name = None;
+                    size = 0;
+                }
+            }
+            Ok(Event::Text(text)) => {
+                let text = text.unescape().map_err(xml_error)?;
+                if current_tag == name_tag.as_bytes() {
+                    name = Some(text.into_owned());
+                } else if current_tag == size_tag.as_bytes() {
+                    size = text.parse().unwrap_or(0);
+                }
+            }
+            Ok(Event::End(e)) => {
+                if e.local_name().as_ref() == entry_tag.as_bytes() {
+                    if let Some(name) = name.take() {
+                        entries.push((name, size));
+                    }
+                }
+                current_tag.clear();
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => return Err(xml_error(e)),
+        }
+    }
+
+    Ok(entries)
+}
+
+// 応答の中の最初の要素の値を取り出す
+fn find_text(xml: &str, tag: &str) -> Result<Option<String>> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut in_tag = false;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => in_tag = e.local_name().as_ref() == tag.as_bytes(),
+            Ok(Event::Text(text)) if in_tag => {
+                return Ok(Some(text.unescape().map_err(xml_error)?.into_owned()));
+            }
+            Ok(Event::End(_)) => in_tag = false,
+            Ok(Event::Eof) => return Ok(None),
+            Ok(_) => {}
+            Err(e) => return Err(xml_error(e)),
+        }
+    }
+}
+
+fn xml_error(e: impl std::fmt::Display) -> AppError {
+    AppError::Remote(format!("Invalid response: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Bytes;
+    use axum::extract::{Query, State};
+    use axum::http::{HeaderMap, Method, StatusCode, Uri};
+    use axum::response::{IntoResponse, Response};
+    use axum::Router;
+    use sha2::{Digest, Sha256};
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
+    use std::sync::{Arc, Mutex};
+
+    use crate::database::setup_database;
+    use crate::vault::with_test_vault;
+
+    type Files = Arc<Mutex<BTreeMap<String, Vec<u8>>>>;
+
+    // テスト用の WebDAV サーバー（コレクションとファイルをメモリ上に保持する）
+    #[derive(Clone, Default)]
+    struct FakeWebDav {
+        collections: Arc<Mutex<BTreeSet<String>>>,
+        files: Files,
+    }
+
+    async fn handle_webdav(
+        State(dav): State<FakeWebDav>,
+        method: Method,
+        uri: Uri,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Response {
+        if !headers
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("Basic "))
+        {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+
+        // 末尾の / を含む親のコレクションのパス
+        let parent_of = |path: &str| path[..=path.rfind('/').unwrap_or(0)].to_string();
+        let path = uri.path().to_string();
+        let parent = parent_of(&path);
+        let mut collections = dav.collections.lock().unwrap();
+        let mut files = dav.files.lock().unwrap();
+
+        match method.as_str() {
+            "MKCOL" if collections.insert(path.clone()) => StatusCode::CREATED.into_response(),
+            "MKCOL" => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+            "PUT" if !collections.contains(&parent) => StatusCode::CONFLICT.into_response(),
+            "PUT" => {
+                files.insert(path, body.to_vec());
+                StatusCode::CREATED.into_response()
+            }
+            "GET" => match files.get(&path) {
+                Some(file) => file.clone().into_response(),
+                None => StatusCode::NOT_FOUND.into_response(),
+            },
+            "DELETE" => match files.remove(&path) {
+                Some(_) => StatusCode::NO_CONTENT.into_response(),
+                None => StatusCode::NOT_FOUND.into_response(),
+            },
+            "PROPFIND" if !collections.contains(&path) => StatusCode::NOT_FOUND.into_response(),
+            "PROPFIND" => {
+                let responses: String = files
+                    .iter()
+                    .filter(|(file_path, _)| parent_of(file_path) == path)
+                    .map(|(file_path, file)| {
+                        format!(
+                            "<d:response><d:href>{}</d:href><d:propstat><d:prop><d:getcontentlength>{}</d:getcontentlength></d:prop></d:propstat></d:response>",
+                            file_path,
+                            file.len()
+                        )
+                    })
+                    .collect();
+                let xml = format!(
+                    r#"<?xml version="1.0"?><d:multistatus xmlns:d="DAV:"><d:response><d:href>{}</d:href></d:response>{}</d:multistatus>"#,
+                    path, responses
+                );
+                (StatusCode::MULTI_STATUS, xml).into_response()
+            }
+            _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+        }
+    }
+
+    // テスト用の S3 互換サーバー（パス形式、一覧は2件ずつ返して続きの取得も確認する）
+    async fn handle_s3(
+        State(files): State<Files>,
+        method: Method,
+        uri: Uri,
+        Query(query): Query<HashMap<String, String>>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Response {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        if !header("authorization").starts_with("AWS4-HMAC-SHA256 Credential=access/")
+            || header("x-amz-content-sha256") != hex::encode(Sha256::digest(&body))
+        {
+            return StatusCode::FORBIDDEN.into_response();
+        }
+
+        let path = uri.path().trim_start_matches('/');
+        let (bucket, key) = match path.split_once('/') {
+            Some((bucket, key)) => (bucket, Some(key.to_string())),
+            None => (path, None),
+        };
+        if bucket != "bucket" {
+            return StatusCode::NOT_FOUND.into_response();
+        }
+        let mut files = files.lock().unwrap();
+
+        match (method, key) {
+            (Method::PUT, Some(key)) => {
+                files.insert(key, body.to_vec());
+                StatusCode::OK.into_response()
+            }
+            (Method::GET, Some(key)) => match files.get(&key) {
+                Some(file) => file.clone().into_response(),
+                None => StatusCode::NOT_FOUND.into_response(),
+            },
+            (Method::DELETE, Some(key)) => {
+                files.remove(&key);
+                StatusCode::NO_CONTENT.into_response()
+            }
+            (Method::GET, None) if query.get("list-type").map(String::as_str) == Some("2") => {
+                let prefix = query.get("prefix").cloned().unwrap_or_default();
+                let start: usize = query
+                    .get("continuation-token")
+                    .and_then(|token| token.parse().ok())
+                    .unwrap_or(0);
+                let keys: Vec<_> = files
+                    .iter()
+                    .filter(|(key, _)| key.starts_with(&prefix))
+                    .collect();
+                let contents: String = keys
+                    .iter()
+                    .skip(start)
+                    .take(2)
+                    .map(|(key, file)| {
+                        format!(
+                            "<Contents><Key>{}</Key><Size>{}</Size></Contents>",
+                            key,
+                            file.len()
+                        )
+                    })
+                    .collect();
+                let truncated = keys.len() > start + 2;
+                let next = if truncated {
+                    format!(
+                        "<NextContinuationToken>{}</NextContinuationToken>",
+                        start + 2
+                    )
+                } else {
+                    String::new()
+                };
+                let xml = format!(
+                    r#"<?xml version="1.0" encoding="UTF-8"?><ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Name>bucket</Name><IsTruncated>{}</IsTruncated>{}{}</ListBucketResult>"#,
+                    truncated, contents, next
+                );
+                xml.into_response()
+            }
+            _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+        }
+    }
+
+    // ポートを指定せずに起動し、接続先のURLを返す
+    async fn serve(router: Router) -> Url {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        Url::parse(&format!("http://{}/", addr)).unwrap()
+    }
+
+    async fn remote_names(target: &RemoteTarget) -> Vec<String> {
+        target
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|backup| backup.file_name)
+            .collect()
+    }
+
+    // アップロード、一覧、取得、保持件数を超えたものの削除までを確認
+    async fn check_round_trip(target: RemoteTarget) {
+        with_test_vault(|vault_dir| async move {
+            // スナップショットを書き出すため、ファイルのDBを使う
+            let (sqlite_pool, _) = setup_database().await.unwrap();
+
+            // まだ何も保存していない場合は空
+            assert!(target.list().await.unwrap().is_empty());
+            target.put("notes.txt", b"memo".to_vec()).await.unwrap();
+
+            let mut created = Vec::new();
+            for _ in 0..3 {
+                created.push(backup::create_backup(&sqlite_pool).await.unwrap().file_name);
+                tokio::time::sleep(Duration::from_millis(2)).await;
+            }
+            created.reverse();
+
+            // 保持件数を超える古いバックアップはアップロードしない
+            let uploaded = push_backups_to(&target, 2).await.unwrap();
+            assert_eq!(uploaded, created[..2]);
+            assert_eq!(remote_names(&target).await, created[..2]);
+
+            // アップロード済みのものは再度アップロードしない
+            assert!(push_backups_to(&target, 2).await.unwrap().is_empty());
+
+            // 新しいバックアップを加えると、保持件数を超えた古いものを削除する
+            let newest = backup::create_backup(&sqlite_pool).await.unwrap().file_name;
+            let uploaded = push_backups_to(&target, 2).await.unwrap();
+            assert_eq!(uploaded, std::slice::from_ref(&newest));
+            assert_eq!(remote_names(&target).await, [newest, created[0].clone()]);
+            assert!(matches!(
+                target.get(&created[1]).await,
+                Err(AppError::NotFound(_))
+            ));
+
+            // バックアップ以外のファイルは一覧に含めず、削除もしない
+            assert_eq!(target.get("notes.txt").await.unwrap(), b"memo");
+
+            // 取り込んだバックアップはアップロードしたものと同じ
+            let local = backup::read_backup(&created[0]).unwrap();
+            std::fs::remove_file(vault_dir.join("backups").join(&created[0])).unwrap();
+            download_backup_from(&target, &created[0]).await.unwrap();
+            assert_eq!(backup::read_backup(&created[0]).unwrap(), local);
+
+            sqlite_pool.close().await;
+
+            // バックアップの形式でない名前は取得しない
+            assert!(matches!(
+                download_backup_from(&target, "notes.txt").await,
+                Err(AppError::NotFound(_))
+            ));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_webdav_round_trip() {
+        let url = serve(
+            Router::new()
+                .fallback(handle_webdav)
+                .with_state(FakeWebDav::default()),
+        )
+        .await;
+        let base_url = url.join("dav/jasmify/").unwrap();
+        let store = WebDavStore::new(
+            Client::new(),
+            &base_url,
+            "default",
+            Some("user".to_string()),
+            Some("secret".to_string()),
+        )
+        .unwrap();
+
+        check_round_trip(RemoteTarget::WebDav(store)).await;
+    }
+
+    #[tokio::test]
+    async fn test_s3_round_trip() {
+        let url = serve(
+            Router::new()
+                .fallback(handle_s3)
+                .with_state(Files::default()),
+        )
+        .await;
+        let base_url = url.join("bucket/jasmify/").unwrap();
+        let store = S3Store::new(
+            Client::new(),
+            &base_url,
+            "default",
+            "access".to_string(),
+            "secret".to_string(),
+            DEFAULT_REMOTE_BACKUP_REGION.to_string(),
+        )
+        .unwrap();
+
+        check_round_trip(RemoteTarget::S3(store)).await;
+    }
+
+    #[test]
+    fn test_parse_entries_webdav() {
+        let xml = r#"<?xml version="1.0"?>
+            <d:multistatus xmlns:d="DAV:">
+              <d:response>
+                <d:href>/dav/jasmify/default/</d:href>
+                <d:propstat><d:prop><d:getcontentlength/></d:prop></d:propstat>
+              </d:response>
+              <d:response>
+                <d:href>/dav/jasmify/default/backup-01JN0000000000000000000000.jbak</d:href>
+                <d:propstat><d:prop><d:getcontentlength>1024</d:getcontentlength></d:prop></d:propstat>
+              </d:response>
+            </d:multistatus>"#;
+
+        let entries = parse_entries(xml, "response", "href", "getcontentlength").unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                ("/dav/jasmify/default/".to_string(), 0),
+                (
+                    "/dav/jasmify/default/backup-01JN0000000000000000000000.jbak".to_string(),
+                    1024
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_entries_s3() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+              <Name>bucket</Name>
+              <IsTruncated>true</IsTruncated>
+              <Contents><Key>jasmify/default/a&amp;b.jbak</Key><Size>10</Size></Contents>
+              <NextContinuationToken>token</NextContinuationToken>
+            </ListBucketResult>"#;
+
+        let entries = parse_entries(xml, "Contents", "Key", "Size").unwrap();
+
+        assert_eq!(entries, vec![("jasmify/default/a&b.jbak".to_string(), 10)]);
+        assert_eq!(
+            find_text(xml, "IsTruncated").unwrap().as_deref(),
+            Some("true")
+        );
+        assert_eq!(
+            find_text(xml, "NextContinuationToken").unwrap().as_deref(),
+            Some("token")
+        );
+        assert_eq!(find_text(xml, "Missing").unwrap(), None);
+    }
+}
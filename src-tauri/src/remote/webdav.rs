@@ -0,0 +1,119 @@
+use reqwest::{Client, Method, RequestBuilder, StatusCode, Url};
+
+use super::{check_status, invalid_setting, parse_entries, REMOTE_BACKUP_URL_ENV_VAR};
+use crate::error::Result;
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:getcontentlength/></d:prop></d:propfind>"#;
+
+// Nextcloud などの WebDAV サーバー（URL はバックアップを置くコレクション）
+pub struct WebDavStore {
+    client: Client,
+    base_url: Url,
+    vault_url: Url,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl WebDavStore {
+    pub fn new(
+        client: Client,
+        base_url: &Url,
+        vault_id: &str,
+        username: Option<String>,
+        password: Option<String>,
+    ) -> Result<Self> {
+        let vault_url = base_url
+            .join(&format!("{}/", vault_id))
+            .map_err(|e| invalid_setting(REMOTE_BACKUP_URL_ENV_VAR, e))?;
+
+        Ok(WebDavStore {
+            client,
+            base_url: base_url.clone(),
+            vault_url,
+            username,
+            password,
+        })
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        }
+    }
+
+    fn file_url(&self, file_name: &str) -> Result<Url> {
+        self.vault_url
+            .join(file_name)
+            .map_err(|e| invalid_setting(REMOTE_BACKUP_URL_ENV_VAR, e))
+    }
+
+    // コレクションを作成（既に存在する場合は 405 が返るため成功とみなす）
+    async fn make_collection(&self, url: &Url) -> Result<()> {
+        let response = self
+            .request(Method::from_bytes(b"MKCOL").unwrap(), url.clone())
+            .send()
+            .await?;
+        if response.status() == StatusCode::METHOD_NOT_ALLOWED {
+            return Ok(());
+        }
+        check_status(response).await?;
+
+        Ok(())
+    }
+
+    pub async fn put(&self, file_name: &str, body: Vec<u8>) -> Result<()> {
+        self.make_collection(&self.base_url).await?;
+        self.make_collection(&self.vault_url).await?;
+
+        let response = self
+            .request(Method::PUT, self.file_url(file_name)?)
+            .body(body)
+            .send()
+            .await?;
+        check_status(response).await?;
+
+        Ok(())
+    }
+
+    pub async fn get(&self, file_name: &str) -> Result<Vec<u8>> {
+        let response = self
+            .request(Method::GET, self.file_url(file_name)?)
+            .send()
+            .await?;
+
+        Ok(check_status(response).await?.bytes().await?.to_vec())
+    }
+
+    pub async fn delete(&self, file_name: &str) -> Result<()> {
+        let response = self
+            .request(Method::DELETE, self.file_url(file_name)?)
+            .send()
+            .await?;
+        check_status(response).await?;
+
+        Ok(())
+    }
+
+    // コレクション内のファイルのパスとサイズ（まだコレクションがない場合は空）
+    pub async fn list(&self) -> Result<Vec<(String, u64)>> {
+        let response = self
+            .request(
+                Method::from_bytes(b"PROPFIND").unwrap(),
+                self.vault_url.clone(),
+            )
+            .header("Depth", "1")
+            .header("Content-Type", "application/xml")
+            .body(PROPFIND_BODY)
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let xml = check_status(response).await?.text().await?;
+
+        parse_entries(&xml, "response", "href", "getcontentlength")
+    }
+}
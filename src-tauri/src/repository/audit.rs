@@ -0,0 +1,422 @@
+use crate::crypto::{compute_chain_hash, get_encryption_key, write_file_atomically};
+use crate::database::get_database_dir;
+use crate::error::Result;
+use crate::models::{AuditLogEntry, AuditLogFilter, AuditLogVerification};
+use sqlx::{Row, SqlitePool};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+use tracing::instrument;
+
+const AUDIT_HEAD_FILE: &str = "audit_head";
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// 同一プロセス内での追記を直列化してチェーンの分岐を防ぐ
+static AUDIT_LOCK: Mutex<()> = Mutex::const_new(());
+
+pub enum AuditInterface {
+    Gui,
+    Cli,
+}
+
+impl AuditInterface {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AuditInterface::Gui => "gui",
+            AuditInterface::Cli => "cli",
+        }
+    }
+}
+
+// 末尾の切り詰めを検出するため、最新の記録を DB とは別のファイルにも残す
+fn get_audit_head_path() -> Result<PathBuf> {
+    Ok(get_database_dir()?.join(AUDIT_HEAD_FILE))
+}
+
+// 復元の記録のみ、復元前の最新の記録もハッシュの対象にする（それ以外の記録のハッシュは変えない）
+fn chain_payload(
+    id: u32,
+    operation: &str,
+    account_ulid: Option<&str>,
+    interface: &str,
+    succeeded: bool,
+    created_at: &str,
+    replaced_head: Option<&str>,
+) -> String {
+    let payload = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        id,
+        operation,
+        account_ulid.unwrap_or(""),
+        interface,
+        succeeded,
+        created_at
+    );
+    match replaced_head {
+        Some(replaced_head) => format!("{}\n{}", payload, replaced_head),
+        None => payload,
+    }
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn append_audit_log(
+    sqlite_pool: &SqlitePool,
+    operation: &str,
+    account_ulid: Option<&str>,
+    interface: AuditInterface,
+    succeeded: bool,
+) -> Result<()> {
+    append_record(
+        sqlite_pool,
+        operation,
+        account_ulid,
+        interface,
+        succeeded,
+        false,
+    )
+    .await
+}
+
+// バックアップから復元した後、復元前の最新の記録を残して復元を記録する
+// （最新の記録を復元したDBに合わせて書き直すだけでは、記録の巻き戻しを隠せてしまうため）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn append_restore_record(
+    sqlite_pool: &SqlitePool,
+    operation: &str,
+    interface: AuditInterface,
+) -> Result<()> {
+    append_record(sqlite_pool, operation, None, interface, true, true).await
+}
+
+async fn append_record(
+    sqlite_pool: &SqlitePool,
+    operation: &str,
+    account_ulid: Option<&str>,
+    interface: AuditInterface,
+    succeeded: bool,
+    restored: bool,
+) -> Result<()> {
+    let _guard = AUDIT_LOCK.lock().await;
+    let audit_head_path = get_audit_head_path()?;
+
+    // 復元前の最新の記録（ファイルがない場合は空）
+    let replaced_head = if restored {
+        match std::fs::read_to_string(&audit_head_path) {
+            Ok(audit_head) => Some(audit_head.trim().to_string()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Some(String::new()),
+            Err(e) => return Err(e.into()),
+        }
+    } else {
+        None
+    };
+
+    let mut tx = sqlite_pool.begin().await?;
+
+    let last_record = sqlx::query(
+        r#"
+        SELECT id, hash FROM audit_log ORDER BY id DESC LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let (last_id, prev_hash) = match last_record {
+        Some(row) => (row.try_get::<u32, _>("id")?, row.try_get("hash")?),
+        None => (0, GENESIS_HASH.to_string()),
+    };
+
+    let created_at: String = sqlx::query_scalar("SELECT strftime('%Y-%m-%d %H:%M:%f', 'now')")
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let id = last_id + 1;
+    let interface = interface.as_str();
+    let key = get_encryption_key()?;
+    let hash = compute_chain_hash(
+        &key,
+        &prev_hash,
+        &chain_payload(
+            id,
+            operation,
+            account_ulid,
+            interface,
+            succeeded,
+            &created_at,
+            replaced_head.as_deref(),
+        ),
+    );
+
+    sqlx::query(
+        r#"
+        INSERT INTO audit_log (
+            id, operation, account_ulid, interface, succeeded, created_at, prev_hash, hash,
+            replaced_head
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(id)
+    .bind(operation)
+    .bind(account_ulid)
+    .bind(interface)
+    .bind(succeeded)
+    .bind(&created_at)
+    .bind(&prev_hash)
+    .bind(&hash)
+    .bind(&replaced_head)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    write_file_atomically(&audit_head_path, format!("{} {}", id, hash).as_bytes())?;
+
+    Ok(())
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_audit_log(
+    sqlite_pool: &SqlitePool,
+    filter: AuditLogFilter,
+) -> Result<Vec<AuditLogEntry>> {
+    let (sql, bindings) = build_filter_conditions(&filter);
+
+    let mut query = sqlx::query(&sql);
+    for binding in &bindings {
+        query = query.bind(binding);
+    }
+
+    let rows = query.fetch_all(sqlite_pool).await?;
+
+    let mut audit_log = Vec::new();
+
+    for row in rows {
+        let id: u32 = row.try_get("id")?;
+        let operation: String = row.try_get("operation")?;
+        let account_ulid: Option<String> = row.try_get("account_ulid")?;
+        let interface: String = row.try_get("interface")?;
+        let succeeded: bool = row.try_get("succeeded")?;
+        let created_at: String = row.try_get("created_at")?;
+        let hash: String = row.try_get("hash")?;
+        let replaced_head: Option<String> = row.try_get("replaced_head")?;
+
+        audit_log.push(AuditLogEntry {
+            id,
+            operation,
+            account_ulid,
+            interface,
+            succeeded,
+            created_at,
+            hash,
+            replaced_head,
+        });
+    }
+
+    Ok(audit_log)
+}
+
+fn build_filter_conditions(filter: &AuditLogFilter) -> (String, Vec<String>) {
+    let mut query = String::from(
+        "SELECT id, operation, account_ulid, interface, succeeded, created_at, hash, replaced_head
+        FROM audit_log
+        WHERE 1=1",
+    );
+
+    let mut bindings = Vec::new();
+
+    if !filter.operation.is_empty() {
+        query.push_str(" AND operation = ?");
+        bindings.push(filter.operation.clone());
+    }
+    if !filter.account_ulid.is_empty() {
+        query.push_str(" AND account_ulid = ?");
+        bindings.push(filter.account_ulid.clone());
+    }
+    if !filter.interface.is_empty() {
+        query.push_str(" AND interface = ?");
+        bindings.push(filter.interface.clone());
+    }
+    if !filter.since.is_empty() {
+        query.push_str(" AND created_at >= ?");
+        bindings.push(filter.since.clone());
+    }
+    if !filter.until.is_empty() {
+        query.push_str(" AND created_at <= ?");
+        bindings.push(filter.until.clone());
+    }
+
+    query.push_str(" ORDER BY id DESC");
+    if let Some(limit) = filter.limit {
+        query.push_str(&format!(" LIMIT {}", limit));
+    }
+
+    (query, bindings)
+}
+
+// ハッシュチェーンを先頭から検証し、改ざん・欠落・切り詰めを検出
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn verify_audit_log(sqlite_pool: &SqlitePool) -> Result<AuditLogVerification> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            id, operation, account_ulid, interface, succeeded, created_at, prev_hash, hash,
+            replaced_head
+        FROM audit_log
+        ORDER BY id
+        "#,
+    )
+    .fetch_all(sqlite_pool)
+    .await?;
+
+    let key = get_encryption_key()?;
+    let mut last_id = 0;
+    let mut last_hash = GENESIS_HASH.to_string();
+    let mut record_count = 0;
+
+    let broken = |id: u32, record_count: u32, reason: &str| AuditLogVerification {
+        valid: false,
+        record_count,
+        broken_at: Some(id),
+        reason: Some(reason.to_string()),
+    };
+
+    for row in rows {
+        let id: u32 = row.try_get("id")?;
+        let operation: String = row.try_get("operation")?;
+        let account_ulid: Option<String> = row.try_get("account_ulid")?;
+        let interface: String = row.try_get("interface")?;
+        let succeeded: bool = row.try_get("succeeded")?;
+        let created_at: String = row.try_get("created_at")?;
+        let prev_hash: String = row.try_get("prev_hash")?;
+        let hash: String = row.try_get("hash")?;
+        let replaced_head: Option<String> = row.try_get("replaced_head")?;
+
+        if id != last_id + 1 {
+            return Ok(broken(
+                id,
+                record_count,
+                "Missing records before this entry",
+            ));
+        }
+        if prev_hash != last_hash {
+            return Ok(broken(
+                id,
+                record_count,
+                "Chain link does not match previous entry",
+            ));
+        }
+
+        let expected_hash = compute_chain_hash(
+            &key,
+            &prev_hash,
+            &chain_payload(
+                id,
+                &operation,
+                account_ulid.as_deref(),
+                &interface,
+                succeeded,
+                &created_at,
+                replaced_head.as_deref(),
+            ),
+        );
+        if hash != expected_hash {
+            return Ok(broken(id, record_count, "Entry has been modified"));
+        }
+
+        last_id = id;
+        last_hash = hash;
+        record_count += 1;
+    }
+
+    // 先頭からの検証では末尾の削除を検出できないため、別ファイルの最新記録と照合
+    let audit_head_path = get_audit_head_path()?;
+    if audit_head_path.exists() {
+        let audit_head = std::fs::read_to_string(&audit_head_path)?;
+        let Some((head_id, head_hash)) = audit_head
+            .trim()
+            .split_once(' ')
+            .and_then(|(head_id, head_hash)| Some((head_id.parse::<u32>().ok()?, head_hash)))
+        else {
+            return Ok(broken(
+                last_id,
+                record_count,
+                "Audit head file is corrupted",
+            ));
+        };
+
+        if head_id > last_id {
+            return Ok(broken(head_id, record_count, "Log has been truncated"));
+        }
+        let recorded_hash: Option<String> =
+            sqlx::query_scalar("SELECT hash FROM audit_log WHERE id = ?")
+                .bind(head_id)
+                .fetch_optional(sqlite_pool)
+                .await?;
+        if recorded_hash.as_deref() != Some(head_hash) {
+            return Ok(broken(head_id, record_count, "Log has been rewritten"));
+        }
+    } else if record_count > 0 {
+        return Ok(broken(last_id, record_count, "Audit head file is missing"));
+    }
+
+    Ok(AuditLogVerification {
+        valid: true,
+        record_count,
+        broken_at: None,
+        reason: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_pool;
+    use crate::vault::with_test_vault;
+
+    #[tokio::test]
+    async fn test_restore_is_recorded_in_chain() {
+        with_test_vault(|_| async {
+            std::fs::create_dir_all(get_database_dir().unwrap()).unwrap();
+
+            // 復元するバックアップの時点の記録は1件、復元前の保管庫の記録は3件
+            let restored_pool = test_pool().await;
+            append_audit_log(&restored_pool, "backup", None, AuditInterface::Gui, true)
+                .await
+                .unwrap();
+            let current_pool = test_pool().await;
+            for _ in 0..3 {
+                append_audit_log(&current_pool, "update", None, AuditInterface::Gui, true)
+                    .await
+                    .unwrap();
+            }
+            let replaced_head = std::fs::read_to_string(get_audit_head_path().unwrap()).unwrap();
+
+            // 復元しただけでは、記録の巻き戻しとして検出される
+            let verification = verify_audit_log(&restored_pool).await.unwrap();
+            assert!(!verification.valid);
+
+            // 復元を記録すると、復元前の最新の記録を残したうえでチェーンがつながる
+            append_restore_record(&restored_pool, "restore_backup", AuditInterface::Gui)
+                .await
+                .unwrap();
+            let verification = verify_audit_log(&restored_pool).await.unwrap();
+            assert!(verification.valid);
+            assert_eq!(verification.record_count, 2);
+
+            let filter = AuditLogFilter {
+                operation: "restore_backup".to_string(),
+                account_ulid: String::new(),
+                interface: String::new(),
+                since: String::new(),
+                until: String::new(),
+                limit: None,
+            };
+            let entries = get_audit_log(&restored_pool, filter).await.unwrap();
+            assert_eq!(
+                entries[0].replaced_head.as_deref(),
+                Some(replaced_head.trim())
+            );
+        })
+        .await;
+    }
+}
@@ -0,0 +1,292 @@
+use crate::crypto::{decrypt_password, encrypt_password};
+use crate::error::Result;
+use aes_gcm::{Aes256Gcm, Key};
+use sqlx::{Row, Sqlite, SqliteConnection, SqlitePool, Transaction};
+use tracing::instrument;
+
+pub struct CompartmentRow {
+    pub category_id: i64,
+    pub category_name: String,
+    pub salt: String,
+    pub key_check: String,
+    pub relock_minutes: u32,
+    pub account_count: u32,
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn list_compartments(sqlite_pool: &SqlitePool) -> Result<Vec<CompartmentRow>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            cp.category_id,
+            c.category_name,
+            cp.salt,
+            cp.key_check,
+            cp.relock_minutes,
+            (SELECT COUNT(*) FROM account_categories ac WHERE ac.category_id = cp.category_id)
+                AS account_count
+        FROM
+            compartments cp
+        JOIN
+            categories c ON cp.category_id = c.id
+        ORDER BY
+            c.category_name
+        "#,
+    )
+    .fetch_all(sqlite_pool)
+    .await?;
+
+    rows.iter().map(compartment_from_row).collect()
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_compartment_by_name(
+    conn: &mut SqliteConnection,
+    category_name: &str,
+) -> Result<Option<CompartmentRow>> {
+    let row = sqlx::query(
+        r#"
+        SELECT
+            cp.category_id,
+            c.category_name,
+            cp.salt,
+            cp.key_check,
+            cp.relock_minutes,
+            (SELECT COUNT(*) FROM account_categories ac WHERE ac.category_id = cp.category_id)
+                AS account_count
+        FROM
+            compartments cp
+        JOIN
+            categories c ON cp.category_id = c.id
+        WHERE
+            c.category_name = ?
+        "#,
+    )
+    .bind(category_name)
+    .fetch_optional(conn)
+    .await?;
+
+    row.as_ref().map(compartment_from_row).transpose()
+}
+
+fn compartment_from_row(row: &sqlx::sqlite::SqliteRow) -> Result<CompartmentRow> {
+    Ok(CompartmentRow {
+        category_id: row.try_get("category_id")?,
+        category_name: row.try_get("category_name")?,
+        salt: row.try_get("salt")?,
+        key_check: row.try_get("key_check")?,
+        relock_minutes: row.try_get("relock_minutes")?,
+        account_count: row.try_get("account_count")?,
+    })
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_category_id(
+    tx: &mut Transaction<'_, Sqlite>,
+    category_name: &str,
+) -> Result<Option<i64>> {
+    let category_id = sqlx::query_scalar("SELECT id FROM categories WHERE category_name = ?")
+        .bind(category_name)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+    Ok(category_id)
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_category_name(
+    conn: &mut SqliteConnection,
+    category_id: i64,
+) -> Result<Option<String>> {
+    let category_name = sqlx::query_scalar("SELECT category_name FROM categories WHERE id = ?")
+        .bind(category_id)
+        .fetch_optional(conn)
+        .await?;
+
+    Ok(category_name)
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn insert_compartment(
+    tx: &mut Transaction<'_, Sqlite>,
+    category_id: i64,
+    salt: &str,
+    key_check: &str,
+    relock_minutes: u32,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO compartments (category_id, salt, key_check, relock_minutes)
+        VALUES (?, ?, ?, ?)
+        "#,
+    )
+    .bind(category_id)
+    .bind(salt)
+    .bind(key_check)
+    .bind(relock_minutes)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn delete_compartment(tx: &mut Transaction<'_, Sqlite>, category_id: i64) -> Result<()> {
+    sqlx::query("DELETE FROM compartments WHERE category_id = ?")
+        .bind(category_id)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+// アカウントが属するコンパートメント（通常のカテゴリの場合は None）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_account_compartment(
+    conn: &mut SqliteConnection,
+    account_ulid: &str,
+) -> Result<Option<i64>> {
+    let compartment_id = sqlx::query_scalar(
+        r#"
+        SELECT cp.category_id
+        FROM account_categories ac
+        JOIN compartments cp ON ac.category_id = cp.category_id
+        WHERE ac.account_ulid = ?
+        "#,
+    )
+    .bind(account_ulid)
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(compartment_id)
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_identifier_compartment(
+    conn: &mut SqliteConnection,
+    identifier_ulid: &str,
+) -> Result<Option<i64>> {
+    let compartment_id = sqlx::query_scalar(
+        r#"
+        SELECT cp.category_id
+        FROM identifiers i
+        JOIN account_categories ac ON i.account_ulid = ac.account_ulid
+        JOIN compartments cp ON ac.category_id = cp.category_id
+        WHERE i.ulid = ?
+        "#,
+    )
+    .bind(identifier_ulid)
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(compartment_id)
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_category_account_ulids(
+    tx: &mut Transaction<'_, Sqlite>,
+    category_id: i64,
+) -> Result<Vec<String>> {
+    let account_ulids = sqlx::query_scalar(
+        r#"
+        SELECT account_ulid FROM account_categories WHERE category_id = ? ORDER BY account_ulid
+        "#,
+    )
+    .bind(category_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(account_ulids)
+}
+
+// アカウントのパスワード・パスワード履歴・変更履歴を別のキーで暗号化し直す
+// （コンパートメントに移す場合は、保管庫のキーで暗号化した同期の記録を残さない）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn rekey_account(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_ulid: &str,
+    from_key: &Key<Aes256Gcm>,
+    to_key: &Key<Aes256Gcm>,
+    to_compartment: Option<i64>,
+) -> Result<()> {
+    for table in ["passwords", "password_history"] {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT t.id, t.encrypted_value, t.nonce
+            FROM {} t
+            JOIN identifiers i ON t.identifier_ulid = i.ulid
+            WHERE i.account_ulid = ?
+            "#,
+            table
+        ))
+        .bind(account_ulid)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        for row in rows {
+            let (encrypted_value, nonce) = reencrypt(&row, from_key, to_key)?;
+            sqlx::query(&format!(
+                "UPDATE {} SET encrypted_value = ?, nonce = ? WHERE id = ?",
+                table
+            ))
+            .bind(encrypted_value)
+            .bind(nonce)
+            .bind(row.try_get::<i64, _>("id")?)
+            .execute(&mut **tx)
+            .await?;
+        }
+    }
+
+    let revision_rows = sqlx::query(
+        r#"
+        SELECT id, encrypted_value, nonce
+        FROM account_revisions
+        WHERE account_ulid = ? AND encrypted_value IS NOT NULL
+        "#,
+    )
+    .bind(account_ulid)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    for row in revision_rows {
+        let (encrypted_value, nonce) = reencrypt(&row, from_key, to_key)?;
+        sqlx::query(
+            r#"
+            UPDATE account_revisions
+            SET encrypted_value = ?, nonce = ?, compartment_id = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(encrypted_value)
+        .bind(nonce)
+        .bind(to_compartment)
+        .bind(row.try_get::<i64, _>("id")?)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    if to_compartment.is_some() {
+        for table in ["sync_bases", "sync_conflicts"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE account_ulid = ?", table))
+                .bind(account_ulid)
+                .execute(&mut **tx)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn reencrypt(
+    row: &sqlx::sqlite::SqliteRow,
+    from_key: &Key<Aes256Gcm>,
+    to_key: &Key<Aes256Gcm>,
+) -> Result<(String, String)> {
+    let value = decrypt_password(
+        from_key,
+        &row.try_get::<String, _>("encrypted_value")?,
+        &row.try_get::<String, _>("nonce")?,
+    )?;
+
+    encrypt_password(to_key, &value)
+}
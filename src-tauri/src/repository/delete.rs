@@ -1,32 +1,75 @@
-use anyhow::Result;
+use crate::error::{AppError, Result};
+use crate::repository::revision::{record_revision, RevisionOperation};
 use sqlx::{Sqlite, SqlitePool, Transaction};
+use tracing::instrument;
 
+// アカウントをゴミ箱に移動（完全な削除はtrashモジュールで行う）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
 pub async fn delete_account(sqlite_pool: &SqlitePool, account_ulid: &str) -> Result<()> {
     let mut tx = sqlite_pool.begin().await?;
 
+    // ゴミ箱に移したアカウントがない場合は変更履歴を記録しない
+    if delete_account_entry(&mut tx, account_ulid).await? == 0 {
+        return Err(AppError::NotFound("Account".to_string()));
+    }
+    record_revision(&mut tx, account_ulid, RevisionOperation::Delete).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// ゴミ箱に移した件数を返す（存在しないかすでにゴミ箱にある場合は0）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn delete_account_entry(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_ulid: &str,
+) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        UPDATE accounts
+        SET deleted_at = CURRENT_TIMESTAMP
+        WHERE ulid = ? AND deleted_at IS NULL
+        "#,
+    )
+    .bind(account_ulid)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+// アカウントを関連する識別子・パスワードごと完全に削除
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn purge_account_entry(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_ulid: &str,
+) -> Result<()> {
     sqlx::query(
         r#"
         DELETE FROM accounts WHERE ulid = ?
         "#,
     )
     .bind(account_ulid)
-    .execute(&mut *tx)
+    .execute(&mut **tx)
     .await?;
 
-    delete_unused_category(&mut tx).await?;
-
-    tx.commit().await?;
+    delete_unused_category(tx).await?;
 
     Ok(())
 }
 
-async fn delete_unused_category(tx: &mut Transaction<'_, Sqlite>) -> Result<()> {
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn delete_unused_category(tx: &mut Transaction<'_, Sqlite>) -> Result<()> {
     sqlx::query(
         r#"
         DELETE FROM categories
         WHERE id NOT IN (
             SELECT DISTINCT category_id FROM account_categories
         )
+        AND id NOT IN (
+            SELECT category_id FROM compartments
+        )
         "#,
     )
     .execute(&mut **tx)
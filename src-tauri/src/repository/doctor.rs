@@ -0,0 +1,289 @@
+use crate::error::Result;
+use sqlx::{Row, Sqlite, SqliteConnection, SqlitePool, Transaction};
+use tracing::instrument;
+use ulid::Ulid;
+
+pub struct ForeignKeyViolation {
+    pub table: String,
+    pub rowid: Option<i64>,
+    pub parent: String,
+}
+
+pub struct PasswordCheckRow {
+    pub id: i64,
+    pub account_ulid: Option<String>,
+    pub encrypted_value: String,
+    pub nonce: String,
+    pub compartment_id: Option<i64>,
+}
+
+// 問題がない場合は空（integrity_check は問題がないと "ok" の1行だけを返す）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn integrity_check(sqlite_pool: &SqlitePool) -> Result<Vec<String>> {
+    let messages: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(sqlite_pool)
+        .await?;
+
+    Ok(messages
+        .into_iter()
+        .filter(|message| message != "ok")
+        .collect())
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn foreign_key_check(conn: &mut SqliteConnection) -> Result<Vec<ForeignKeyViolation>> {
+    let rows = sqlx::query("PRAGMA foreign_key_check")
+        .fetch_all(conn)
+        .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(ForeignKeyViolation {
+                table: row.try_get("table")?,
+                rowid: row.try_get("rowid")?,
+                parent: row.try_get("parent")?,
+            })
+        })
+        .collect()
+}
+
+// 参照先のアカウントがない識別子に、同じULIDのアカウントを作成する（識別子とパスワードは残す）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn recover_identifier_account(
+    tx: &mut Transaction<'_, Sqlite>,
+    rowid: i64,
+    account_name: &str,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO accounts (ulid, account_name)
+        SELECT account_ulid, ? FROM identifiers WHERE rowid = ?
+        "#,
+    )
+    .bind(account_name)
+    .bind(rowid)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+// 参照先の識別子がないパスワードやパスワード履歴に、同じULIDの識別子を新しいアカウントに作成する
+// （テーブル名は点検で見つかったものに限る）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn recover_password_identifier(
+    tx: &mut Transaction<'_, Sqlite>,
+    table: &str,
+    rowid: i64,
+    account_name: &str,
+) -> Result<()> {
+    let identifier_ulid: Option<String> = sqlx::query_scalar(&format!(
+        "SELECT identifier_ulid FROM \"{}\" WHERE rowid = ?",
+        table
+    ))
+    .bind(rowid)
+    .fetch_optional(&mut **tx)
+    .await?;
+    let Some(identifier_ulid) = identifier_ulid else {
+        return Ok(());
+    };
+
+    // 同じ識別子を参照する行が複数ある場合は、最初の行で作成した識別子を使う
+    let exists: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM identifiers WHERE ulid = ?)")
+            .bind(&identifier_ulid)
+            .fetch_one(&mut **tx)
+            .await?;
+    if exists {
+        return Ok(());
+    }
+
+    let account_ulid = Ulid::new().to_string();
+    sqlx::query("INSERT INTO accounts (ulid, account_name) VALUES (?, ?)")
+        .bind(&account_ulid)
+        .bind(account_name)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("INSERT INTO identifiers (ulid, account_ulid, identifier) VALUES (?, ?, ?)")
+        .bind(&identifier_ulid)
+        .bind(&account_ulid)
+        .bind(account_name)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+// 参照先がない関連付けを削除（関連付けには秘密の情報が含まれないため削除してよい）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn delete_category_link(tx: &mut Transaction<'_, Sqlite>, rowid: i64) -> Result<()> {
+    sqlx::query("DELETE FROM account_categories WHERE rowid = ?")
+        .bind(rowid)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+// すべてのパスワードと、暗号化に使われたコンパートメント（保管庫のキーの場合は NULL）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_password_check_rows(sqlite_pool: &SqlitePool) -> Result<Vec<PasswordCheckRow>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            p.id,
+            i.account_ulid,
+            p.encrypted_value,
+            p.nonce,
+            (
+                SELECT MIN(cp.category_id)
+                FROM account_categories ac
+                JOIN compartments cp ON ac.category_id = cp.category_id
+                WHERE ac.account_ulid = i.account_ulid
+            ) AS compartment_id
+        FROM passwords p
+        LEFT JOIN identifiers i ON p.identifier_ulid = i.ulid
+        ORDER BY p.id
+        "#,
+    )
+    .fetch_all(sqlite_pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(PasswordCheckRow {
+                id: row.try_get("id")?,
+                account_ulid: row.try_get("account_ulid")?,
+                encrypted_value: row.try_get("encrypted_value")?,
+                nonce: row.try_get("nonce")?,
+                compartment_id: row.try_get("compartment_id")?,
+            })
+        })
+        .collect()
+}
+
+// 識別子のないアカウント（ULIDとアカウント名、スナップショットのある変更履歴があるか、ゴミ箱のアカウントも含む）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn find_accounts_without_identifier(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<(String, String, bool)>> {
+    Ok(sqlx::query_as(
+        r#"
+        SELECT
+            a.ulid,
+            a.account_name,
+            EXISTS (
+                SELECT 1 FROM account_revisions r
+                WHERE r.account_ulid = a.ulid AND r.encrypted_value IS NOT NULL AND r.undone = 0
+            ) AS has_snapshot
+        FROM accounts a
+        WHERE a.ulid NOT IN (SELECT account_ulid FROM identifiers)
+        ORDER BY a.id
+        "#,
+    )
+    .fetch_all(conn)
+    .await?)
+}
+
+// カテゴリのないアカウント（ULIDとアカウント名、ゴミ箱のアカウントも含む）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn find_accounts_without_category(
+    sqlite_pool: &SqlitePool,
+) -> Result<Vec<(String, String)>> {
+    Ok(sqlx::query_as(
+        r#"
+        SELECT ulid, account_name FROM accounts
+        WHERE ulid NOT IN (
+            SELECT ac.account_ulid
+            FROM account_categories ac
+            JOIN categories c ON ac.category_id = c.id
+        )
+        ORDER BY id
+        "#,
+    )
+    .fetch_all(sqlite_pool)
+    .await?)
+}
+
+// 複数のカテゴリに関連付けられたアカウント（ULIDと関連付けの数）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn find_duplicate_category_links(sqlite_pool: &SqlitePool) -> Result<Vec<(String, i64)>> {
+    Ok(sqlx::query_as(
+        r#"
+        SELECT account_ulid, COUNT(*) AS link_count
+        FROM account_categories
+        GROUP BY account_ulid
+        HAVING COUNT(*) > 1
+        ORDER BY account_ulid
+        "#,
+    )
+    .fetch_all(sqlite_pool)
+    .await?)
+}
+
+// どのアカウントにも使われていないカテゴリ（コンパートメントは空でも残す）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn find_orphan_categories(sqlite_pool: &SqlitePool) -> Result<Vec<String>> {
+    Ok(sqlx::query_scalar(
+        r#"
+        SELECT category_name FROM categories
+        WHERE id NOT IN (SELECT category_id FROM account_categories)
+        AND id NOT IN (SELECT category_id FROM compartments)
+        ORDER BY category_name
+        "#,
+    )
+    .fetch_all(sqlite_pool)
+    .await?)
+}
+
+// アカウントごとに関連付けを1つだけ残す
+// （コンパートメントのキーで暗号化されている可能性があるため、コンパートメントへの関連付けを優先する）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn remove_duplicate_category_links(tx: &mut Transaction<'_, Sqlite>) -> Result<()> {
+    sqlx::query(
+        r#"
+        DELETE FROM account_categories
+        WHERE id NOT IN (
+            SELECT (
+                SELECT ac.id
+                FROM account_categories ac
+                LEFT JOIN compartments cp ON ac.category_id = cp.category_id
+                WHERE ac.account_ulid = links.account_ulid
+                ORDER BY cp.category_id IS NULL, ac.id
+                LIMIT 1
+            )
+            FROM (SELECT DISTINCT account_ulid FROM account_categories) links
+        )
+        "#,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+// カテゴリのないアカウントを指定したカテゴリに関連付ける（カテゴリがなければ作成する）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn link_accounts_without_category(
+    tx: &mut Transaction<'_, Sqlite>,
+    category_name: &str,
+) -> Result<()> {
+    sqlx::query("INSERT OR IGNORE INTO categories (category_name) VALUES (?)")
+        .bind(category_name)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO account_categories (account_ulid, category_id)
+        SELECT a.ulid, c.id
+        FROM accounts a, categories c
+        WHERE c.category_name = ?
+        AND a.ulid NOT IN (SELECT account_ulid FROM account_categories)
+        "#,
+    )
+    .bind(category_name)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use crate::error::Result;
+use crate::models::KeyEscrowInfo;
+use sqlx::{Row, Sqlite, SqlitePool, Transaction};
+use tracing::instrument;
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn list_key_escrows(sqlite_pool: &SqlitePool) -> Result<Vec<KeyEscrowInfo>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT recipient, key_fingerprint, created_at
+        FROM key_escrows
+        ORDER BY created_at, recipient
+        "#,
+    )
+    .fetch_all(sqlite_pool)
+    .await?;
+
+    let mut escrows = Vec::new();
+    for row in rows {
+        escrows.push(KeyEscrowInfo {
+            recipient: row.try_get("recipient")?,
+            key_fingerprint: row.try_get("key_fingerprint")?,
+            created_at: row.try_get("created_at")?,
+        });
+    }
+
+    Ok(escrows)
+}
+
+// 暗号化したキーと、復号後の照合に使うキーの識別子を取得（管理者による復旧で使う）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn list_wrapped_keys(sqlite_pool: &SqlitePool) -> Result<Vec<(String, String)>> {
+    let rows = sqlx::query("SELECT wrapped_key, key_fingerprint FROM key_escrows")
+        .fetch_all(sqlite_pool)
+        .await?;
+
+    let mut wrapped_keys = Vec::new();
+    for row in rows {
+        wrapped_keys.push((row.try_get("wrapped_key")?, row.try_get("key_fingerprint")?));
+    }
+
+    Ok(wrapped_keys)
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn delete_key_escrows(tx: &mut Transaction<'_, Sqlite>) -> Result<()> {
+    sqlx::query("DELETE FROM key_escrows")
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+// 登録済みの公開鍵の場合は何もしない
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn insert_key_escrow(
+    tx: &mut Transaction<'_, Sqlite>,
+    recipient: &str,
+    wrapped_key: &str,
+    key_fingerprint: &str,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO key_escrows (recipient, wrapped_key, key_fingerprint)
+        VALUES (?, ?, ?)
+        "#,
+    )
+    .bind(recipient)
+    .bind(wrapped_key)
+    .bind(key_fingerprint)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
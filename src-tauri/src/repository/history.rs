@@ -0,0 +1,432 @@
+use crate::compartment::{identifier_key, is_hidden};
+use crate::crypto::decrypt_password;
+use crate::error::{AppError, Result};
+use crate::models::PasswordHistory;
+use crate::repository::revision::{record_revision, RevisionOperation};
+use crate::settings::read_vault_settings;
+use sqlx::{Row, Sqlite, SqlitePool, Transaction};
+use tracing::instrument;
+
+// 上書き・削除される前のパスワードを履歴に退避
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn archive_password(tx: &mut Transaction<'_, Sqlite>, password_id: u32) -> Result<()> {
+    let archived = sqlx::query(
+        r#"
+        INSERT INTO password_history (password_id, identifier_ulid, encrypted_value, nonce, set_at)
+        SELECT id, identifier_ulid, encrypted_value, nonce, updated_at
+        FROM passwords
+        WHERE id = ?
+        RETURNING identifier_ulid
+        "#,
+    )
+    .bind(password_id)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    if let Some(row) = archived {
+        let identifier_ulid: String = row.try_get("identifier_ulid")?;
+        // 保持件数は保管庫の設定（0は無制限）
+        let limit = read_vault_settings(tx).await?.password_history_limit;
+        prune_password_history(tx, &identifier_ulid, limit).await?;
+    }
+
+    Ok(())
+}
+
+// 保持件数を超えた古い履歴を削除（0は無制限）
+async fn prune_password_history(
+    tx: &mut Transaction<'_, Sqlite>,
+    identifier_ulid: &str,
+    limit: u32,
+) -> Result<()> {
+    if limit == 0 {
+        return Ok(());
+    }
+
+    sqlx::query(
+        r#"
+        DELETE FROM password_history
+        WHERE identifier_ulid = ?
+        AND id NOT IN (
+            SELECT id FROM password_history
+            WHERE identifier_ulid = ?
+            ORDER BY id DESC
+            LIMIT ?
+        )
+        "#,
+    )
+    .bind(identifier_ulid)
+    .bind(identifier_ulid)
+    .bind(limit)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_password_history(
+    sqlite_pool: &SqlitePool,
+    identifier_ulid: String,
+) -> Result<Vec<PasswordHistory>> {
+    let history_rows = sqlx::query(
+        r#"
+        SELECT
+            h.id,
+            h.password_id,
+            h.set_at,
+            h.replaced_at,
+            cp.category_id AS compartment_id
+        FROM
+            password_history h
+        LEFT JOIN
+            identifiers i ON h.identifier_ulid = i.ulid
+        LEFT JOIN
+            account_categories ac ON i.account_ulid = ac.account_ulid
+        LEFT JOIN
+            compartments cp ON ac.category_id = cp.category_id
+        WHERE
+            h.identifier_ulid = ?
+        ORDER BY
+            h.id DESC;
+        "#,
+    )
+    .bind(&identifier_ulid)
+    .fetch_all(sqlite_pool)
+    .await?;
+
+    let mut history_vec = Vec::new();
+
+    for row in history_rows {
+        // ロック中のコンパートメントのアカウントの履歴は表示しない
+        if is_hidden(row.try_get("compartment_id")?) {
+            continue;
+        }
+
+        let id: u32 = row.try_get("id")?;
+        let password_id: u32 = row.try_get("password_id")?;
+        let set_at: Option<String> = row.try_get("set_at")?;
+        let replaced_at: String = row.try_get("replaced_at")?;
+
+        history_vec.push(PasswordHistory {
+            id,
+            password_id,
+            set_at,
+            replaced_at,
+        });
+    }
+
+    Ok(history_vec)
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn reveal_password_history(sqlite_pool: &SqlitePool, history_id: u32) -> Result<String> {
+    let row = sqlx::query(
+        r#"
+        SELECT identifier_ulid, encrypted_value, nonce
+        FROM password_history
+        WHERE id = ?
+        "#,
+    )
+    .bind(history_id)
+    .fetch_optional(sqlite_pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Password history".to_string()))?;
+
+    let identifier_ulid: String = row.try_get("identifier_ulid")?;
+    let encrypted_value: String = row.try_get("encrypted_value")?;
+    let nonce: String = row.try_get("nonce")?;
+
+    let key = identifier_key(&mut *sqlite_pool.acquire().await?, &identifier_ulid).await?;
+    decrypt_password(&key, &encrypted_value, &nonce)
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_password_history_account_ulid(
+    sqlite_pool: &SqlitePool,
+    history_id: u32,
+) -> Result<Option<String>> {
+    let account_ulid = sqlx::query_scalar(
+        r#"
+        SELECT i.account_ulid
+        FROM password_history h
+        JOIN identifiers i ON h.identifier_ulid = i.ulid
+        WHERE h.id = ?
+        "#,
+    )
+    .bind(history_id)
+    .fetch_optional(sqlite_pool)
+    .await?;
+
+    Ok(account_ulid)
+}
+
+// 履歴のパスワードを元のパスワードに戻す（元のパスワードが削除済みの場合は新規に追加）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn restore_password_history(sqlite_pool: &SqlitePool, history_id: u32) -> Result<String> {
+    let mut tx = sqlite_pool.begin().await?;
+
+    let history = sqlx::query(
+        r#"
+        SELECT password_id, identifier_ulid, encrypted_value, nonce
+        FROM password_history
+        WHERE id = ?
+        "#,
+    )
+    .bind(history_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Password history".to_string()))?;
+
+    let password_id: u32 = history.try_get("password_id")?;
+    let identifier_ulid: String = history.try_get("identifier_ulid")?;
+    let encrypted_value: String = history.try_get("encrypted_value")?;
+    let nonce: String = history.try_get("nonce")?;
+
+    let password_exists = sqlx::query(
+        r#"
+        SELECT id FROM passwords WHERE id = ? AND identifier_ulid = ?
+        "#,
+    )
+    .bind(password_id)
+    .bind(&identifier_ulid)
+    .fetch_optional(&mut *tx)
+    .await?
+    .is_some();
+
+    if password_exists {
+        // 現在のパスワードも履歴に残してから置き換える
+        archive_password(&mut tx, password_id).await?;
+        sqlx::query(
+            r#"
+            UPDATE passwords
+            SET encrypted_value = ?, nonce = ?, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+        )
+        .bind(&encrypted_value)
+        .bind(&nonce)
+        .bind(password_id)
+        .execute(&mut *tx)
+        .await?;
+    } else {
+        sqlx::query(
+            r#"
+            INSERT INTO passwords (identifier_ulid, encrypted_value, nonce)
+            VALUES (?, ?, ?)
+            "#,
+        )
+        .bind(&identifier_ulid)
+        .bind(&encrypted_value)
+        .bind(&nonce)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let account_ulid: String = sqlx::query(
+        r#"
+        SELECT account_ulid FROM identifiers WHERE ulid = ?
+        "#,
+    )
+    .bind(&identifier_ulid)
+    .fetch_one(&mut *tx)
+    .await?
+    .try_get("account_ulid")?;
+    record_revision(&mut tx, &account_ulid, RevisionOperation::Update).await?;
+
+    tx.commit().await?;
+
+    Ok(account_ulid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_pool;
+    use crate::models::VaultSettings;
+
+    const ACCOUNT_ULID: &str = "01JN0000000000000000000000";
+    const IDENTIFIER_ULID: &str = "01JN0000000000000000000001";
+
+    async fn insert_password(sqlite_pool: &SqlitePool) -> u32 {
+        sqlx::query(
+            r#"
+            INSERT INTO accounts (ulid, account_name) VALUES (?, 'Mail');
+            INSERT INTO identifiers (ulid, account_ulid, identifier) VALUES (?, ?, 'user');
+            "#,
+        )
+        .bind(ACCOUNT_ULID)
+        .bind(IDENTIFIER_ULID)
+        .bind(ACCOUNT_ULID)
+        .execute(sqlite_pool)
+        .await
+        .unwrap();
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO passwords (identifier_ulid, encrypted_value, nonce)
+            VALUES (?, 'v0', 'n')
+            RETURNING id
+            "#,
+        )
+        .bind(IDENTIFIER_ULID)
+        .fetch_one(sqlite_pool)
+        .await
+        .unwrap()
+    }
+
+    // 保持件数を保管庫の設定に保存する
+    async fn save_history_limit(sqlite_pool: &SqlitePool, limit: u32) {
+        crate::repository::settings::save_settings(
+            sqlite_pool,
+            &[("passwordHistoryLimit".to_string(), limit.to_string())],
+        )
+        .await
+        .unwrap();
+    }
+
+    // パスワードを変更したように、現在の値を履歴に退避してから値を更新する
+    async fn change_password(sqlite_pool: &SqlitePool, password_id: u32, value: &str) {
+        let mut tx = sqlite_pool.begin().await.unwrap();
+        archive_password(&mut tx, password_id).await.unwrap();
+        sqlx::query("UPDATE passwords SET encrypted_value = ? WHERE id = ?")
+            .bind(value)
+            .bind(password_id)
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    async fn history_values(sqlite_pool: &SqlitePool) -> Vec<String> {
+        sqlx::query_scalar("SELECT encrypted_value FROM password_history ORDER BY id DESC")
+            .fetch_all(sqlite_pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_password_history_is_pruned_to_limit() {
+        let sqlite_pool = test_pool().await;
+        let password_id = insert_password(&sqlite_pool).await;
+        save_history_limit(&sqlite_pool, 3).await;
+
+        for i in 1..=5 {
+            change_password(&sqlite_pool, password_id, &format!("v{}", i)).await;
+        }
+
+        // 保持件数を超えた分は古いものから削除され、新しい履歴が残ることを確認
+        assert_eq!(history_values(&sqlite_pool).await, ["v4", "v3", "v2"]);
+    }
+
+    #[tokio::test]
+    async fn test_password_history_is_kept_without_limit() {
+        let sqlite_pool = test_pool().await;
+        let password_id = insert_password(&sqlite_pool).await;
+        save_history_limit(&sqlite_pool, 0).await;
+
+        for i in 1..=5 {
+            change_password(&sqlite_pool, password_id, &format!("v{}", i)).await;
+        }
+
+        assert_eq!(history_values(&sqlite_pool).await.len(), 5);
+        // 退避した時点の値の設定日時も履歴に残ることを確認
+        let set_at_missing: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM password_history WHERE set_at IS NULL")
+                .fetch_one(&sqlite_pool)
+                .await
+                .unwrap();
+        assert_eq!(set_at_missing, 0);
+    }
+
+    #[tokio::test]
+    async fn test_archive_password_applies_default_limit() {
+        let sqlite_pool = test_pool().await;
+        let password_id = insert_password(&sqlite_pool).await;
+
+        let limit = VaultSettings::default().password_history_limit;
+        for _ in 0..limit + 2 {
+            let mut tx = sqlite_pool.begin().await.unwrap();
+            archive_password(&mut tx, password_id).await.unwrap();
+            tx.commit().await.unwrap();
+        }
+
+        assert_eq!(history_values(&sqlite_pool).await.len(), limit as usize);
+    }
+
+    #[tokio::test]
+    async fn test_password_history_follows_replaced_identifier() {
+        const NEW_IDENTIFIER_ULID: &str = "01JN0000000000000000000002";
+        let sqlite_pool = test_pool().await;
+        let password_id = insert_password(&sqlite_pool).await;
+        change_password(&sqlite_pool, password_id, "v1").await;
+
+        let mut tx = sqlite_pool.begin().await.unwrap();
+        crate::repository::update::replace_identifier_ulid(
+            &mut tx,
+            IDENTIFIER_ULID,
+            NEW_IDENTIFIER_ULID,
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        // 識別子のULIDを付け替えても、パスワードと履歴は新しい識別子に残ることを確認
+        assert_eq!(
+            get_password_history(&sqlite_pool, NEW_IDENTIFIER_ULID.to_string())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        let passwords: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM passwords WHERE identifier_ulid = ?")
+                .bind(NEW_IDENTIFIER_ULID)
+                .fetch_one(&sqlite_pool)
+                .await
+                .unwrap();
+        assert_eq!(passwords, 1);
+        let old_identifiers: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM identifiers WHERE ulid = ?")
+                .bind(IDENTIFIER_ULID)
+                .fetch_one(&sqlite_pool)
+                .await
+                .unwrap();
+        assert_eq!(old_identifiers, 0);
+    }
+
+    #[tokio::test]
+    async fn test_password_history_of_locked_compartment_is_hidden() {
+        let sqlite_pool = test_pool().await;
+        let password_id = insert_password(&sqlite_pool).await;
+        change_password(&sqlite_pool, password_id, "v1").await;
+        assert_eq!(
+            get_password_history(&sqlite_pool, IDENTIFIER_ULID.to_string())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        sqlx::query(
+            r#"
+            INSERT INTO categories (category_name) VALUES ('Private');
+            INSERT INTO compartments (category_id, salt, key_check, relock_minutes)
+            SELECT id, '00', '00:00', 5 FROM categories WHERE category_name = 'Private';
+            INSERT INTO account_categories (account_ulid, category_id)
+            SELECT ?, id FROM categories WHERE category_name = 'Private';
+            "#,
+        )
+        .bind(ACCOUNT_ULID)
+        .execute(&sqlite_pool)
+        .await
+        .unwrap();
+
+        // ロック中のコンパートメントのアカウントの履歴は一覧に含まれないことを確認
+        assert!(
+            get_password_history(&sqlite_pool, IDENTIFIER_ULID.to_string())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
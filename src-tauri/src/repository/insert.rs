@@ -1,29 +1,68 @@
+use crate::compartment::account_key;
 use crate::crypto;
+use crate::error::Result;
 use crate::models::FormData;
+use crate::repository::revision::{record_revision, RevisionOperation};
+use crate::validation::validate_form_data;
 use aes_gcm::{Aes256Gcm, Key};
-use anyhow::Result;
 use sqlx::{Sqlite, SqlitePool, Transaction};
+use tracing::instrument;
 use ulid::Ulid;
 
-pub async fn insert_new_account(sqlite_pool: &SqlitePool, form_data: FormData) -> Result<()> {
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn insert_new_account(sqlite_pool: &SqlitePool, form_data: FormData) -> Result<String> {
+    let form_data = validate_form_data(form_data)?;
+
     let mut tx = sqlite_pool.begin().await?;
+    let account_ulid = insert_new_account_entry(&mut tx, &form_data).await?;
+    tx.commit().await?;
+
+    Ok(account_ulid)
+}
 
+// 新しいULIDでアカウント一式を登録し、変更履歴に記録（入力値はチェック済みのもの）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn insert_new_account_entry(
+    tx: &mut Transaction<'_, Sqlite>,
+    form_data: &FormData,
+) -> Result<String> {
     let account_ulid = Ulid::new().to_string();
     let identifier_ulid = Ulid::new().to_string();
 
-    insert_account(&mut tx, &account_ulid, &form_data.account_name).await?;
-    insert_identifier(
-        &mut tx,
-        &account_ulid,
-        &identifier_ulid,
-        &form_data.identifier,
-    )
-    .await?;
-    insert_category(&mut tx, &form_data.category_name).await?;
-    insert_account_categories(&mut tx, &account_ulid, &form_data.category_name).await?;
-    insert_passwords(&mut tx, &identifier_ulid, &form_data.passwords).await?;
+    insert_account_entry(tx, &account_ulid, &identifier_ulid, form_data).await?;
+    record_revision(tx, &account_ulid, RevisionOperation::Insert).await?;
 
-    tx.commit().await?;
+    Ok(account_ulid)
+}
+
+// 指定したULIDでアカウント一式を登録（変更履歴からの復元でも使用）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn insert_account_entry(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_ulid: &str,
+    identifier_ulid: &str,
+    form_data: &FormData,
+) -> Result<()> {
+    insert_account(tx, account_ulid, &form_data.account_name).await?;
+    insert_account_details(tx, account_ulid, identifier_ulid, form_data).await?;
+
+    Ok(())
+}
+
+// 既存のアカウントに識別子・カテゴリ・パスワードを登録（点検でのアカウントの作り直しでも使用）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn insert_account_details(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_ulid: &str,
+    identifier_ulid: &str,
+    form_data: &FormData,
+) -> Result<()> {
+    insert_identifier(tx, account_ulid, identifier_ulid, &form_data.identifier).await?;
+    insert_category(tx, &form_data.category_name).await?;
+    insert_account_categories(tx, account_ulid, &form_data.category_name).await?;
+    // コンパートメントのカテゴリの場合はコンパートメントのキーで暗号化する
+    let key = account_key(tx, account_ulid).await?;
+    insert_passwords(tx, &key, identifier_ulid, &form_data.passwords).await?;
 
     Ok(())
 }
@@ -67,6 +106,7 @@ async fn insert_identifier(
     Ok(())
 }
 
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
 pub async fn insert_category(tx: &mut Transaction<'_, Sqlite>, category_name: &str) -> Result<()> {
     // カテゴリが存在しない場合は挿入
     sqlx::query(
@@ -104,16 +144,12 @@ async fn insert_account_categories(
 
 async fn insert_passwords(
     tx: &mut Transaction<'_, Sqlite>,
+    key: &Key<Aes256Gcm>,
     identifier_ulid: &str,
     passwords: &Vec<String>,
 ) -> Result<()> {
-    let key: Key<Aes256Gcm> = crypto::get_encryption_key();
-
     for password in passwords {
-        let (encrypted_value, nonce) = match crypto::encrypt_password(&key, password) {
-            Ok((ct, n)) => (ct, n),
-            Err(_) => return Err(anyhow::anyhow!("Encryption failed")),
-        };
+        let (encrypted_value, nonce) = crypto::encrypt_password(key, password)?;
 
         sqlx::query(
             r#"
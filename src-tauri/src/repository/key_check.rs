@@ -0,0 +1,121 @@
+use crate::crypto::{decrypt_password, encrypt_password, get_encryption_key};
+use crate::error::{AppError, Result};
+use aes_gcm::{Aes256Gcm, Key};
+use sqlx::{Row, SqlitePool};
+use tracing::instrument;
+
+const KEY_CHECK_NAME: &str = "key_check";
+const KEY_CHECK_VALUE: &str = "jasmify-key-check";
+
+async fn get_key_check(sqlite_pool: &SqlitePool) -> Result<Option<String>> {
+    Ok(
+        sqlx::query_scalar("SELECT value FROM vault_meta WHERE name = ?")
+            .bind(KEY_CHECK_NAME)
+            .fetch_optional(sqlite_pool)
+            .await?,
+    )
+}
+
+// キーが保管庫と一致するか確認する（DBには書き込まないため、読み取り専用で開いたDBにも使える）
+// 照合用の値がない既存の保管庫は、登録済みのパスワードのいずれかが復号できるかで判断する
+// （違うキーで認証を通ることはないため、壊れた行があってもキーの違いと誤認しない）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn key_matches(sqlite_pool: &SqlitePool, key: &Key<Aes256Gcm>) -> Result<bool> {
+    if let Some(key_check) = get_key_check(sqlite_pool).await? {
+        let (encrypted_value, nonce) = key_check
+            .split_once(':')
+            .ok_or_else(|| AppError::Internal("Invalid key check value".to_string()))?;
+        return Ok(matches!(
+            decrypt_password(key, encrypted_value, nonce),
+            Ok(value) if value == KEY_CHECK_VALUE
+        ));
+    }
+
+    let passwords = sqlx::query("SELECT encrypted_value, nonce FROM passwords")
+        .fetch_all(sqlite_pool)
+        .await?;
+    if passwords.is_empty() {
+        return Ok(true);
+    }
+    for row in passwords {
+        let encrypted_value: String = row.try_get("encrypted_value")?;
+        let nonce: String = row.try_get("nonce")?;
+        if decrypt_password(key, &encrypted_value, &nonce).is_ok() {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+// 暗号化キーが保管庫と一致するか照合する（DBには書き込まない）
+// （復号に失敗した原因がキーの違いかデータの破損かを区別するために使う）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn verify_encryption_key(sqlite_pool: &SqlitePool) -> Result<()> {
+    let key = get_encryption_key()?;
+
+    if key_matches(sqlite_pool, &key).await? {
+        Ok(())
+    } else {
+        Err(AppError::WrongKey)
+    }
+}
+
+// 照合用の値がない保管庫に、一致を確認したキーで照合用の値を作成する（保管庫を書き込み可能で開く際に使う）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn create_key_check_if_missing(sqlite_pool: &SqlitePool) -> Result<()> {
+    if get_key_check(sqlite_pool).await?.is_some() {
+        return Ok(());
+    }
+    verify_encryption_key(sqlite_pool).await?;
+
+    let key = get_encryption_key()?;
+    let (encrypted_value, nonce) = encrypt_password(&key, KEY_CHECK_VALUE)?;
+    sqlx::query("INSERT OR IGNORE INTO vault_meta (name, value) VALUES (?, ?)")
+        .bind(KEY_CHECK_NAME)
+        .bind(format!("{}:{}", encrypted_value, nonce))
+        .execute(sqlite_pool)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_pool;
+
+    #[tokio::test]
+    async fn test_key_matches_without_key_check() {
+        let sqlite_pool = test_pool().await;
+        let key = *Key::<Aes256Gcm>::from_slice(&[7u8; 32]);
+        let other_key = *Key::<Aes256Gcm>::from_slice(&[8u8; 32]);
+
+        // パスワードがない保管庫はどのキーでも一致とする
+        assert!(key_matches(&sqlite_pool, &key).await.unwrap());
+
+        // 最初の行が壊れていても、復号できる行があれば一致とする
+        let (encrypted_value, nonce) = encrypt_password(&key, "secret").unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO accounts (ulid, account_name) VALUES ('01JN0000000000000000000000', 'Mail');
+            INSERT INTO identifiers (ulid, account_ulid, identifier)
+            VALUES ('01JN0000000000000000000001', '01JN0000000000000000000000', 'user');
+            INSERT INTO passwords (identifier_ulid, encrypted_value, nonce)
+            VALUES ('01JN0000000000000000000001', 'broken', 'broken');
+            INSERT INTO passwords (identifier_ulid, encrypted_value, nonce)
+            VALUES ('01JN0000000000000000000001', ?, ?);
+            "#,
+        )
+        .bind(&encrypted_value)
+        .bind(&nonce)
+        .execute(&sqlite_pool)
+        .await
+        .unwrap();
+        assert!(key_matches(&sqlite_pool, &key).await.unwrap());
+        assert!(!key_matches(&sqlite_pool, &other_key).await.unwrap());
+
+        // 照合しても照合用の値は作成されないことを確認
+        assert_eq!(get_key_check(&sqlite_pool).await.unwrap(), None);
+    }
+}
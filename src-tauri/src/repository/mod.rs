@@ -1,4 +1,16 @@
+pub mod audit;
+pub mod compartment;
 pub mod delete;
+pub mod doctor;
+pub mod escrow;
+pub mod history;
 pub mod insert;
+pub mod key_check;
 pub mod read;
+pub mod revision;
+pub mod settings;
+pub mod share;
+pub mod sync;
+pub mod trash;
+pub mod travel;
 pub mod update;
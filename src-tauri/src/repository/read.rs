@@ -1,8 +1,11 @@
-use crate::crypto::{decrypt_password, get_encryption_key};
-use crate::models::{AccountSummary, PasswordInfo, SearchCriteria};
-use anyhow::Result;
-use sqlx::{Row, SqlitePool};
+use crate::compartment::{account_key, identifier_key, is_hidden};
+use crate::crypto::{decrypt_password, run_crypto};
+use crate::error::Result;
+use crate::models::{AccountInfo, AccountSummary, PasswordInfo, SearchCriteria};
+use sqlx::{Row, Sqlite, SqlitePool, Transaction};
+use tracing::instrument;
 
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
 pub async fn get_account_summary(sqlite_pool: &SqlitePool) -> Result<Vec<AccountSummary>> {
     let accounts_rows = sqlx::query(
         r#"
@@ -11,7 +14,9 @@ pub async fn get_account_summary(sqlite_pool: &SqlitePool) -> Result<Vec<Account
             a.account_name,
             i.ulid AS identifier_ulid,
             i.identifier,
-            c.category_name
+            c.category_name,
+            a.version,
+            cp.category_id AS compartment_id
         FROM 
             accounts a
         LEFT JOIN 
@@ -19,7 +24,11 @@ pub async fn get_account_summary(sqlite_pool: &SqlitePool) -> Result<Vec<Account
         LEFT JOIN 
             account_categories ac ON a.ulid = ac.account_ulid
         LEFT JOIN 
-            categories c ON ac.category_id = c.id;
+            categories c ON ac.category_id = c.id
+        LEFT JOIN 
+            compartments cp ON ac.category_id = cp.category_id
+        WHERE 
+            a.deleted_at IS NULL;
         "#,
     )
     .fetch_all(sqlite_pool)
@@ -28,11 +37,17 @@ pub async fn get_account_summary(sqlite_pool: &SqlitePool) -> Result<Vec<Account
     let mut account_summary_vec = Vec::new();
 
     for row in accounts_rows {
+        // ロック中のコンパートメントのアカウントは表示しない
+        if is_hidden(row.try_get("compartment_id")?) {
+            continue;
+        }
+
         let account_ulid: String = row.try_get("account_ulid")?;
         let account_name: String = row.try_get("account_name")?;
         let identifier: String = row.try_get("identifier")?;
         let identifier_ulid: String = row.try_get("identifier_ulid")?;
         let category_name: String = row.try_get("category_name")?;
+        let version: u32 = row.try_get("version")?;
 
         let account_summary = AccountSummary {
             account_ulid,
@@ -40,6 +55,7 @@ pub async fn get_account_summary(sqlite_pool: &SqlitePool) -> Result<Vec<Account
             identifier_ulid,
             identifier,
             category_name,
+            version,
         };
 
         account_summary_vec.push(account_summary);
@@ -48,6 +64,7 @@ pub async fn get_account_summary(sqlite_pool: &SqlitePool) -> Result<Vec<Account
     Ok(account_summary_vec)
 }
 
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
 pub async fn get_password_info(
     sqlite_pool: &SqlitePool,
     identifier_ulid: String,
@@ -68,23 +85,134 @@ pub async fn get_password_info(
     .fetch_all(sqlite_pool)
     .await?;
 
-    let mut passwords_vec = Vec::new();
+    let key = identifier_key(&mut *sqlite_pool.acquire().await?, &identifier_ulid).await?;
+
+    run_crypto(move || {
+        let mut passwords_vec = Vec::new();
+
+        for password in passwords_rows {
+            let id: u32 = password.try_get("id")?;
+            let encrypted_value: String = password.try_get("encrypted_value")?;
+            let nonce: String = password.try_get("nonce")?;
+
+            let password_raw = decrypt_password(&key, &encrypted_value, &nonce)?;
+
+            let password_info = PasswordInfo { id, password_raw };
+            passwords_vec.push(password_info);
+        }
+
+        Ok(passwords_vec)
+    })
+    .await
+}
+
+// トランザクション内でアカウント一式を取得（ゴミ箱のアカウントも含む）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_account_info(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_ulid: &str,
+) -> Result<Option<AccountInfo>> {
+    let account_row = sqlx::query(
+        r#"
+        SELECT
+            a.account_name,
+            a.version,
+            i.ulid AS identifier_ulid,
+            i.identifier,
+            c.category_name
+        FROM
+            accounts a
+        LEFT JOIN
+            identifiers i ON a.ulid = i.account_ulid
+        LEFT JOIN
+            account_categories ac ON a.ulid = ac.account_ulid
+        LEFT JOIN
+            categories c ON ac.category_id = c.id
+        WHERE
+            a.ulid = ?
+        "#,
+    )
+    .bind(account_ulid)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    let Some(account_row) = account_row else {
+        return Ok(None);
+    };
+
+    let account_name: String = account_row.try_get("account_name")?;
+    let version: u32 = account_row.try_get("version")?;
+    let identifier_ulid: String = account_row.try_get("identifier_ulid")?;
+    let identifier: String = account_row.try_get("identifier")?;
+    let category_name: String = account_row.try_get("category_name")?;
+
+    let passwords_rows = sqlx::query(
+        r#"
+        SELECT id, encrypted_value, nonce
+        FROM passwords
+        WHERE identifier_ulid = ?
+        ORDER BY id
+        "#,
+    )
+    .bind(&identifier_ulid)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let key = account_key(tx, account_ulid).await?;
+    let mut passwords = Vec::new();
 
     for password in passwords_rows {
         let id: u32 = password.try_get("id")?;
         let encrypted_value: String = password.try_get("encrypted_value")?;
         let nonce: String = password.try_get("nonce")?;
-
-        let key = get_encryption_key();
         let password_raw = decrypt_password(&key, &encrypted_value, &nonce)?;
 
-        let password_info = PasswordInfo { id, password_raw };
-        passwords_vec.push(password_info);
+        passwords.push(PasswordInfo { id, password_raw });
     }
 
-    Ok(passwords_vec)
+    Ok(Some(AccountInfo {
+        account_ulid: account_ulid.to_string(),
+        account_name,
+        identifier_ulid,
+        identifier,
+        passwords,
+        category_name,
+        version,
+    }))
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_account_ulid(
+    sqlite_pool: &SqlitePool,
+    identifier_ulid: &str,
+) -> Result<Option<String>> {
+    let account_ulid = sqlx::query_scalar(
+        r#"
+        SELECT account_ulid FROM identifiers WHERE ulid = ?
+        "#,
+    )
+    .bind(identifier_ulid)
+    .fetch_optional(sqlite_pool)
+    .await?;
+
+    Ok(account_ulid)
+}
+
+// ゴミ箱も含めてアカウントが1件でも登録されているか
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn has_accounts(sqlite_pool: &SqlitePool) -> Result<bool> {
+    let has_accounts = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM accounts)
+        "#,
+    )
+    .fetch_one(sqlite_pool)
+    .await?;
+
+    Ok(has_accounts)
 }
 
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
 pub async fn get_search_results(
     sqlite_pool: &SqlitePool,
     search_criteria: SearchCriteria,
@@ -101,11 +229,16 @@ pub async fn get_search_results(
     let mut search_results = Vec::<AccountSummary>::new();
 
     for row in rows {
+        if is_hidden(row.try_get("compartment_id")?) {
+            continue;
+        }
+
         let account_ulid: String = row.try_get("account_ulid")?;
         let account_name: String = row.try_get("account_name")?;
         let identifier_ulid: String = row.try_get("identifier_ulid")?;
         let identifier: String = row.try_get("identifier")?;
         let category_name: String = row.try_get("category_name")?;
+        let version: u32 = row.try_get("version")?;
 
         let result = AccountSummary {
             account_ulid,
@@ -113,6 +246,7 @@ pub async fn get_search_results(
             identifier_ulid,
             identifier,
             category_name,
+            version,
         };
 
         search_results.push(result);
@@ -128,12 +262,15 @@ fn build_filter_conditions(criteria: &SearchCriteria) -> (String, Vec<String>) {
             accounts.account_name, 
             identifiers.ulid AS identifier_ulid, 
             identifiers.identifier, 
-            categories.category_name
+            categories.category_name,
+            accounts.version,
+            compartments.category_id AS compartment_id
         FROM accounts
         LEFT JOIN identifiers ON accounts.ulid = identifiers.account_ulid
         LEFT JOIN account_categories ON accounts.ulid = account_categories.account_ulid
         LEFT JOIN categories ON account_categories.category_id = categories.id
-        WHERE 1=1",
+        LEFT JOIN compartments ON account_categories.category_id = compartments.category_id
+        WHERE accounts.deleted_at IS NULL",
     );
 
     let mut bindings = Vec::new();
@@ -0,0 +1,402 @@
+use crate::compartment::compartment_key;
+use crate::crypto::{decrypt_password, encrypt_password, run_crypto};
+use crate::error::{AppError, Result};
+use crate::models::{AccountRevision, AccountSnapshot};
+use crate::repository::compartment::get_account_compartment;
+use crate::repository::delete::delete_account_entry;
+use crate::repository::insert::{insert_account_details, insert_account_entry};
+use crate::repository::read::get_account_info;
+use crate::repository::trash::restore_account_entry;
+use crate::repository::update::{replace_identifier_ulid, update_account_entry};
+use sqlx::{Row, Sqlite, SqlitePool, Transaction};
+use std::collections::HashMap;
+use tracing::instrument;
+
+pub enum RevisionOperation {
+    Insert,
+    Update,
+    Delete,
+    Restore,
+    Revert,
+    Sync,
+}
+
+impl RevisionOperation {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RevisionOperation::Insert => "insert",
+            RevisionOperation::Update => "update",
+            RevisionOperation::Delete => "delete",
+            RevisionOperation::Restore => "restore",
+            RevisionOperation::Revert => "revert",
+            RevisionOperation::Sync => "sync",
+        }
+    }
+}
+
+// 変更後のアカウント全体を暗号化して変更履歴に記録（ゴミ箱への移動の場合はスナップショットなし）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn record_revision(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_ulid: &str,
+    operation: RevisionOperation,
+) -> Result<()> {
+    let snapshot = match operation {
+        RevisionOperation::Delete => None,
+        _ => get_account_info(tx, account_ulid)
+            .await?
+            .map(|account_info| AccountSnapshot {
+                identifier_ulid: account_info.identifier_ulid.clone(),
+                form_data: account_info.into(),
+            }),
+    };
+
+    // コンパートメントのアカウントはコンパートメントのキーで暗号化する
+    let compartment_id = get_account_compartment(tx, account_ulid).await?;
+    let (encrypted_value, nonce) = match snapshot {
+        Some(snapshot) => {
+            let key = compartment_key(tx, compartment_id).await?;
+            let (encrypted_value, nonce) =
+                encrypt_password(&key, &serde_json::to_string(&snapshot)?)?;
+            (Some(encrypted_value), Some(nonce))
+        }
+        None => (None, None),
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO account_revisions (account_ulid, operation, encrypted_value, nonce, compartment_id)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(account_ulid)
+    .bind(operation.as_str())
+    .bind(encrypted_value)
+    .bind(nonce)
+    .bind(compartment_id)
+    .execute(&mut **tx)
+    .await?;
+
+    bump_account_version(tx, account_ulid).await?;
+
+    Ok(())
+}
+
+// 変更のたびにバージョンを加算し、古いスナップショットからの更新を検出できるようにする
+async fn bump_account_version(tx: &mut Transaction<'_, Sqlite>, account_ulid: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE accounts
+        SET version = version + 1
+        WHERE ulid = ?
+        "#,
+    )
+    .bind(account_ulid)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn list_revisions(
+    sqlite_pool: &SqlitePool,
+    account_ulid: String,
+) -> Result<Vec<AccountRevision>> {
+    let revision_rows = sqlx::query(
+        r#"
+        SELECT
+            id,
+            account_ulid,
+            operation,
+            encrypted_value,
+            nonce,
+            compartment_id,
+            undone,
+            created_at
+        FROM
+            account_revisions
+        WHERE
+            account_ulid = ?
+        ORDER BY
+            id DESC;
+        "#,
+    )
+    .bind(&account_ulid)
+    .fetch_all(sqlite_pool)
+    .await?;
+
+    // スナップショットを暗号化したキー（ロック中のコンパートメントの場合はCompartmentLocked）
+    let mut keys = HashMap::new();
+    let mut conn = sqlite_pool.acquire().await?;
+    for row in &revision_rows {
+        let encrypted_value: Option<String> = row.try_get("encrypted_value")?;
+        let compartment_id: Option<i64> = row.try_get("compartment_id")?;
+        if encrypted_value.is_some() && !keys.contains_key(&compartment_id) {
+            keys.insert(
+                compartment_id,
+                compartment_key(&mut conn, compartment_id).await?,
+            );
+        }
+    }
+    drop(conn);
+
+    // 一覧の表示名を得るため、すべての変更履歴を復号する
+    run_crypto(move || {
+        let mut revisions_vec = Vec::new();
+
+        for row in revision_rows {
+            let id: u32 = row.try_get("id")?;
+            let account_ulid: String = row.try_get("account_ulid")?;
+            let operation: String = row.try_get("operation")?;
+            let encrypted_value: Option<String> = row.try_get("encrypted_value")?;
+            let nonce: Option<String> = row.try_get("nonce")?;
+            let compartment_id: Option<i64> = row.try_get("compartment_id")?;
+            let undone: bool = row.try_get("undone")?;
+            let created_at: String = row.try_get("created_at")?;
+
+            let account_name = match (encrypted_value, nonce) {
+                (Some(encrypted_value), Some(nonce)) => {
+                    let key = &keys[&compartment_id];
+                    let snapshot: AccountSnapshot =
+                        serde_json::from_str(&decrypt_password(key, &encrypted_value, &nonce)?)?;
+                    Some(snapshot.form_data.account_name)
+                }
+                _ => None,
+            };
+
+            revisions_vec.push(AccountRevision {
+                id,
+                account_ulid,
+                operation,
+                account_name,
+                undone,
+                created_at,
+            });
+        }
+
+        Ok(revisions_vec)
+    })
+    .await
+}
+
+// 指定した変更履歴の状態にアカウントを戻す（この操作自体も変更履歴に記録される）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn revert_account_to_revision(
+    sqlite_pool: &SqlitePool,
+    revision_id: u32,
+) -> Result<String> {
+    let mut tx = sqlite_pool.begin().await?;
+
+    let (account_ulid, snapshot) = get_revision_snapshot(&mut tx, revision_id).await?;
+    let is_deleted = snapshot.is_none();
+
+    if is_deleted {
+        record_revision(&mut tx, &account_ulid, RevisionOperation::Delete).await?;
+    }
+    apply_snapshot(&mut tx, &account_ulid, snapshot).await?;
+    if !is_deleted {
+        record_revision(&mut tx, &account_ulid, RevisionOperation::Revert).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(account_ulid)
+}
+
+// 直前の変更を取り消し、対象のアカウントのULIDを返す（繰り返し呼ぶとさらに前の変更を取り消す）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn undo_last_change(sqlite_pool: &SqlitePool) -> Result<String> {
+    let mut tx = sqlite_pool.begin().await?;
+
+    let last_revision = sqlx::query(
+        r#"
+        SELECT id, account_ulid
+        FROM account_revisions
+        WHERE undone = 0
+        ORDER BY id DESC
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Change to undo".to_string()))?;
+
+    let last_revision_id: u32 = last_revision.try_get("id")?;
+    let account_ulid: String = last_revision.try_get("account_ulid")?;
+
+    let previous_revision = sqlx::query(
+        r#"
+        SELECT id
+        FROM account_revisions
+        WHERE account_ulid = ? AND undone = 0 AND id < ?
+        ORDER BY id DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(&account_ulid)
+    .bind(last_revision_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    // 直前の変更履歴がない場合は新規登録の取り消しなので削除する
+    let snapshot = match previous_revision {
+        Some(row) => get_revision_snapshot(&mut tx, row.try_get("id")?).await?.1,
+        None => None,
+    };
+    apply_snapshot(&mut tx, &account_ulid, snapshot).await?;
+    bump_account_version(&mut tx, &account_ulid).await?;
+
+    sqlx::query(
+        r#"
+        UPDATE account_revisions
+        SET undone = 1
+        WHERE id = ?
+        "#,
+    )
+    .bind(last_revision_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(account_ulid)
+}
+
+// 識別子のないアカウントを、最後の変更履歴のスナップショットから作り直す（作り直せた場合は true）
+// スナップショットがない場合やロック中のコンパートメントで暗号化されている場合は何もしない
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn rebuild_account_from_latest_revision(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_ulid: &str,
+) -> Result<bool> {
+    let revision_id: Option<u32> = sqlx::query_scalar(
+        r#"
+        SELECT id
+        FROM account_revisions
+        WHERE account_ulid = ? AND encrypted_value IS NOT NULL AND undone = 0
+        ORDER BY id DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(account_ulid)
+    .fetch_optional(&mut **tx)
+    .await?;
+    let Some(revision_id) = revision_id else {
+        return Ok(false);
+    };
+
+    let mut snapshot = match get_revision_snapshot(tx, revision_id).await {
+        Ok((_, Some(snapshot))) => snapshot,
+        Ok((_, None)) | Err(AppError::CompartmentLocked(_)) => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    // 識別子だけが失われてパスワードが残っている場合は、残っているパスワードを使う
+    let has_passwords: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM passwords WHERE identifier_ulid = ?)")
+            .bind(&snapshot.identifier_ulid)
+            .fetch_one(&mut **tx)
+            .await?;
+    if has_passwords {
+        snapshot.form_data.passwords.clear();
+    }
+
+    // 残っている関連付けはスナップショットのカテゴリに置き換える（ゴミ箱の状態とバージョンは残す）
+    sqlx::query("DELETE FROM account_categories WHERE account_ulid = ?")
+        .bind(account_ulid)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("UPDATE accounts SET account_name = ? WHERE ulid = ?")
+        .bind(&snapshot.form_data.account_name)
+        .bind(account_ulid)
+        .execute(&mut **tx)
+        .await?;
+    insert_account_details(
+        tx,
+        account_ulid,
+        &snapshot.identifier_ulid,
+        &snapshot.form_data,
+    )
+    .await?;
+    record_revision(tx, account_ulid, RevisionOperation::Revert).await?;
+
+    Ok(true)
+}
+
+async fn get_revision_snapshot(
+    tx: &mut Transaction<'_, Sqlite>,
+    revision_id: u32,
+) -> Result<(String, Option<AccountSnapshot>)> {
+    let row = sqlx::query(
+        r#"
+        SELECT account_ulid, encrypted_value, nonce, compartment_id
+        FROM account_revisions
+        WHERE id = ?
+        "#,
+    )
+    .bind(revision_id)
+    .fetch_optional(&mut **tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Revision".to_string()))?;
+
+    let account_ulid: String = row.try_get("account_ulid")?;
+    let encrypted_value: Option<String> = row.try_get("encrypted_value")?;
+    let nonce: Option<String> = row.try_get("nonce")?;
+    let compartment_id: Option<i64> = row.try_get("compartment_id")?;
+
+    let snapshot = match (encrypted_value, nonce) {
+        (Some(encrypted_value), Some(nonce)) => {
+            let key = compartment_key(tx, compartment_id).await?;
+            let snapshot_json = decrypt_password(&key, &encrypted_value, &nonce)?;
+            Some(serde_json::from_str(&snapshot_json)?)
+        }
+        _ => None,
+    };
+
+    Ok((account_ulid, snapshot))
+}
+
+// 既存の登録・更新・削除処理を使ってアカウントをスナップショットの状態にする
+async fn apply_snapshot(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_ulid: &str,
+    snapshot: Option<AccountSnapshot>,
+) -> Result<()> {
+    let current = get_account_info(tx, account_ulid).await?;
+
+    match (current, snapshot) {
+        (Some(account_info), Some(snapshot)) => {
+            restore_account_entry(tx, account_ulid).await?;
+            // 識別子のULIDが異なる場合は、パスワード履歴を残したまま付け替えてから更新する
+            let mut account_info = account_info;
+            if account_info.identifier_ulid != snapshot.identifier_ulid {
+                replace_identifier_ulid(
+                    tx,
+                    &account_info.identifier_ulid,
+                    &snapshot.identifier_ulid,
+                )
+                .await?;
+                account_info.identifier_ulid = snapshot.identifier_ulid.clone();
+            }
+            update_account_entry(tx, &snapshot.form_data, &account_info).await?;
+        }
+        (None, Some(snapshot)) => {
+            insert_account_entry(
+                tx,
+                account_ulid,
+                &snapshot.identifier_ulid,
+                &snapshot.form_data,
+            )
+            .await?;
+        }
+        (Some(_), None) => {
+            delete_account_entry(tx, account_ulid).await?;
+        }
+        (None, None) => {
+            // すでに完全に削除済みの場合は何もしない
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use crate::error::Result;
+use sqlx::{Sqlite, SqliteConnection, SqlitePool, Transaction};
+use tracing::instrument;
+
+// 以前の環境変数の値を設定に移したかどうか（vault_meta に記録する）
+const ENV_SETTINGS_IMPORTED_NAME: &str = "env_settings_imported";
+
+// 保存されているすべての設定（項目名とJSONの値）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_settings(conn: &mut SqliteConnection) -> Result<Vec<(String, String)>> {
+    Ok(
+        sqlx::query_as("SELECT name, value FROM settings ORDER BY name")
+            .fetch_all(conn)
+            .await?,
+    )
+}
+
+// 指定した項目だけを保存する（他の項目はそのまま残す）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn save_settings(sqlite_pool: &SqlitePool, settings: &[(String, String)]) -> Result<()> {
+    let mut tx = sqlite_pool.begin().await?;
+    upsert_settings(&mut tx, settings).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn is_env_settings_imported(sqlite_pool: &SqlitePool) -> Result<bool> {
+    Ok(
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM vault_meta WHERE name = ?)")
+            .bind(ENV_SETTINGS_IMPORTED_NAME)
+            .fetch_one(sqlite_pool)
+            .await?,
+    )
+}
+
+// 以前の環境変数の値を保存し、移したことを記録する（すでに記録されている場合は何もしない）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn save_env_settings(
+    sqlite_pool: &SqlitePool,
+    settings: &[(String, String)],
+) -> Result<()> {
+    let mut tx = sqlite_pool.begin().await?;
+
+    let marked = sqlx::query("INSERT OR IGNORE INTO vault_meta (name, value) VALUES (?, '1')")
+        .bind(ENV_SETTINGS_IMPORTED_NAME)
+        .execute(&mut *tx)
+        .await?;
+    if marked.rows_affected() == 0 {
+        return Ok(());
+    }
+    upsert_settings(&mut tx, settings).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn upsert_settings(
+    tx: &mut Transaction<'_, Sqlite>,
+    settings: &[(String, String)],
+) -> Result<()> {
+    for (name, value) in settings {
+        sqlx::query(
+            r#"
+            INSERT INTO settings (name, value) VALUES (?, ?)
+            ON CONFLICT(name) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(name)
+        .bind(value)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use crate::crypto::{decrypt_password, encrypt_password, get_encryption_key};
+use crate::error::{AppError, Result};
+use aes_gcm::{Aes256Gcm, Key};
+use age::secrecy::ExposeSecret;
+use age::x25519::Identity;
+use sqlx::SqlitePool;
+use std::str::FromStr;
+use tracing::instrument;
+
+const SHARE_IDENTITY_NAME: &str = "share_identity";
+
+// 共有用の X25519 の鍵（保管庫のキーで暗号化して保存し、ない場合は作成する）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_share_identity(sqlite_pool: &SqlitePool) -> Result<Identity> {
+    let key = get_encryption_key()?;
+
+    if let Some(identity) = load_share_identity(sqlite_pool, &key).await? {
+        return Ok(identity);
+    }
+
+    let identity = Identity::generate();
+    let (encrypted_value, nonce) = encrypt_password(&key, identity.to_string().expose_secret())?;
+    // 同時に作成された場合は先に保存された鍵を使う
+    sqlx::query("INSERT OR IGNORE INTO vault_meta (name, value) VALUES (?, ?)")
+        .bind(SHARE_IDENTITY_NAME)
+        .bind(format!("{}:{}", encrypted_value, nonce))
+        .execute(sqlite_pool)
+        .await?;
+
+    load_share_identity(sqlite_pool, &key)
+        .await?
+        .ok_or_else(|| AppError::Internal("Share identity was not saved".to_string()))
+}
+
+async fn load_share_identity(
+    sqlite_pool: &SqlitePool,
+    key: &Key<Aes256Gcm>,
+) -> Result<Option<Identity>> {
+    let stored: Option<String> = sqlx::query_scalar("SELECT value FROM vault_meta WHERE name = ?")
+        .bind(SHARE_IDENTITY_NAME)
+        .fetch_optional(sqlite_pool)
+        .await?;
+
+    let Some(stored) = stored else {
+        return Ok(None);
+    };
+    let (encrypted_value, nonce) = stored
+        .split_once(':')
+        .ok_or_else(|| AppError::Internal("Invalid share identity value".to_string()))?;
+    let secret = decrypt_password(key, encrypted_value, nonce)?;
+
+    Identity::from_str(&secret)
+        .map(Some)
+        .map_err(|_| AppError::Tampered)
+}
@@ -0,0 +1,664 @@
+use crate::crypto::{decrypt_password, encrypt_password, get_encryption_key};
+use crate::error::{AppError, Result};
+use crate::models::{AccountInfo, ChangeSet, SyncAccount, SyncConflict, SyncRecord};
+use crate::repository::compartment::get_account_compartment;
+use crate::repository::delete::delete_account_entry;
+use crate::repository::insert::insert_account_entry;
+use crate::repository::read::get_account_info;
+use crate::repository::revision::{record_revision, RevisionOperation};
+use crate::repository::trash::{purge_account, restore_account_entry};
+use crate::repository::update::update_account_entry;
+use crate::sync::{merge_account, MergeAction, SyncField};
+use serde_json::Value;
+use sqlx::{Row, Sqlite, SqlitePool, Transaction};
+use std::collections::HashMap;
+use tracing::instrument;
+
+// ゴミ箱も含めた全アカウントと、完全に削除したアカウントの削除の記録
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn export_records(sqlite_pool: &SqlitePool) -> Result<Vec<SyncRecord>> {
+    let mut tx = sqlite_pool.begin().await?;
+
+    // コンパートメントのアカウントは、保管庫のキーだけで復号できる変更セットに含めない
+    let account_ulids: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT ulid FROM accounts
+        WHERE ulid NOT IN (
+            SELECT ac.account_ulid
+            FROM account_categories ac
+            JOIN compartments cp ON ac.category_id = cp.category_id
+        )
+        ORDER BY ulid
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut records = Vec::new();
+    for account_ulid in account_ulids {
+        let state = get_sync_account(&mut tx, &account_ulid)
+            .await?
+            .map(|(_, account)| account);
+        records.push(SyncRecord {
+            account_ulid,
+            state,
+        });
+    }
+
+    let tombstones: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT account_ulid FROM sync_tombstones ORDER BY account_ulid
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    records.extend(tombstones.into_iter().map(|account_ulid| SyncRecord {
+        account_ulid,
+        state: None,
+    }));
+
+    tx.commit().await?;
+
+    Ok(records)
+}
+
+// 同期相手ごとに、前回取り込んだ相手側の状態
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn export_bases(sqlite_pool: &SqlitePool) -> Result<HashMap<String, Vec<SyncRecord>>> {
+    let key = get_encryption_key()?;
+
+    let base_rows = sqlx::query(
+        r#"
+        SELECT peer_device_id, account_ulid, encrypted_value, nonce
+        FROM sync_bases
+        ORDER BY peer_device_id, account_ulid
+        "#,
+    )
+    .fetch_all(sqlite_pool)
+    .await?;
+
+    let mut bases: HashMap<String, Vec<SyncRecord>> = HashMap::new();
+    for row in base_rows {
+        let base_json = decrypt_password(
+            &key,
+            &row.try_get::<String, _>("encrypted_value")?,
+            &row.try_get::<String, _>("nonce")?,
+        )?;
+        bases
+            .entry(row.try_get("peer_device_id")?)
+            .or_default()
+            .push(SyncRecord {
+                account_ulid: row.try_get("account_ulid")?,
+                state: serde_json::from_str(&base_json)?,
+            });
+    }
+
+    Ok(bases)
+}
+
+// 相手側の変更セットを取り込み、反映したアカウント数と競合の数を返す
+// （device_id はこの端末のID、相手側がこの端末から前回取り込んだ状態もマージの基準に使う）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn apply_change_set(
+    sqlite_pool: &SqlitePool,
+    device_id: &str,
+    change_set: &ChangeSet,
+) -> Result<(u32, u32)> {
+    let mut tx = sqlite_pool.begin().await?;
+    let key = get_encryption_key()?;
+
+    let peer_bases: HashMap<&str, &Option<SyncAccount>> = change_set
+        .bases
+        .get(device_id)
+        .into_iter()
+        .flatten()
+        .map(|record| (record.account_ulid.as_str(), &record.state))
+        .collect();
+
+    let mut applied = 0;
+    let mut conflict_count = 0;
+
+    for record in &change_set.records {
+        // この端末でコンパートメントに入れたアカウントは同期しない
+        if get_account_compartment(&mut tx, &record.account_ulid)
+            .await?
+            .is_some()
+        {
+            continue;
+        }
+
+        let base_row = sqlx::query(
+            r#"
+            SELECT encrypted_value, nonce
+            FROM sync_bases
+            WHERE peer_device_id = ? AND account_ulid = ?
+            "#,
+        )
+        .bind(&change_set.device_id)
+        .bind(&record.account_ulid)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let base: Option<Option<SyncAccount>> = match base_row {
+            Some(row) => {
+                let base_json = decrypt_password(
+                    &key,
+                    &row.try_get::<String, _>("encrypted_value")?,
+                    &row.try_get::<String, _>("nonce")?,
+                )?;
+                Some(serde_json::from_str(&base_json)?)
+            }
+            None => None,
+        };
+
+        // 前回から相手側が変わっていない場合は何もしない
+        if base.as_ref() == Some(&record.state) {
+            continue;
+        }
+
+        let local = get_sync_account(&mut tx, &record.account_ulid).await?;
+        let local_purged = is_purged(&mut tx, &record.account_ulid).await?;
+        let bases: Vec<&Option<SyncAccount>> = base
+            .iter()
+            .chain(peer_bases.get(record.account_ulid.as_str()).copied())
+            .collect();
+        let (action, conflicts) = merge_account(
+            &bases,
+            local.as_ref().map(|(_, account)| account),
+            local_purged,
+            &record.state,
+        );
+
+        match action {
+            MergeAction::Keep => {}
+            MergeAction::Insert(account) => {
+                apply_sync_account(&mut tx, &record.account_ulid, None, &account).await?;
+                applied += 1;
+            }
+            MergeAction::Update(account) => {
+                apply_sync_account(&mut tx, &record.account_ulid, local, &account).await?;
+                applied += 1;
+            }
+            MergeAction::Purge => {
+                purge_account(&mut tx, &record.account_ulid).await?;
+                applied += 1;
+            }
+        }
+
+        // 前回の取り込みで残った競合は、今回のマージの結果で置き換える
+        sqlx::query(
+            r#"
+            DELETE FROM sync_conflicts WHERE account_ulid = ? AND peer_device_id = ?
+            "#,
+        )
+        .bind(&record.account_ulid)
+        .bind(&change_set.device_id)
+        .execute(&mut *tx)
+        .await?;
+
+        for (field, remote_value) in conflicts {
+            let (encrypted_value, nonce) =
+                encrypt_password(&key, &serde_json::to_string(&remote_value)?)?;
+            sqlx::query(
+                r#"
+                INSERT INTO sync_conflicts (account_ulid, peer_device_id, field, encrypted_value, nonce)
+                VALUES (?, ?, ?, ?, ?)
+                ON CONFLICT (account_ulid, peer_device_id, field) DO UPDATE SET
+                    encrypted_value = excluded.encrypted_value,
+                    nonce = excluded.nonce,
+                    created_at = CURRENT_TIMESTAMP
+                "#,
+            )
+            .bind(&record.account_ulid)
+            .bind(&change_set.device_id)
+            .bind(field.as_str())
+            .bind(encrypted_value)
+            .bind(nonce)
+            .execute(&mut *tx)
+            .await?;
+            conflict_count += 1;
+        }
+
+        let (encrypted_value, nonce) =
+            encrypt_password(&key, &serde_json::to_string(&record.state)?)?;
+        sqlx::query(
+            r#"
+            INSERT INTO sync_bases (peer_device_id, account_ulid, encrypted_value, nonce)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (peer_device_id, account_ulid) DO UPDATE SET
+                encrypted_value = excluded.encrypted_value,
+                nonce = excluded.nonce
+            "#,
+        )
+        .bind(&change_set.device_id)
+        .bind(&record.account_ulid)
+        .bind(encrypted_value)
+        .bind(nonce)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok((applied, conflict_count))
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn list_sync_conflicts(sqlite_pool: &SqlitePool) -> Result<Vec<SyncConflict>> {
+    let mut tx = sqlite_pool.begin().await?;
+    let key = get_encryption_key()?;
+
+    let conflict_rows = sqlx::query(
+        r#"
+        SELECT id, account_ulid, field, encrypted_value, nonce, created_at
+        FROM sync_conflicts
+        ORDER BY id
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut conflicts = Vec::new();
+    for row in conflict_rows {
+        let account_ulid: String = row.try_get("account_ulid")?;
+        let field: String = row.try_get("field")?;
+        let remote_json = decrypt_password(
+            &key,
+            &row.try_get::<String, _>("encrypted_value")?,
+            &row.try_get::<String, _>("nonce")?,
+        )?;
+
+        let local = get_sync_account(&mut tx, &account_ulid).await?;
+        let local_value = match &local {
+            Some((_, account)) => SyncField::parse(&field)?.get(account),
+            None => Value::Null,
+        };
+
+        conflicts.push(SyncConflict {
+            id: row.try_get("id")?,
+            account_name: local.map(|(account_info, _)| account_info.account_name),
+            account_ulid,
+            field,
+            local_value,
+            remote_value: serde_json::from_str(&remote_json)?,
+            created_at: row.try_get("created_at")?,
+        });
+    }
+
+    tx.commit().await?;
+
+    Ok(conflicts)
+}
+
+// 競合を解決する（keep_remote が false の場合は手元の値のまま競合を消す）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn resolve_sync_conflict(
+    sqlite_pool: &SqlitePool,
+    conflict_id: u32,
+    keep_remote: bool,
+) -> Result<()> {
+    let mut tx = sqlite_pool.begin().await?;
+
+    let row = sqlx::query(
+        r#"
+        SELECT account_ulid, field, encrypted_value, nonce
+        FROM sync_conflicts
+        WHERE id = ?
+        "#,
+    )
+    .bind(conflict_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Sync conflict".to_string()))?;
+
+    let account_ulid: String = row.try_get("account_ulid")?;
+    let field = SyncField::parse(&row.try_get::<String, _>("field")?)?;
+
+    if keep_remote {
+        let key = get_encryption_key()?;
+        let remote_json = decrypt_password(
+            &key,
+            &row.try_get::<String, _>("encrypted_value")?,
+            &row.try_get::<String, _>("nonce")?,
+        )?;
+        let remote_value: Value = serde_json::from_str(&remote_json)?;
+        let local = get_sync_account(&mut tx, &account_ulid).await?;
+
+        match (field, local) {
+            // 相手側で完全に削除されたアカウントは、元に戻せるようゴミ箱に移動する
+            (SyncField::Deleted, Some((_, account))) if remote_value.is_null() => {
+                if !account.trashed {
+                    record_revision(&mut tx, &account_ulid, RevisionOperation::Delete).await?;
+                    delete_account_entry(&mut tx, &account_ulid).await?;
+                }
+            }
+            (SyncField::Deleted, None) if remote_value.is_null() => {}
+            // 手元で完全に削除したアカウントを相手側の状態で復活させる
+            (SyncField::Deleted, local) => {
+                let account: SyncAccount = serde_json::from_value(remote_value)?;
+                sqlx::query(
+                    r#"
+                    DELETE FROM sync_tombstones WHERE account_ulid = ?
+                    "#,
+                )
+                .bind(&account_ulid)
+                .execute(&mut *tx)
+                .await?;
+                apply_sync_account(&mut tx, &account_ulid, local, &account).await?;
+            }
+            (field, Some((account_info, account))) => {
+                let mut merged = account.clone();
+                field.set(&mut merged, remote_value)?;
+                apply_sync_account(
+                    &mut tx,
+                    &account_ulid,
+                    Some((account_info, account)),
+                    &merged,
+                )
+                .await?;
+            }
+            // 競合の後に手元で完全に削除した場合は、反映先がないため競合を消すだけにする
+            (_, None) => {}
+        }
+    }
+
+    sqlx::query(
+        r#"
+        DELETE FROM sync_conflicts WHERE id = ?
+        "#,
+    )
+    .bind(conflict_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// 同期で扱う形でアカウントを取得（ゴミ箱のアカウントも含む）
+async fn get_sync_account(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_ulid: &str,
+) -> Result<Option<(AccountInfo, SyncAccount)>> {
+    let Some(account_info) = get_account_info(tx, account_ulid).await? else {
+        return Ok(None);
+    };
+    let trashed: bool = sqlx::query_scalar(
+        r#"
+        SELECT deleted_at IS NOT NULL FROM accounts WHERE ulid = ?
+        "#,
+    )
+    .bind(account_ulid)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let account = SyncAccount {
+        identifier_ulid: account_info.identifier_ulid.clone(),
+        form_data: account_info.clone().into(),
+        trashed,
+    };
+
+    Ok(Some((account_info, account)))
+}
+
+async fn is_purged(tx: &mut Transaction<'_, Sqlite>, account_ulid: &str) -> Result<bool> {
+    let is_purged = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM sync_tombstones WHERE account_ulid = ?)
+        "#,
+    )
+    .bind(account_ulid)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(is_purged)
+}
+
+// 既存の登録・更新・ゴミ箱の処理を使ってアカウントを同期後の状態にする（変更履歴にも記録される）
+async fn apply_sync_account(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_ulid: &str,
+    local: Option<(AccountInfo, SyncAccount)>,
+    account: &SyncAccount,
+) -> Result<()> {
+    let was_trashed = match local {
+        Some((account_info, local)) => {
+            if update_account_entry(tx, &account.form_data, &account_info).await? {
+                record_revision(tx, account_ulid, RevisionOperation::Sync).await?;
+            }
+            local.trashed
+        }
+        None => {
+            insert_account_entry(
+                tx,
+                account_ulid,
+                &account.identifier_ulid,
+                &account.form_data,
+            )
+            .await?;
+            record_revision(tx, account_ulid, RevisionOperation::Sync).await?;
+            false
+        }
+    };
+
+    if account.trashed && !was_trashed {
+        record_revision(tx, account_ulid, RevisionOperation::Delete).await?;
+        delete_account_entry(tx, account_ulid).await?;
+    } else if !account.trashed && was_trashed {
+        restore_account_entry(tx, account_ulid).await?;
+        record_revision(tx, account_ulid, RevisionOperation::Restore).await?;
+    }
+
+    Ok(())
+}
+
+// 同期サーバーから前回取得した位置（初めての場合は 0）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_server_cursor(sqlite_pool: &SqlitePool, server_url: &str) -> Result<i64> {
+    let revision: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT revision FROM sync_server_cursors WHERE server_url = ?
+        "#,
+    )
+    .bind(server_url)
+    .fetch_optional(sqlite_pool)
+    .await?;
+
+    Ok(revision.unwrap_or(0))
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn set_server_cursor(
+    sqlite_pool: &SqlitePool,
+    server_url: &str,
+    revision: i64,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO sync_server_cursors (server_url, revision)
+        VALUES (?, ?)
+        ON CONFLICT (server_url) DO UPDATE SET revision = excluded.revision
+        "#,
+    )
+    .bind(server_url)
+    .bind(revision)
+    .execute(sqlite_pool)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_pool;
+    use crate::models::FormData;
+    use crate::vault::with_test_vault;
+
+    const ACCOUNT_ULID: &str = "01JN0000000000000000000000";
+
+    fn account(passwords: &[&str]) -> SyncAccount {
+        SyncAccount {
+            identifier_ulid: "01JN0000000000000000000001".to_string(),
+            form_data: FormData {
+                account_name: "Mail".to_string(),
+                identifier: "user@example.com".to_string(),
+                passwords: passwords.iter().map(|p| p.to_string()).collect(),
+                category_name: "Web".to_string(),
+            },
+            trashed: false,
+        }
+    }
+
+    fn change_set(device_id: &str, state: Option<SyncAccount>) -> ChangeSet {
+        ChangeSet {
+            device_id: device_id.to_string(),
+            records: vec![SyncRecord {
+                account_ulid: ACCOUNT_ULID.to_string(),
+                state,
+            }],
+            bases: HashMap::new(),
+        }
+    }
+
+    async fn apply(sqlite_pool: &SqlitePool, change_set: ChangeSet) -> (u32, u32) {
+        apply_change_set(sqlite_pool, "LOCAL", &change_set)
+            .await
+            .unwrap()
+    }
+
+    async fn local_account(sqlite_pool: &SqlitePool) -> Option<SyncAccount> {
+        let mut tx = sqlite_pool.begin().await.unwrap();
+        get_sync_account(&mut tx, ACCOUNT_ULID)
+            .await
+            .unwrap()
+            .map(|(_, account)| account)
+    }
+
+    async fn is_local_purged(sqlite_pool: &SqlitePool) -> bool {
+        let mut tx = sqlite_pool.begin().await.unwrap();
+        is_purged(&mut tx, ACCOUNT_ULID).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_apply_change_set_inserts_updates_and_purges() {
+        // 変更セットの暗号化に保管庫のキーを使うため、キーファイルのある保管庫で実行する
+        with_test_vault(|_| async {
+            let sqlite_pool = test_pool().await;
+
+            // 手元にないアカウントは追加
+            assert_eq!(
+                apply(&sqlite_pool, change_set("PEER", Some(account(&["p1"])))).await,
+                (1, 0)
+            );
+            assert_eq!(local_account(&sqlite_pool).await, Some(account(&["p1"])));
+
+            // 前回から変わっていない変更セットは何も反映しない
+            assert_eq!(
+                apply(&sqlite_pool, change_set("PEER", Some(account(&["p1"])))).await,
+                (0, 0)
+            );
+
+            // 手元で変更していないアカウントは相手側の変更に従う
+            assert_eq!(
+                apply(&sqlite_pool, change_set("PEER", Some(account(&["p2"])))).await,
+                (1, 0)
+            );
+            assert_eq!(local_account(&sqlite_pool).await, Some(account(&["p2"])));
+
+            // 相手側で完全に削除されたアカウントは、手元でも削除して削除の記録を残す
+            assert_eq!(apply(&sqlite_pool, change_set("PEER", None)).await, (1, 0));
+            assert_eq!(local_account(&sqlite_pool).await, None);
+            assert!(is_local_purged(&sqlite_pool).await);
+            let records = export_records(&sqlite_pool).await.unwrap();
+            assert_eq!(records.len(), 1);
+            assert_eq!(records[0].state, None);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_conflicts_are_kept_per_peer_and_resolved() {
+        with_test_vault(|_| async {
+            let sqlite_pool = test_pool().await;
+            apply(&sqlite_pool, change_set("PEER_A", Some(account(&["a"])))).await;
+
+            // 別々の端末の同じ項目の競合は、どちらも上書きせずに残す
+            assert_eq!(
+                apply(&sqlite_pool, change_set("PEER_B", Some(account(&["b"])))).await,
+                (0, 1)
+            );
+            assert_eq!(
+                apply(&sqlite_pool, change_set("PEER_C", Some(account(&["c"])))).await,
+                (0, 1)
+            );
+            let conflicts = list_sync_conflicts(&sqlite_pool).await.unwrap();
+            let remote_values: Vec<&Value> = conflicts.iter().map(|c| &c.remote_value).collect();
+            assert_eq!(
+                remote_values,
+                [&serde_json::json!(["b"]), &serde_json::json!(["c"])]
+            );
+            assert!(conflicts
+                .iter()
+                .all(|c| c.local_value == serde_json::json!(["a"])));
+
+            // 相手側の値を採用すると手元に反映される
+            resolve_sync_conflict(&sqlite_pool, conflicts[0].id, true)
+                .await
+                .unwrap();
+            assert_eq!(local_account(&sqlite_pool).await, Some(account(&["b"])));
+
+            // 手元の値のままにすると、値を変えずに競合だけを消す
+            resolve_sync_conflict(&sqlite_pool, conflicts[1].id, false)
+                .await
+                .unwrap();
+            assert_eq!(local_account(&sqlite_pool).await, Some(account(&["b"])));
+            assert!(list_sync_conflicts(&sqlite_pool).await.unwrap().is_empty());
+
+            assert!(matches!(
+                resolve_sync_conflict(&sqlite_pool, conflicts[0].id, true).await,
+                Err(AppError::NotFound(_))
+            ));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_resolve_deleted_conflicts() {
+        with_test_vault(|_| async {
+            let sqlite_pool = test_pool().await;
+            apply(&sqlite_pool, change_set("PEER_A", Some(account(&["a"])))).await;
+            apply(&sqlite_pool, change_set("PEER_A", None)).await;
+
+            // 手元で完全に削除したアカウントを相手側が変更した場合は競合になり、採用すると復活する
+            assert_eq!(
+                apply(&sqlite_pool, change_set("PEER_B", Some(account(&["b"])))).await,
+                (0, 1)
+            );
+            assert_eq!(local_account(&sqlite_pool).await, None);
+            let conflicts = list_sync_conflicts(&sqlite_pool).await.unwrap();
+            assert_eq!(conflicts[0].field, SyncField::Deleted.as_str());
+            resolve_sync_conflict(&sqlite_pool, conflicts[0].id, true)
+                .await
+                .unwrap();
+            assert_eq!(local_account(&sqlite_pool).await, Some(account(&["b"])));
+            assert!(!is_local_purged(&sqlite_pool).await);
+
+            // 手元のアカウントを相手側が完全に削除した競合は、採用するとゴミ箱に移動する
+            assert_eq!(
+                apply(&sqlite_pool, change_set("PEER_C", None)).await,
+                (0, 1)
+            );
+            let conflicts = list_sync_conflicts(&sqlite_pool).await.unwrap();
+            assert_eq!(conflicts[0].remote_value, Value::Null);
+            resolve_sync_conflict(&sqlite_pool, conflicts[0].id, true)
+                .await
+                .unwrap();
+            let mut trashed = account(&["b"]);
+            trashed.trashed = true;
+            assert_eq!(local_account(&sqlite_pool).await, Some(trashed));
+        })
+        .await;
+    }
+}
@@ -0,0 +1,242 @@
+use crate::compartment::is_hidden;
+use crate::error::{AppError, Result};
+use crate::models::TrashedAccount;
+use crate::repository::delete::purge_account_entry;
+use crate::repository::revision::{record_revision, RevisionOperation};
+use sqlx::{Row, Sqlite, SqlitePool, Transaction};
+use tracing::instrument;
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_trashed_accounts(sqlite_pool: &SqlitePool) -> Result<Vec<TrashedAccount>> {
+    let accounts_rows = sqlx::query(
+        r#"
+        SELECT
+            a.ulid AS account_ulid,
+            a.account_name,
+            i.identifier,
+            c.category_name,
+            a.deleted_at,
+            cp.category_id AS compartment_id
+        FROM
+            accounts a
+        LEFT JOIN
+            identifiers i ON a.ulid = i.account_ulid
+        LEFT JOIN
+            account_categories ac ON a.ulid = ac.account_ulid
+        LEFT JOIN
+            categories c ON ac.category_id = c.id
+        LEFT JOIN
+            compartments cp ON ac.category_id = cp.category_id
+        WHERE
+            a.deleted_at IS NOT NULL
+        ORDER BY
+            a.deleted_at DESC;
+        "#,
+    )
+    .fetch_all(sqlite_pool)
+    .await?;
+
+    let mut trashed_accounts = Vec::new();
+
+    for row in accounts_rows {
+        // ロック中のコンパートメントのアカウントは表示しない
+        if is_hidden(row.try_get("compartment_id")?) {
+            continue;
+        }
+
+        let account_ulid: String = row.try_get("account_ulid")?;
+        let account_name: String = row.try_get("account_name")?;
+        let identifier: String = row.try_get("identifier")?;
+        let category_name: String = row.try_get("category_name")?;
+        let deleted_at: String = row.try_get("deleted_at")?;
+
+        trashed_accounts.push(TrashedAccount {
+            account_ulid,
+            account_name,
+            identifier,
+            category_name,
+            deleted_at,
+        });
+    }
+
+    Ok(trashed_accounts)
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn restore_account(sqlite_pool: &SqlitePool, account_ulid: &str) -> Result<()> {
+    let mut tx = sqlite_pool.begin().await?;
+
+    if !restore_account_entry(&mut tx, account_ulid).await? {
+        return Err(AppError::NotFound("Account in trash".to_string()));
+    }
+    record_revision(&mut tx, account_ulid, RevisionOperation::Restore).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// ゴミ箱から戻し、戻したかどうかを返す
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn restore_account_entry(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_ulid: &str,
+) -> Result<bool> {
+    let result = sqlx::query(
+        r#"
+        UPDATE accounts
+        SET deleted_at = NULL
+        WHERE ulid = ? AND deleted_at IS NOT NULL
+        "#,
+    )
+    .bind(account_ulid)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn empty_trash(sqlite_pool: &SqlitePool) -> Result<()> {
+    let mut tx = sqlite_pool.begin().await?;
+
+    let account_ulids: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT ulid FROM accounts WHERE deleted_at IS NOT NULL
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    purge_accounts(&mut tx, &account_ulids).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// 保持期間を過ぎたゴミ箱のアカウントを完全に削除（保持日数が0の場合は自動削除しない）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn purge_expired_trash(sqlite_pool: &SqlitePool, retention_days: u32) -> Result<()> {
+    if retention_days == 0 {
+        return Ok(());
+    }
+
+    let mut tx = sqlite_pool.begin().await?;
+
+    let account_ulids: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT ulid FROM accounts
+        WHERE deleted_at IS NOT NULL
+        AND deleted_at <= datetime('now', '-' || ? || ' days')
+        "#,
+    )
+    .bind(retention_days)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    purge_accounts(&mut tx, &account_ulids).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// 変更履歴も含めて完全に削除する（取り消しはできない）
+async fn purge_accounts(tx: &mut Transaction<'_, Sqlite>, account_ulids: &[String]) -> Result<()> {
+    for account_ulid in account_ulids {
+        purge_account(tx, account_ulid).await?;
+    }
+
+    Ok(())
+}
+
+// 他の端末にも削除を伝えるため、削除の記録を残して完全に削除する
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn purge_account(tx: &mut Transaction<'_, Sqlite>, account_ulid: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        DELETE FROM account_revisions WHERE account_ulid = ?
+        "#,
+    )
+    .bind(account_ulid)
+    .execute(&mut **tx)
+    .await?;
+
+    purge_account_entry(tx, account_ulid).await?;
+
+    sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO sync_tombstones (account_ulid)
+        VALUES (?)
+        "#,
+    )
+    .bind(account_ulid)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_pool;
+
+    #[tokio::test]
+    async fn test_trashed_accounts_of_locked_compartment_are_hidden() {
+        let sqlite_pool = test_pool().await;
+        sqlx::query(
+            r#"
+            INSERT INTO categories (category_name) VALUES ('Web'), ('Private');
+            INSERT INTO compartments (category_id, salt, key_check, relock_minutes)
+            SELECT id, '00', '00:00', 5 FROM categories WHERE category_name = 'Private';
+            INSERT INTO accounts (ulid, account_name, deleted_at) VALUES
+                ('01JN0000000000000000000000', 'Mail', CURRENT_TIMESTAMP),
+                ('01JN0000000000000000000001', 'Diary', CURRENT_TIMESTAMP);
+            INSERT INTO identifiers (ulid, account_ulid, identifier) VALUES
+                ('01JN0000000000000000000002', '01JN0000000000000000000000', 'user'),
+                ('01JN0000000000000000000003', '01JN0000000000000000000001', 'user');
+            INSERT INTO account_categories (account_ulid, category_id)
+            SELECT '01JN0000000000000000000000', id FROM categories WHERE category_name = 'Web';
+            INSERT INTO account_categories (account_ulid, category_id)
+            SELECT '01JN0000000000000000000001', id FROM categories WHERE category_name = 'Private';
+            "#,
+        )
+        .execute(&sqlite_pool)
+        .await
+        .unwrap();
+
+        // ロック中のコンパートメントのアカウントはゴミ箱の一覧に含まれないことを確認
+        let trashed = get_trashed_accounts(&sqlite_pool).await.unwrap();
+        let names: Vec<&str> = trashed.iter().map(|a| a.account_name.as_str()).collect();
+        assert_eq!(names, ["Mail"]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_trashed_account_is_not_found() {
+        let sqlite_pool = test_pool().await;
+        sqlx::query(
+            r#"
+            INSERT INTO accounts (ulid, account_name, deleted_at)
+            VALUES ('01JN0000000000000000000000', 'Mail', CURRENT_TIMESTAMP)
+            "#,
+        )
+        .execute(&sqlite_pool)
+        .await
+        .unwrap();
+
+        // すでにゴミ箱にあるアカウントや存在しないアカウントは削除できず、変更履歴も残らないことを確認
+        for account_ulid in ["01JN0000000000000000000000", "01JN0000000000000000000009"] {
+            assert!(matches!(
+                crate::repository::delete::delete_account(&sqlite_pool, account_ulid).await,
+                Err(AppError::NotFound(_))
+            ));
+        }
+        let revisions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM account_revisions")
+            .fetch_one(&sqlite_pool)
+            .await
+            .unwrap();
+        assert_eq!(revisions, 0);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::error::{AppError, Result};
+use crate::models::FieldError;
+use sqlx::{Row, Sqlite, SqlitePool, Transaction};
+use tracing::instrument;
+
+pub struct TravelSealRow {
+    pub salt: String,
+    pub sealed: Vec<u8>,
+    pub safe_categories: Vec<String>,
+    pub created_at: String,
+}
+
+// 封印したスナップショットの中で、端末から外したアカウントのうちまだ戻していないもの
+const RESTORE_ULIDS: &str = "SELECT ulid FROM temp.travel_restore_ulids";
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn get_travel_seal(sqlite_pool: &SqlitePool) -> Result<Option<TravelSealRow>> {
+    let row = sqlx::query(
+        r#"
+        SELECT salt, sealed, safe_categories, created_at
+        FROM travel_seal
+        WHERE id = 1
+        "#,
+    )
+    .fetch_optional(sqlite_pool)
+    .await?;
+
+    row.map(|row| {
+        let safe_categories: String = row.try_get("safe_categories")?;
+        Ok(TravelSealRow {
+            salt: row.try_get("salt")?,
+            sealed: row.try_get("sealed")?,
+            safe_categories: serde_json::from_str(&safe_categories)?,
+            created_at: row.try_get("created_at")?,
+        })
+    })
+    .transpose()
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn is_travel_mode(sqlite_pool: &SqlitePool) -> Result<bool> {
+    Ok(
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM travel_seal WHERE id = 1)")
+            .fetch_one(sqlite_pool)
+            .await?,
+    )
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn insert_travel_seal(
+    tx: &mut Transaction<'_, Sqlite>,
+    salt: &str,
+    sealed: &[u8],
+    safe_categories: &[String],
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO travel_seal (id, salt, sealed, safe_categories)
+        VALUES (1, ?, ?, ?)
+        "#,
+    )
+    .bind(salt)
+    .bind(sealed)
+    .bind(serde_json::to_string(safe_categories)?)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn delete_travel_seal(tx: &mut Transaction<'_, Sqlite>) -> Result<()> {
+    sqlx::query("DELETE FROM travel_seal")
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+// 端末に残すカテゴリ以外のアカウントとカテゴリを、変更履歴も含めて削除する
+// （他の端末から削除されないよう、同期用の削除の記録は残さない）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn remove_travel_accounts(
+    tx: &mut Transaction<'_, Sqlite>,
+    safe_categories: &[String],
+) -> Result<()> {
+    let safe_categories = serde_json::to_string(safe_categories)?;
+
+    let account_ulids: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT ac.account_ulid
+        FROM account_categories ac
+        JOIN categories c ON ac.category_id = c.id
+        WHERE c.category_name NOT IN (SELECT value FROM json_each(?))
+        "#,
+    )
+    .bind(&safe_categories)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    for account_ulid in &account_ulids {
+        for table in ["account_revisions", "sync_bases", "sync_conflicts"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE account_ulid = ?", table))
+                .bind(account_ulid)
+                .execute(&mut **tx)
+                .await?;
+        }
+        sqlx::query("DELETE FROM accounts WHERE ulid = ?")
+            .bind(account_ulid)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    // コンパートメントもカテゴリと一緒に削除される
+    sqlx::query(
+        r#"
+        DELETE FROM categories
+        WHERE category_name NOT IN (SELECT value FROM json_each(?))
+        "#,
+    )
+    .bind(&safe_categories)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+// sealed として接続したスナップショットから、端末から外したアカウントを戻す
+// トラベルモード中に変更した残りのアカウントはそのまま残し、削除したアカウントは戻さない
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn restore_travel_accounts(
+    tx: &mut Transaction<'_, Sqlite>,
+    safe_categories: &[String],
+) -> Result<()> {
+    let safe_categories = serde_json::to_string(safe_categories)?;
+
+    sqlx::query("DROP TABLE IF EXISTS temp.travel_restore_ulids")
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE TEMP TABLE travel_restore_ulids AS
+        SELECT ac.account_ulid AS ulid
+        FROM sealed.account_categories ac
+        JOIN sealed.categories c ON ac.category_id = c.id
+        WHERE c.category_name NOT IN (SELECT value FROM json_each(?))
+        AND ac.account_ulid NOT IN (SELECT ulid FROM main.accounts)
+        AND ac.account_ulid NOT IN (SELECT account_ulid FROM main.sync_tombstones)
+        "#,
+    )
+    .bind(&safe_categories)
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query("DROP TABLE IF EXISTS temp.travel_restore_categories")
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query(&format!(
+        r#"
+        CREATE TEMP TABLE travel_restore_categories AS
+        SELECT id AS sealed_id, category_name
+        FROM sealed.categories
+        WHERE category_name NOT IN (SELECT value FROM json_each(?))
+        AND (
+            id IN (
+                SELECT category_id FROM sealed.account_categories
+                WHERE account_ulid IN ({restore_ulids})
+            )
+            OR id IN (SELECT category_id FROM sealed.compartments)
+        )
+        "#,
+        restore_ulids = RESTORE_ULIDS
+    ))
+    .bind(&safe_categories)
+    .execute(&mut **tx)
+    .await?;
+
+    // トラベルモード中に同じ名前のカテゴリを作った場合、どちらかがコンパートメントだと統合できない
+    let conflicting_category: Option<String> = sqlx::query_scalar(
+        r#"
+        SELECT t.category_name
+        FROM temp.travel_restore_categories t
+        JOIN main.categories c ON c.category_name = t.category_name
+        WHERE t.sealed_id IN (SELECT category_id FROM sealed.compartments)
+        OR c.id IN (SELECT category_id FROM main.compartments)
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    if let Some(category_name) = conflicting_category {
+        return Err(AppError::Validation(vec![FieldError {
+            field: "categoryName".to_string(),
+            index: None,
+            message: format!(
+                "The category \"{}\" was created during travel mode; rename it before turning travel mode off",
+                category_name
+            ),
+        }]));
+    }
+
+    sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO main.categories (category_name)
+        SELECT category_name FROM temp.travel_restore_categories
+        "#,
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO main.compartments (category_id, salt, key_check, relock_minutes, created_at)
+        SELECT c.id, cp.salt, cp.key_check, cp.relock_minutes, cp.created_at
+        FROM temp.travel_restore_categories t
+        JOIN sealed.compartments cp ON cp.category_id = t.sealed_id
+        JOIN main.categories c ON c.category_name = t.category_name
+        "#,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    copy_sealed_rows(tx, "accounts", &format!("ulid IN ({})", RESTORE_ULIDS)).await?;
+    let by_account = format!("account_ulid IN ({})", RESTORE_ULIDS);
+    let by_identifier = format!(
+        "identifier_ulid IN (SELECT ulid FROM sealed.identifiers WHERE {})",
+        by_account
+    );
+    copy_sealed_rows(tx, "identifiers", &by_account).await?;
+    copy_sealed_rows(tx, "passwords", &by_identifier).await?;
+    copy_sealed_rows(tx, "password_history", &by_identifier).await?;
+    copy_sealed_rows(tx, "account_revisions", &by_account).await?;
+    copy_sealed_rows(tx, "sync_bases", &by_account).await?;
+    copy_sealed_rows(tx, "sync_conflicts", &by_account).await?;
+
+    // カテゴリのIDは戻したときに振り直されるため、名前で対応付ける
+    sqlx::query(&format!(
+        r#"
+        INSERT INTO main.account_categories (account_ulid, category_id)
+        SELECT ac.account_ulid, c.id
+        FROM sealed.account_categories ac
+        JOIN temp.travel_restore_categories t ON t.sealed_id = ac.category_id
+        JOIN main.categories c ON c.category_name = t.category_name
+        WHERE ac.{}
+        "#,
+        by_account
+    ))
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(&format!(
+        r#"
+        UPDATE main.account_revisions
+        SET compartment_id = (
+            SELECT c.id
+            FROM temp.travel_restore_categories t
+            JOIN main.categories c ON c.category_name = t.category_name
+            WHERE t.sealed_id = account_revisions.compartment_id
+        )
+        WHERE compartment_id IS NOT NULL
+        AND {}
+        "#,
+        by_account
+    ))
+    .execute(&mut **tx)
+    .await?;
+
+    for table in ["travel_restore_ulids", "travel_restore_categories"] {
+        sqlx::query(&format!("DROP TABLE temp.{}", table))
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(())
+}
+
+// スナップショットの行をそのまま戻す（IDは AUTOINCREMENT のため、削除後に作った行と重ならない）
+// スナップショットの後に追加された列は既定値のままにする
+async fn copy_sealed_rows(
+    tx: &mut Transaction<'_, Sqlite>,
+    table: &str,
+    condition: &str,
+) -> Result<()> {
+    let columns: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT name FROM pragma_table_info(?, 'sealed')
+        WHERE name IN (SELECT name FROM pragma_table_info(?, 'main'))
+        "#,
+    )
+    .bind(table)
+    .bind(table)
+    .fetch_all(&mut **tx)
+    .await?;
+    let columns = columns.join(", ");
+
+    sqlx::query(&format!(
+        "INSERT INTO main.{table} ({columns}) SELECT {columns} FROM sealed.{table} WHERE {condition}",
+    ))
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
@@ -1,17 +1,68 @@
+use crate::compartment::{account_key, compartment_key};
 use crate::crypto;
+use crate::error::{AppError, Result};
 use crate::models::{AccountInfo, FormData, FormDataField};
+use crate::repository::compartment::{get_account_compartment, rekey_account};
+use crate::repository::history::archive_password;
 use crate::repository::insert::insert_category;
+use crate::repository::read::get_account_info;
+use crate::repository::revision::{record_revision, RevisionOperation};
+use crate::validation::validate_form_data;
 use aes_gcm::{Aes256Gcm, Key};
-use anyhow::Result;
 use sqlx::{Sqlite, SqlitePool, Transaction};
+use tracing::instrument;
 
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
 pub async fn update_account_info(
     sqlite_pool: &SqlitePool,
     form_data: FormData,
     account_info: AccountInfo,
 ) -> Result<()> {
+    let form_data = validate_form_data(form_data)?;
+
     let mut tx = sqlite_pool.begin().await?;
 
+    // 画面が保持しているバージョンと異なる場合は上書きせずに最新の状態を返す
+    let current_version: Option<u32> = sqlx::query_scalar(
+        r#"
+        SELECT version FROM accounts WHERE ulid = ? AND deleted_at IS NULL
+        "#,
+    )
+    .bind(&account_info.account_ulid)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if current_version != Some(account_info.version) {
+        let current = match current_version {
+            Some(_) => get_account_info(&mut tx, &account_info.account_ulid).await?,
+            None => None,
+        };
+        return Err(AppError::Conflict {
+            current: current.map(Box::new),
+        });
+    }
+
+    if update_account_entry(&mut tx, &form_data, &account_info).await? {
+        record_revision(
+            &mut tx,
+            &account_info.account_ulid,
+            RevisionOperation::Update,
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// 差分のある項目のみ更新し、変更があったかどうかを返す
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn update_account_entry(
+    tx: &mut Transaction<'_, Sqlite>,
+    form_data: &FormData,
+    account_info: &AccountInfo,
+) -> Result<bool> {
     let old_form_data: FormData = account_info.clone().into();
     let differences = form_data.diff(&old_form_data);
 
@@ -24,45 +75,50 @@ pub async fn update_account_info(
                 match difference {
                     FormDataField::AccountName => {
                         // account_nameが変更された場合の処理
-                        update_account(
-                            &mut tx,
-                            &account_info.account_ulid,
-                            &form_data.account_name,
-                        )
-                        .await?;
+                        update_account(tx, &account_info.account_ulid, &form_data.account_name)
+                            .await?;
                     }
                     FormDataField::Identifier => {
                         // identifierが変更された場合の処理
-                        update_identifier(
-                            &mut tx,
-                            &account_info.identifier_ulid,
-                            &form_data.identifier,
-                        )
-                        .await?;
+                        update_identifier(tx, &account_info.identifier_ulid, &form_data.identifier)
+                            .await?;
                     }
                     FormDataField::Passwords => {
-                        // passwordsが変更された場合の処理
-                        let key: Key<Aes256Gcm> = crypto::get_encryption_key();
-                        update_passwords(&mut tx, &key, &form_data, &account_info).await?;
+                        // passwordsが変更された場合の処理（カテゴリの変更前のキーで暗号化する）
+                        let key: Key<Aes256Gcm> =
+                            account_key(tx, &account_info.account_ulid).await?;
+                        update_passwords(tx, &key, form_data, account_info).await?;
                     }
                     FormDataField::CategoryName => {
                         // category_nameが変更された場合の処理
-                        insert_category(&mut tx, &form_data.category_name).await?;
-                        update_category(
-                            &mut tx,
-                            &account_info.account_ulid,
-                            &form_data.category_name,
-                        )
-                        .await?;
+                        let old_compartment =
+                            get_account_compartment(tx, &account_info.account_ulid).await?;
+                        insert_category(tx, &form_data.category_name).await?;
+                        update_category(tx, &account_info.account_ulid, &form_data.category_name)
+                            .await?;
+                        let new_compartment =
+                            get_account_compartment(tx, &account_info.account_ulid).await?;
+
+                        // コンパートメントをまたいで移動する場合は、移動先のキーで暗号化し直す
+                        if old_compartment != new_compartment {
+                            let from_key = compartment_key(tx, old_compartment).await?;
+                            let to_key = compartment_key(tx, new_compartment).await?;
+                            rekey_account(
+                                tx,
+                                &account_info.account_ulid,
+                                &from_key,
+                                &to_key,
+                                new_compartment,
+                            )
+                            .await?;
+                        }
                     }
                 }
             }
         }
     }
 
-    tx.commit().await?;
-
-    Ok(())
+    Ok(!differences.is_empty())
 }
 
 async fn update_account(
@@ -105,6 +161,45 @@ async fn update_identifier(
     Ok(())
 }
 
+// 識別子のULIDを付け替える（パスワードとパスワード履歴は新しい識別子へ移す）
+#[instrument(level = "debug", skip_all, err(level = "debug"))]
+pub async fn replace_identifier_ulid(
+    tx: &mut Transaction<'_, Sqlite>,
+    identifier_ulid: &str,
+    new_identifier_ulid: &str,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO identifiers (ulid, account_ulid, identifier, created_at)
+        SELECT ?, account_ulid, identifier, created_at
+        FROM identifiers
+        WHERE ulid = ?
+        "#,
+    )
+    .bind(new_identifier_ulid)
+    .bind(identifier_ulid)
+    .execute(&mut **tx)
+    .await?;
+
+    for table in ["passwords", "password_history"] {
+        sqlx::query(&format!(
+            "UPDATE {} SET identifier_ulid = ? WHERE identifier_ulid = ?",
+            table
+        ))
+        .bind(new_identifier_ulid)
+        .bind(identifier_ulid)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    sqlx::query("DELETE FROM identifiers WHERE ulid = ?")
+        .bind(identifier_ulid)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
 async fn update_category(
     tx: &mut Transaction<'_, Sqlite>,
     account_ulid: &str,
@@ -147,6 +242,7 @@ async fn update_existing_passwords(
     for (new_password, old_password_info) in form_data.passwords.iter().zip(&account_info.passwords)
     {
         if new_password != &old_password_info.password_raw {
+            archive_password(tx, old_password_info.id).await?;
             let (encrypted_value, nonce) = crypto::encrypt_password(key, new_password)?;
             sqlx::query(
                 r#"
@@ -203,6 +299,7 @@ async fn delete_old_passwords(
 
     if old_len > new_len {
         for old_password_info in &account_info.passwords[new_len..] {
+            archive_password(tx, old_password_info.id).await?;
             sqlx::query(
                 r#"
                 DELETE FROM passwords
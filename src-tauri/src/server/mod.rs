@@ -0,0 +1,596 @@
+mod store;
+
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Path, Query, State};
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{post, put};
+use axum::{Json, Router};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::migrate::Migrator;
+use sqlx::SqlitePool;
+use std::env;
+use std::path::{Path as FsPath, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+
+use crate::database::create_pool;
+use crate::error::Result;
+use crate::models::{PullResponse, PushResponse, RegisterDeviceRequest};
+use crate::paths::get_data_dir;
+
+pub const SYNC_SERVER_ADDR_ENV_VAR: &str = "JASMIFY_SYNC_SERVER_ADDR";
+pub const SYNC_SERVER_DIR_ENV_VAR: &str = "JASMIFY_SYNC_SERVER_DIR";
+const DEFAULT_SYNC_SERVER_ADDR: &str = "127.0.0.1:8787";
+const SYNC_SERVER_DIR: &str = "sync_server";
+const SYNC_SERVER_DATABASE_FILE: &str = "server.sqlite";
+
+pub const DEVICE_HEADER: &str = "x-jasmify-device";
+pub const TIMESTAMP_HEADER: &str = "x-jasmify-timestamp";
+pub const SIGNATURE_HEADER: &str = "x-jasmify-signature";
+pub const NONCE_HEADER: &str = "x-jasmify-nonce";
+// 署名の時刻と受信時の時刻の許容差（秒、同じリクエストの再送は番号で拒否する）
+const MAX_CLOCK_SKEW_SECS: u64 = 300;
+const MAX_CHANGE_SET_SIZE: usize = 32 * 1024 * 1024;
+
+static SERVER_MIGRATOR: Migrator = sqlx::migrate!("./server_migrations");
+
+// 同期サーバーが返すエラー（本文は code と message の JSON）
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(&'static str),
+    Conflict(&'static str),
+    Internal(String),
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, code, message) = match self {
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, "bad_request", message),
+            ApiError::Unauthorized(message) => (
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                message.to_string(),
+            ),
+            ApiError::Conflict(message) => (StatusCode::CONFLICT, "conflict", message.to_string()),
+            ApiError::Internal(message) => {
+                tracing::error!(error = %message, "Internal error");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal",
+                    "Internal error".to_string(),
+                )
+            }
+        };
+
+        (status, Json(json!({ "code": code, "message": message }))).into_response()
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+// 署名の対象（メソッド・パスとクエリ・時刻・リクエストの番号・本文のハッシュ）
+pub fn signing_message(
+    method: &str,
+    path_and_query: &str,
+    timestamp: u64,
+    nonce: u64,
+    body: &[u8],
+) -> Vec<u8> {
+    format!(
+        "{}\n{}\n{}\n{}\n{}",
+        method,
+        path_and_query,
+        timestamp,
+        nonce,
+        hex::encode(Sha256::digest(body))
+    )
+    .into_bytes()
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+// 指定した長さのHEXを検証してバイト列に変換
+fn decode_hex<const N: usize>(value: &str, name: &str) -> Result<[u8; N], ApiError> {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| ApiError::BadRequest(format!("Invalid {}", name)))
+}
+
+fn check_vault_id(vault_id: &str) -> Result<(), ApiError> {
+    decode_hex::<16>(vault_id, "vault ID").map(|_| ())
+}
+
+// 登録済みの端末の公開鍵でリクエストの署名を検証し、端末IDを返す
+// （署名が正しくても、前回より番号が大きくないリクエストは再送として拒否する）
+async fn authenticate(
+    pool: &SqlitePool,
+    vault_id: &str,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<String, ApiError> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(ApiError::Unauthorized("Missing signature"))
+    };
+    let device_id = header(DEVICE_HEADER)?;
+    let timestamp: u64 = header(TIMESTAMP_HEADER)?
+        .parse()
+        .map_err(|_| ApiError::Unauthorized("Invalid timestamp"))?;
+    let nonce: i64 = header(NONCE_HEADER)?
+        .parse()
+        .ok()
+        .filter(|nonce| *nonce > 0)
+        .ok_or(ApiError::Unauthorized("Invalid nonce"))?;
+    let signature = decode_hex::<64>(header(SIGNATURE_HEADER)?, "signature")
+        .map_err(|_| ApiError::Unauthorized("Invalid signature"))?;
+
+    if unix_time().abs_diff(timestamp) > MAX_CLOCK_SKEW_SECS {
+        return Err(ApiError::Unauthorized("Request has expired"));
+    }
+
+    let public_key = store::get_device_key(pool, vault_id, device_id)
+        .await?
+        .ok_or(ApiError::Unauthorized("Unknown device"))?;
+    let verifying_key = decode_hex::<32>(&public_key, "public key")
+        .ok()
+        .and_then(|public_key| VerifyingKey::from_bytes(&public_key).ok())
+        .ok_or_else(|| ApiError::Internal("Stored public key is invalid".to_string()))?;
+
+    let path_and_query = uri
+        .path_and_query()
+        .map_or(uri.path(), |path_and_query| path_and_query.as_str());
+    let message = signing_message(
+        method.as_str(),
+        path_and_query,
+        timestamp,
+        nonce as u64,
+        body,
+    );
+    verifying_key
+        .verify(&message, &Signature::from_bytes(&signature))
+        .map_err(|_| ApiError::Unauthorized("Invalid signature"))?;
+
+    if !store::advance_nonce(pool, vault_id, device_id, nonce).await? {
+        return Err(ApiError::Unauthorized("Request has already been used"));
+    }
+
+    Ok(device_id.to_string())
+}
+
+// 端末を登録（保管庫の最初の端末の登録で、保管庫の認証用の値を記録する）
+async fn register_device(
+    State(pool): State<SqlitePool>,
+    Path(vault_id): Path<String>,
+    Json(request): Json<RegisterDeviceRequest>,
+) -> Result<StatusCode, ApiError> {
+    check_vault_id(&vault_id)?;
+    if ulid::Ulid::from_string(&request.device_id).is_err() {
+        return Err(ApiError::BadRequest("Invalid device ID".to_string()));
+    }
+    let public_key = decode_hex::<32>(&request.public_key, "public key")?;
+    if VerifyingKey::from_bytes(&public_key).is_err() {
+        return Err(ApiError::BadRequest("Invalid public key".to_string()));
+    }
+    let vault_auth = decode_hex::<32>(&request.vault_auth, "vault auth")?;
+    let auth_verifier = hex::encode(Sha256::digest(vault_auth));
+
+    store::register_device(
+        &pool,
+        &vault_id,
+        &auth_verifier,
+        &request.device_id,
+        &request.public_key,
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// 端末の最新の変更セットを保存（暗号化済みのため、サーバーは中身を扱わない）
+async fn push_change_set(
+    State(pool): State<SqlitePool>,
+    Path(vault_id): Path<String>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<PushResponse>, ApiError> {
+    check_vault_id(&vault_id)?;
+    let device_id = authenticate(&pool, &vault_id, &method, &uri, &headers, &body).await?;
+    if body.is_empty() {
+        return Err(ApiError::BadRequest("Change set is empty".to_string()));
+    }
+
+    let revision = store::put_change_set(&pool, &vault_id, &device_id, &body).await?;
+
+    Ok(Json(PushResponse { revision }))
+}
+
+#[derive(Deserialize)]
+struct PullQuery {
+    #[serde(default)]
+    since: i64,
+}
+
+// 前回の取得以降に更新された他の端末の変更セットを返す
+async fn pull_change_sets(
+    State(pool): State<SqlitePool>,
+    Path(vault_id): Path<String>,
+    Query(query): Query<PullQuery>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Json<PullResponse>, ApiError> {
+    check_vault_id(&vault_id)?;
+    let device_id = authenticate(&pool, &vault_id, &method, &uri, &headers, &[]).await?;
+
+    let (revision, change_sets) =
+        store::get_change_sets_since(&pool, &vault_id, &device_id, query.since).await?;
+
+    Ok(Json(PullResponse {
+        revision,
+        change_sets,
+    }))
+}
+
+pub fn router(pool: SqlitePool) -> Router {
+    Router::new()
+        .route("/v1/vaults/{vault_id}/devices", post(register_device))
+        .route(
+            "/v1/vaults/{vault_id}/change-sets",
+            put(push_change_set).get(pull_change_sets),
+        )
+        .layer(DefaultBodyLimit::max(MAX_CHANGE_SET_SIZE))
+        .with_state(pool)
+}
+
+// サーバーのデータベースを開き、未適用のマイグレーションを適用
+pub async fn open_server_database(server_dir: &FsPath) -> Result<SqlitePool> {
+    std::fs::create_dir_all(server_dir)?;
+    let database_url = format!(
+        "sqlite://{}",
+        server_dir.join(SYNC_SERVER_DATABASE_FILE).display()
+    );
+    let pool = create_pool(&database_url).await?;
+    SERVER_MIGRATOR.run(&pool).await?;
+
+    Ok(pool)
+}
+
+pub async fn serve(listener: TcpListener, pool: SqlitePool) -> Result<()> {
+    axum::serve(listener, router(pool)).await?;
+
+    Ok(())
+}
+
+// 環境変数の設定でサーバーを起動（既定では localhost のみで待ち受ける）
+pub async fn run_from_env() -> Result<()> {
+    let addr = env::var(SYNC_SERVER_ADDR_ENV_VAR)
+        .ok()
+        .filter(|addr| !addr.is_empty())
+        .unwrap_or_else(|| DEFAULT_SYNC_SERVER_ADDR.to_string());
+    let server_dir = env::var_os(SYNC_SERVER_DIR_ENV_VAR)
+        .filter(|server_dir| !server_dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| get_data_dir().join(SYNC_SERVER_DIR));
+
+    let pool = open_server_database(&server_dir).await?;
+    let listener = TcpListener::bind(&addr).await?;
+    tracing::info!(
+        addr = %listener.local_addr()?,
+        data = %server_dir.display(),
+        "Sync server listening"
+    );
+
+    serve(listener, pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use reqwest::Client;
+
+    const VAULT_ID: &str = "00112233445566778899aabbccddeeff";
+    const VAULT_AUTH: [u8; 32] = [9u8; 32];
+
+    #[test]
+    fn test_signing_message_covers_request() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let verifying_key = signing_key.verifying_key();
+        let message = signing_message("PUT", "/v1/vaults/00/change-sets", 1700000000, 1, b"blob");
+        let signature = signing_key.sign(&message);
+
+        assert!(verifying_key.verify(&message, &signature).is_ok());
+
+        // パス・時刻・番号・本文のいずれかが異なる場合は検証に失敗することを確認
+        for other in [
+            signing_message("PUT", "/v1/vaults/01/change-sets", 1700000000, 1, b"blob"),
+            signing_message("PUT", "/v1/vaults/00/change-sets", 1700000001, 1, b"blob"),
+            signing_message("PUT", "/v1/vaults/00/change-sets", 1700000000, 2, b"blob"),
+            signing_message("PUT", "/v1/vaults/00/change-sets", 1700000000, 1, b"blob2"),
+        ] {
+            assert!(verifying_key.verify(&other, &signature).is_err());
+        }
+    }
+
+    // テスト用の端末（ULID の端末IDと署名鍵）
+    struct TestDevice {
+        device_id: String,
+        signing_key: SigningKey,
+    }
+
+    impl TestDevice {
+        fn new(seed: u8) -> Self {
+            TestDevice {
+                device_id: ulid::Ulid::new().to_string(),
+                signing_key: SigningKey::from_bytes(&[seed; 32]),
+            }
+        }
+
+        fn register_request(&self, vault_auth: [u8; 32]) -> RegisterDeviceRequest {
+            RegisterDeviceRequest {
+                device_id: self.device_id.clone(),
+                public_key: hex::encode(self.signing_key.verifying_key().as_bytes()),
+                vault_auth: hex::encode(vault_auth),
+            }
+        }
+    }
+
+    // 一時ディレクトリのデータベースでサーバーを起動し、接続先のURLを返す
+    async fn start_server() -> String {
+        let server_dir = std::env::temp_dir().join(format!("sync_server_{}", ulid::Ulid::new()));
+        let pool = open_server_database(&server_dir).await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, pool));
+
+        format!("http://{}", addr)
+    }
+
+    async fn register(server_url: &str, request: &RegisterDeviceRequest) -> reqwest::StatusCode {
+        Client::new()
+            .post(format!("{}/v1/vaults/{}/devices", server_url, VAULT_ID))
+            .json(request)
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    // 指定した時刻と番号で署名したリクエストを送信
+    async fn send_signed(
+        server_url: &str,
+        device: &TestDevice,
+        method: reqwest::Method,
+        path_and_query: &str,
+        timestamp: u64,
+        nonce: u64,
+        body: &[u8],
+    ) -> reqwest::Response {
+        let signature = device.signing_key.sign(&signing_message(
+            method.as_str(),
+            path_and_query,
+            timestamp,
+            nonce,
+            body,
+        ));
+
+        Client::new()
+            .request(method, format!("{}{}", server_url, path_and_query))
+            .header(DEVICE_HEADER, &device.device_id)
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(NONCE_HEADER, nonce.to_string())
+            .header(SIGNATURE_HEADER, hex::encode(signature.to_bytes()))
+            .body(body.to_vec())
+            .send()
+            .await
+            .unwrap()
+    }
+
+    fn change_sets_path(since: Option<i64>) -> String {
+        match since {
+            Some(since) => format!("/v1/vaults/{}/change-sets?since={}", VAULT_ID, since),
+            None => format!("/v1/vaults/{}/change-sets", VAULT_ID),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_device() {
+        let server_url = start_server().await;
+        let device = TestDevice::new(1);
+
+        assert_eq!(
+            register(&server_url, &device.register_request(VAULT_AUTH)).await,
+            StatusCode::NO_CONTENT
+        );
+        // 同じ鍵での登録し直しは成功し、別の鍵での登録は拒否されることを確認
+        assert_eq!(
+            register(&server_url, &device.register_request(VAULT_AUTH)).await,
+            StatusCode::NO_CONTENT
+        );
+        let mut other_key = TestDevice::new(2).register_request(VAULT_AUTH);
+        other_key.device_id = device.device_id.clone();
+        assert_eq!(
+            register(&server_url, &other_key).await,
+            StatusCode::CONFLICT
+        );
+
+        // 保管庫のキーが異なる端末は追加できないことを確認
+        assert_eq!(
+            register(&server_url, &TestDevice::new(3).register_request([8u8; 32])).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_push_and_pull_change_sets() {
+        let server_url = start_server().await;
+        let laptop = TestDevice::new(1);
+        let desktop = TestDevice::new(2);
+        for device in [&laptop, &desktop] {
+            register(&server_url, &device.register_request(VAULT_AUTH)).await;
+        }
+
+        let response = send_signed(
+            &server_url,
+            &laptop,
+            reqwest::Method::PUT,
+            &change_sets_path(None),
+            unix_time(),
+            1,
+            b"sealed",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let pushed: PushResponse = response.json().await.unwrap();
+
+        // 他の端末の変更セットだけを取得し、取得済みの位置からは取得しないことを確認
+        let response = send_signed(
+            &server_url,
+            &desktop,
+            reqwest::Method::GET,
+            &change_sets_path(Some(0)),
+            unix_time(),
+            1,
+            b"",
+        )
+        .await;
+        let pulled: PullResponse = response.json().await.unwrap();
+        assert_eq!(pulled.revision, pushed.revision);
+        assert_eq!(pulled.change_sets.len(), 1);
+        assert_eq!(pulled.change_sets[0].device_id, laptop.device_id);
+        assert_eq!(pulled.change_sets[0].blob, hex::encode(b"sealed"));
+
+        let response = send_signed(
+            &server_url,
+            &laptop,
+            reqwest::Method::GET,
+            &change_sets_path(Some(0)),
+            unix_time(),
+            2,
+            b"",
+        )
+        .await;
+        let pulled: PullResponse = response.json().await.unwrap();
+        assert!(pulled.change_sets.is_empty());
+
+        let response = send_signed(
+            &server_url,
+            &desktop,
+            reqwest::Method::GET,
+            &change_sets_path(Some(pushed.revision)),
+            unix_time(),
+            2,
+            b"",
+        )
+        .await;
+        let pulled: PullResponse = response.json().await.unwrap();
+        assert!(pulled.change_sets.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_requests() {
+        let server_url = start_server().await;
+        let device = TestDevice::new(1);
+        register(&server_url, &device.register_request(VAULT_AUTH)).await;
+        let path = change_sets_path(None);
+
+        // 別の鍵で署名したリクエストは拒否されることを確認
+        let mut impostor = TestDevice::new(2);
+        impostor.device_id = device.device_id.clone();
+        let response = send_signed(
+            &server_url,
+            &impostor,
+            reqwest::Method::PUT,
+            &path,
+            unix_time(),
+            1,
+            b"sealed",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // 許容差を超えて古い時刻のリクエストは拒否されることを確認
+        let response = send_signed(
+            &server_url,
+            &device,
+            reqwest::Method::PUT,
+            &path,
+            unix_time() - MAX_CLOCK_SKEW_SECS - 1,
+            1,
+            b"sealed",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // 登録していない端末のリクエストは拒否されることを確認
+        let response = send_signed(
+            &server_url,
+            &TestDevice::new(3),
+            reqwest::Method::PUT,
+            &path,
+            unix_time(),
+            1,
+            b"sealed",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // 許容差の範囲内でも、同じリクエストの再送や番号の小さいリクエストは拒否されることを確認
+        let timestamp = unix_time();
+        let response = send_signed(
+            &server_url,
+            &device,
+            reqwest::Method::PUT,
+            &path,
+            timestamp,
+            5,
+            b"sealed",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        for nonce in [5, 4] {
+            let response = send_signed(
+                &server_url,
+                &device,
+                reqwest::Method::PUT,
+                &path,
+                timestamp,
+                nonce,
+                b"sealed",
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let response = send_signed(
+            &server_url,
+            &device,
+            reqwest::Method::PUT,
+            &path,
+            timestamp,
+            6,
+            b"sealed",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
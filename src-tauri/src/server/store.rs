@@ -0,0 +1,190 @@
+use sqlx::{Row, SqlitePool};
+
+use super::ApiError;
+use crate::models::StoredChangeSet;
+
+// 端末を登録（同じ端末IDに別の公開鍵を登録し直すことはできない）
+pub async fn register_device(
+    pool: &SqlitePool,
+    vault_id: &str,
+    auth_verifier: &str,
+    device_id: &str,
+    public_key: &str,
+) -> Result<(), ApiError> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO server_vaults (vault_id, auth_verifier)
+        VALUES (?, ?)
+        "#,
+    )
+    .bind(vault_id)
+    .bind(auth_verifier)
+    .execute(&mut *tx)
+    .await?;
+
+    let stored_verifier: String = sqlx::query_scalar(
+        r#"
+        SELECT auth_verifier FROM server_vaults WHERE vault_id = ?
+        "#,
+    )
+    .bind(vault_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if stored_verifier != auth_verifier {
+        return Err(ApiError::Unauthorized("Invalid vault auth"));
+    }
+
+    let stored_key: Option<String> = sqlx::query_scalar(
+        r#"
+        SELECT public_key FROM server_devices WHERE vault_id = ? AND device_id = ?
+        "#,
+    )
+    .bind(vault_id)
+    .bind(device_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    match stored_key {
+        Some(stored_key) if stored_key == public_key => {}
+        Some(_) => {
+            return Err(ApiError::Conflict(
+                "Device is already registered with another key",
+            ))
+        }
+        None => {
+            sqlx::query(
+                r#"
+                INSERT INTO server_devices (vault_id, device_id, public_key)
+                VALUES (?, ?, ?)
+                "#,
+            )
+            .bind(vault_id)
+            .bind(device_id)
+            .bind(public_key)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub async fn get_device_key(
+    pool: &SqlitePool,
+    vault_id: &str,
+    device_id: &str,
+) -> Result<Option<String>, ApiError> {
+    let public_key = sqlx::query_scalar(
+        r#"
+        SELECT public_key FROM server_devices WHERE vault_id = ? AND device_id = ?
+        "#,
+    )
+    .bind(vault_id)
+    .bind(device_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(public_key)
+}
+
+// 前回より大きい番号の場合のみ、最後に受け付けた番号を更新する（更新できた場合は true）
+pub async fn advance_nonce(
+    pool: &SqlitePool,
+    vault_id: &str,
+    device_id: &str,
+    nonce: i64,
+) -> Result<bool, ApiError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE server_devices SET last_nonce = ?
+        WHERE vault_id = ? AND device_id = ? AND last_nonce < ?
+        "#,
+    )
+    .bind(nonce)
+    .bind(vault_id)
+    .bind(device_id)
+    .bind(nonce)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+// 端末の変更セットを置き換え、新しい revision を返す（1つの文で採番と保存を行う）
+pub async fn put_change_set(
+    pool: &SqlitePool,
+    vault_id: &str,
+    device_id: &str,
+    blob: &[u8],
+) -> Result<i64, ApiError> {
+    let revision = sqlx::query_scalar(
+        r#"
+        INSERT INTO server_change_sets (vault_id, device_id, revision, blob)
+        VALUES (?, ?, (SELECT COALESCE(MAX(revision), 0) + 1 FROM server_change_sets), ?)
+        ON CONFLICT (vault_id, device_id) DO UPDATE SET
+            revision = excluded.revision,
+            blob = excluded.blob,
+            updated_at = CURRENT_TIMESTAMP
+        RETURNING revision
+        "#,
+    )
+    .bind(vault_id)
+    .bind(device_id)
+    .bind(blob)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(revision)
+}
+
+// since より後に更新された他の端末の変更セットと、保管庫の最新の revision を返す
+pub async fn get_change_sets_since(
+    pool: &SqlitePool,
+    vault_id: &str,
+    device_id: &str,
+    since: i64,
+) -> Result<(i64, Vec<StoredChangeSet>), ApiError> {
+    // 同じ時点の状態から取得するよう、読み取りもトランザクション内で行う
+    let mut tx = pool.begin().await?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT device_id, revision, blob
+        FROM server_change_sets
+        WHERE vault_id = ? AND revision > ? AND device_id != ?
+        ORDER BY revision
+        "#,
+    )
+    .bind(vault_id)
+    .bind(since)
+    .bind(device_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let latest_revision: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT MAX(revision) FROM server_change_sets WHERE vault_id = ?
+        "#,
+    )
+    .bind(vault_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let mut change_sets = Vec::new();
+    for row in rows {
+        let blob: Vec<u8> = row.try_get("blob")?;
+        change_sets.push(StoredChangeSet {
+            device_id: row.try_get("device_id")?,
+            revision: row.try_get("revision")?,
+            blob: hex::encode(blob),
+        });
+    }
+
+    Ok((latest_revision.unwrap_or(0).max(since), change_sets))
+}
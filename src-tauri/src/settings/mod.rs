@@ -0,0 +1,579 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::BTreeSet;
+use std::env;
+use std::io::ErrorKind;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use crate::crypto::write_file_atomically;
+use crate::error::{AppError, Result};
+use crate::models::{AppSettings, BackupSettings, FieldError, GeneratorSettings, VaultSettings};
+use crate::paths::get_data_dir;
+use crate::repository;
+
+const APP_SETTINGS_FILE: &str = "settings.json";
+
+// 以前のバージョンで設定に使っていた環境変数と、値を移す項目（backup.keep は backup の中の keep）
+const LEGACY_ENV_SETTINGS: [(&str, &str); 6] = [
+    ("JASMIFY_BACKUP_KEEP", "backup.keep"),
+    ("JASMIFY_BACKUP_EVERY_CHANGES", "backup.everyChanges"),
+    ("JASMIFY_BACKUP_INTERVAL_HOURS", "backup.intervalHours"),
+    ("JASMIFY_REMOTE_BACKUP_KEEP", "backup.remoteKeep"),
+    ("JASMIFY_TRASH_RETENTION_DAYS", "trashRetentionDays"),
+    ("JASMIFY_PASSWORD_HISTORY_LIMIT", "passwordHistoryLimit"),
+];
+
+const DEFAULT_AUTO_LOCK_MINUTES: u32 = 15;
+const DEFAULT_CLIPBOARD_CLEAR_SECONDS: u32 = 30;
+const DEFAULT_GENERATOR_LENGTH: u32 = 20;
+const DEFAULT_BACKUP_KEEP: u32 = 10;
+const DEFAULT_BACKUP_EVERY_CHANGES: u32 = 20;
+const DEFAULT_BACKUP_INTERVAL_HOURS: u32 = 24;
+const DEFAULT_REMOTE_BACKUP_KEEP: u32 = 30;
+const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+const DEFAULT_PASSWORD_HISTORY_LIMIT: u32 = 20;
+const DEFAULT_UI_LANGUAGE: &str = "en";
+
+const MAX_AUTO_LOCK_MINUTES: u32 = 24 * 60;
+const MAX_CLIPBOARD_CLEAR_SECONDS: u32 = 60 * 60;
+const GENERATOR_LENGTH_RANGE: RangeInclusive<u32> = 8..=128;
+const MAX_BACKUP_KEEP: u32 = 1000;
+const MAX_BACKUP_EVERY_CHANGES: u32 = 10000;
+const MAX_BACKUP_INTERVAL_HOURS: u32 = 24 * 365;
+const MAX_TRASH_RETENTION_DAYS: u32 = 3650;
+const MAX_PASSWORD_HISTORY_LIMIT: u32 = 1000;
+const UI_LANGUAGES: [&str; 2] = ["en", "ja"];
+
+impl Default for VaultSettings {
+    fn default() -> Self {
+        VaultSettings {
+            auto_lock_minutes: DEFAULT_AUTO_LOCK_MINUTES,
+            clipboard_clear_seconds: DEFAULT_CLIPBOARD_CLEAR_SECONDS,
+            generator: GeneratorSettings::default(),
+            backup: BackupSettings::default(),
+            trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+            password_history_limit: DEFAULT_PASSWORD_HISTORY_LIMIT,
+        }
+    }
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        GeneratorSettings {
+            length: DEFAULT_GENERATOR_LENGTH,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+        }
+    }
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings {
+            keep: DEFAULT_BACKUP_KEEP,
+            every_changes: DEFAULT_BACKUP_EVERY_CHANGES,
+            interval_hours: DEFAULT_BACKUP_INTERVAL_HOURS,
+            remote_keep: DEFAULT_REMOTE_BACKUP_KEEP,
+        }
+    }
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            ui_language: DEFAULT_UI_LANGUAGE.to_string(),
+        }
+    }
+}
+
+// 保管庫の設定を読み込む（保存されていない項目や不正な値の項目は既定値）
+pub async fn load_vault_settings(sqlite_pool: &SqlitePool) -> Result<VaultSettings> {
+    let mut conn = sqlite_pool.acquire().await?;
+    read_vault_settings(&mut conn).await
+}
+
+// トランザクションの中で設定を使う場合（パスワード履歴の保持件数など）
+pub async fn read_vault_settings(conn: &mut SqliteConnection) -> Result<VaultSettings> {
+    Ok(merge_settings(
+        &read_stored_settings(conn).await?,
+        validate_vault_settings,
+    ))
+}
+
+async fn read_stored_settings(conn: &mut SqliteConnection) -> Result<Map<String, Value>> {
+    let mut stored = Map::new();
+    for (name, value) in repository::settings::get_settings(conn).await? {
+        match serde_json::from_str(&value) {
+            Ok(value) => {
+                stored.insert(name, value);
+            }
+            Err(_) => tracing::warn!(setting = %name, "Stored setting is not valid JSON"),
+        }
+    }
+
+    Ok(stored)
+}
+
+// 保管庫の設定を検証して保存する
+// （このバージョンが知らない項目は、backup などの中の項目も含めて変更しない）
+pub async fn save_vault_settings(
+    sqlite_pool: &SqlitePool,
+    settings: &VaultSettings,
+) -> Result<VaultSettings> {
+    validate_vault_settings(settings)?;
+
+    let stored = {
+        let mut conn = sqlite_pool.acquire().await?;
+        read_stored_settings(&mut conn).await?
+    };
+    let values: Vec<(String, String)> = to_map(settings)?
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match (stored.get(&name), value) {
+                (Some(Value::Object(stored_object)), Value::Object(object)) => {
+                    let mut merged = stored_object.clone();
+                    merged.extend(object);
+                    Value::Object(merged)
+                }
+                (_, value) => value,
+            };
+            (name, value.to_string())
+        })
+        .collect();
+    repository::settings::save_settings(sqlite_pool, &values).await?;
+
+    Ok(settings.clone())
+}
+
+// 以前のバージョンで環境変数に設定していた値を、保管庫の設定に一度だけ移す（移した後は環境変数を読まない）
+pub async fn import_env_settings(sqlite_pool: &SqlitePool) -> Result<()> {
+    import_legacy_settings(sqlite_pool, |env_var| env::var(env_var).ok()).await
+}
+
+// 以前の環境変数の名前から値を取得して移す（設定の画面で保存済みの項目は上書きしない）
+async fn import_legacy_settings(
+    sqlite_pool: &SqlitePool,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<()> {
+    if repository::settings::is_env_settings_imported(sqlite_pool).await? {
+        return Ok(());
+    }
+
+    let mut stored = {
+        let mut conn = sqlite_pool.acquire().await?;
+        read_stored_settings(&mut conn).await?
+    };
+    let mut names = BTreeSet::new();
+    for (env_var, path) in LEGACY_ENV_SETTINGS {
+        let Some(value) = lookup(env_var).and_then(|value| value.trim().parse::<u32>().ok()) else {
+            continue;
+        };
+        if get_setting(&stored, path).is_none() {
+            tracing::info!(setting = %path, "Moving the setting from the environment variable");
+            set_setting(&mut stored, path, value.into());
+            names.insert(path.split_once('.').map_or(path, |(name, _)| name));
+        }
+    }
+
+    let values: Vec<(String, String)> = names
+        .into_iter()
+        .map(|name| (name.to_string(), stored[name].to_string()))
+        .collect();
+    repository::settings::save_env_settings(sqlite_pool, &values).await
+}
+
+fn get_app_settings_path() -> PathBuf {
+    get_data_dir().join(APP_SETTINGS_FILE)
+}
+
+// 設定ファイルの内容（ファイルがない場合や壊れている場合は空）
+fn read_app_settings_file() -> Result<Map<String, Value>> {
+    match std::fs::read_to_string(get_app_settings_path()) {
+        Ok(content) => Ok(serde_json::from_str(&content).unwrap_or_else(|_| {
+            tracing::warn!("The settings file is not a JSON object, using the defaults");
+            Map::new()
+        })),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Map::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn load_app_settings() -> Result<AppSettings> {
+    Ok(merge_settings(
+        &read_app_settings_file()?,
+        validate_app_settings,
+    ))
+}
+
+// アプリの設定を検証して保存する（このバージョンが知らない項目はファイルに残す）
+pub fn save_app_settings(settings: &AppSettings) -> Result<AppSettings> {
+    validate_app_settings(settings)?;
+
+    let mut content = read_app_settings_file()?;
+    content.extend(to_map(settings)?);
+    std::fs::create_dir_all(get_data_dir())?;
+    write_file_atomically(
+        &get_app_settings_path(),
+        serde_json::to_string_pretty(&content)?.as_bytes(),
+    )?;
+
+    Ok(settings.clone())
+}
+
+fn to_map<T: Serialize>(settings: &T) -> Result<Map<String, Value>> {
+    match serde_json::to_value(settings)? {
+        Value::Object(map) => Ok(map),
+        _ => Err(AppError::Internal(
+            "Settings must serialize to an object".to_string(),
+        )),
+    }
+}
+
+// "backup.keep" のような名前の項目の値（中の項目は1段まで）
+fn get_setting<'a>(map: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    match path.split_once('.') {
+        Some((name, field)) => map.get(name)?.as_object()?.get(field),
+        None => map.get(path),
+    }
+}
+
+fn set_setting(map: &mut Map<String, Value>, path: &str, value: Value) {
+    let Some((name, field)) = path.split_once('.') else {
+        map.insert(path.to_string(), value);
+        return;
+    };
+    let entry = map.entry(name).or_insert_with(|| Value::Object(Map::new()));
+    if !entry.is_object() {
+        *entry = Value::Object(Map::new());
+    }
+    if let Value::Object(object) = entry {
+        object.insert(field.to_string(), value);
+    }
+}
+
+// 既定値に保存されている項目を1つずつ重ね、読み込めない値や検証を通らない値の項目は既定値のままにする
+// 知らない項目（新しいバージョンで追加された項目など）は読み飛ばす
+fn merge_settings<T>(stored: &Map<String, Value>, validate: fn(&T) -> Result<()>) -> T
+where
+    T: Default + Serialize + DeserializeOwned,
+{
+    let defaults = T::default();
+    let Ok(mut merged) = to_map(&defaults) else {
+        return defaults;
+    };
+
+    for (name, value) in stored {
+        if !merged.contains_key(name) {
+            tracing::debug!(setting = %name, "Keeping unknown setting");
+            continue;
+        }
+        let mut candidate = merged.clone();
+        candidate.insert(name.clone(), value.clone());
+        match serde_json::from_value::<T>(Value::Object(candidate.clone())) {
+            Ok(settings) if validate(&settings).is_ok() => merged = candidate,
+            _ => tracing::warn!(setting = %name, "Invalid setting, using the default"),
+        }
+    }
+
+    serde_json::from_value(Value::Object(merged)).unwrap_or(defaults)
+}
+
+fn check_max(errors: &mut Vec<FieldError>, field: &str, value: u32, max: u32) {
+    if value > max {
+        errors.push(FieldError {
+            field: field.to_string(),
+            index: None,
+            message: format!("Must be {} or less", max),
+        });
+    }
+}
+
+fn validate_vault_settings(settings: &VaultSettings) -> Result<()> {
+    let mut errors = Vec::new();
+
+    check_max(
+        &mut errors,
+        "autoLockMinutes",
+        settings.auto_lock_minutes,
+        MAX_AUTO_LOCK_MINUTES,
+    );
+    check_max(
+        &mut errors,
+        "clipboardClearSeconds",
+        settings.clipboard_clear_seconds,
+        MAX_CLIPBOARD_CLEAR_SECONDS,
+    );
+
+    let generator = &settings.generator;
+    if !GENERATOR_LENGTH_RANGE.contains(&generator.length) {
+        errors.push(FieldError {
+            field: "generator.length".to_string(),
+            index: None,
+            message: format!(
+                "Must be between {} and {}",
+                GENERATOR_LENGTH_RANGE.start(),
+                GENERATOR_LENGTH_RANGE.end()
+            ),
+        });
+    }
+    if !(generator.lowercase || generator.uppercase || generator.digits || generator.symbols) {
+        errors.push(FieldError {
+            field: "generator".to_string(),
+            index: None,
+            message: "Select at least one kind of character".to_string(),
+        });
+    }
+
+    check_max(
+        &mut errors,
+        "backup.keep",
+        settings.backup.keep,
+        MAX_BACKUP_KEEP,
+    );
+    check_max(
+        &mut errors,
+        "backup.everyChanges",
+        settings.backup.every_changes,
+        MAX_BACKUP_EVERY_CHANGES,
+    );
+    check_max(
+        &mut errors,
+        "backup.intervalHours",
+        settings.backup.interval_hours,
+        MAX_BACKUP_INTERVAL_HOURS,
+    );
+    check_max(
+        &mut errors,
+        "backup.remoteKeep",
+        settings.backup.remote_keep,
+        MAX_BACKUP_KEEP,
+    );
+    check_max(
+        &mut errors,
+        "trashRetentionDays",
+        settings.trash_retention_days,
+        MAX_TRASH_RETENTION_DAYS,
+    );
+    check_max(
+        &mut errors,
+        "passwordHistoryLimit",
+        settings.password_history_limit,
+        MAX_PASSWORD_HISTORY_LIMIT,
+    );
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Validation(errors))
+    }
+}
+
+fn validate_app_settings(settings: &AppSettings) -> Result<()> {
+    if UI_LANGUAGES.contains(&settings.ui_language.as_str()) {
+        return Ok(());
+    }
+
+    Err(AppError::Validation(vec![FieldError {
+        field: "uiLanguage".to_string(),
+        index: None,
+        message: format!("Must be one of {}", UI_LANGUAGES.join(", ")),
+    }]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_pool;
+    use serde_json::json;
+
+    fn stored(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    async fn stored_value(sqlite_pool: &SqlitePool, name: &str) -> Value {
+        let value: String = sqlx::query_scalar("SELECT value FROM settings WHERE name = ?")
+            .bind(name)
+            .fetch_one(sqlite_pool)
+            .await
+            .unwrap();
+        serde_json::from_str(&value).unwrap()
+    }
+
+    #[test]
+    fn test_merge_settings() {
+        let settings: VaultSettings = merge_settings(
+            &stored(json!({
+                "autoLockMinutes": 5,
+                "passwordHistoryLimit": 5,
+                // 範囲外や型の違う値は既定値のまま
+                "clipboardClearSeconds": 999999,
+                "trashRetentionDays": "forever",
+                // 一部だけ保存されている場合は、残りの項目を既定値で補う
+                "generator": { "length": 32, "symbols": false },
+                "backup": { "keep": 3, "theme": "dark" },
+                // 知らない項目は読み飛ばす
+                "theme": "dark",
+            })),
+            validate_vault_settings,
+        );
+
+        let defaults = VaultSettings::default();
+        assert_eq!(settings.auto_lock_minutes, 5);
+        assert_eq!(settings.password_history_limit, 5);
+        assert_eq!(
+            settings.clipboard_clear_seconds,
+            defaults.clipboard_clear_seconds
+        );
+        assert_eq!(settings.trash_retention_days, defaults.trash_retention_days);
+        assert_eq!(settings.generator.length, 32);
+        assert!(!settings.generator.symbols);
+        assert!(settings.generator.lowercase);
+        assert_eq!(settings.backup.keep, 3);
+        assert_eq!(settings.backup.every_changes, defaults.backup.every_changes);
+        assert_eq!(settings.backup.remote_keep, defaults.backup.remote_keep);
+    }
+
+    #[test]
+    fn test_validate_vault_settings() {
+        assert!(validate_vault_settings(&VaultSettings::default()).is_ok());
+
+        let mut settings = VaultSettings {
+            generator: GeneratorSettings {
+                length: 4,
+                lowercase: false,
+                uppercase: false,
+                digits: false,
+                symbols: false,
+            },
+            password_history_limit: MAX_PASSWORD_HISTORY_LIMIT + 1,
+            ..VaultSettings::default()
+        };
+        settings.backup.keep = MAX_BACKUP_KEEP + 1;
+        settings.backup.remote_keep = MAX_BACKUP_KEEP + 1;
+        let Err(AppError::Validation(errors)) = validate_vault_settings(&settings) else {
+            panic!("invalid settings were accepted");
+        };
+        let fields: Vec<&str> = errors.iter().map(|error| error.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            [
+                "generator.length",
+                "generator",
+                "backup.keep",
+                "backup.remoteKeep",
+                "passwordHistoryLimit"
+            ]
+        );
+
+        let language = AppSettings {
+            ui_language: "fr".to_string(),
+        };
+        assert!(validate_app_settings(&language).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_setting_survives_round_trip() {
+        let sqlite_pool = test_pool().await;
+        repository::settings::save_settings(
+            &sqlite_pool,
+            &[
+                ("lockOnSleep".to_string(), json!(true).to_string()),
+                (
+                    "generator".to_string(),
+                    json!({ "length": 32, "pronounceable": true }).to_string(),
+                ),
+            ],
+        )
+        .await
+        .unwrap();
+
+        // 読み込んだ設定をそのまま保存しても、知らない項目は変わらずに残る
+        let settings = load_vault_settings(&sqlite_pool).await.unwrap();
+        save_vault_settings(&sqlite_pool, &settings).await.unwrap();
+
+        assert_eq!(load_vault_settings(&sqlite_pool).await.unwrap(), settings);
+        assert_eq!(stored_value(&sqlite_pool, "lockOnSleep").await, json!(true));
+        let generator = stored_value(&sqlite_pool, "generator").await;
+        assert_eq!(generator["length"], json!(32));
+        assert_eq!(generator["pronounceable"], json!(true));
+    }
+
+    #[tokio::test]
+    async fn test_save_vault_settings_keeps_unknown_settings() {
+        let sqlite_pool = test_pool().await;
+        repository::settings::save_settings(
+            &sqlite_pool,
+            &[
+                ("theme".to_string(), json!("dark").to_string()),
+                (
+                    "backup".to_string(),
+                    json!({ "keep": 3, "compress": true }).to_string(),
+                ),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let mut settings = load_vault_settings(&sqlite_pool).await.unwrap();
+        assert_eq!(settings.backup.keep, 3);
+        settings.backup.keep = 5;
+        settings.password_history_limit = 0;
+        save_vault_settings(&sqlite_pool, &settings).await.unwrap();
+
+        // 知らない項目は、backup の中の項目も含めて残る
+        assert_eq!(load_vault_settings(&sqlite_pool).await.unwrap(), settings);
+        assert_eq!(stored_value(&sqlite_pool, "theme").await, json!("dark"));
+        let backup = stored_value(&sqlite_pool, "backup").await;
+        assert_eq!(backup["keep"], json!(5));
+        assert_eq!(backup["compress"], json!(true));
+    }
+
+    #[tokio::test]
+    async fn test_import_legacy_settings_once() {
+        let sqlite_pool = test_pool().await;
+        // 設定の画面で保存済みの項目は環境変数で上書きしない
+        repository::settings::save_settings(
+            &sqlite_pool,
+            &[("backup".to_string(), json!({ "keep": 3 }).to_string())],
+        )
+        .await
+        .unwrap();
+
+        let env_vars = [
+            ("JASMIFY_BACKUP_KEEP", "7"),
+            ("JASMIFY_REMOTE_BACKUP_KEEP", "4"),
+            ("JASMIFY_PASSWORD_HISTORY_LIMIT", " 9 "),
+            ("JASMIFY_TRASH_RETENTION_DAYS", "forever"),
+        ];
+        let lookup = |name: &str| {
+            env_vars
+                .iter()
+                .find(|(env_var, _)| *env_var == name)
+                .map(|(_, value)| value.to_string())
+        };
+        import_legacy_settings(&sqlite_pool, lookup).await.unwrap();
+
+        let settings = load_vault_settings(&sqlite_pool).await.unwrap();
+        assert_eq!(settings.backup.keep, 3);
+        assert_eq!(settings.backup.remote_keep, 4);
+        assert_eq!(settings.password_history_limit, 9);
+        assert_eq!(
+            settings.trash_retention_days,
+            VaultSettings::default().trash_retention_days
+        );
+
+        // 移した後は値が変わっても読まない
+        import_legacy_settings(&sqlite_pool, |_| Some("1".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(load_vault_settings(&sqlite_pool).await.unwrap(), settings);
+    }
+}
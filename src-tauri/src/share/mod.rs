@@ -0,0 +1,247 @@
+use age::armor::{ArmoredWriter, Format};
+use age::x25519::{Identity, Recipient};
+use age::DecryptError;
+use sqlx::SqlitePool;
+use std::io::Write;
+use std::str::FromStr;
+
+use crate::error::{AppError, Result};
+use crate::models::{FieldError, FormData, SharePayload};
+use crate::repository;
+use crate::validation::validate_form_data;
+
+const SHARE_FORMAT: &str = "jasmify-share/1";
+
+fn field_error(field: &str, index: Option<usize>, message: &str) -> AppError {
+    AppError::Validation(vec![FieldError {
+        field: field.to_string(),
+        index,
+        message: message.to_string(),
+    }])
+}
+
+// 受け取る人の公開鍵（age1...）を検証して変換
+pub fn parse_recipients(recipients: &[String]) -> Result<Vec<Recipient>> {
+    if recipients.is_empty() {
+        return Err(field_error(
+            "recipients",
+            None,
+            "At least one recipient is required",
+        ));
+    }
+
+    recipients
+        .iter()
+        .enumerate()
+        .map(|(index, recipient)| {
+            Recipient::from_str(recipient.trim())
+                .map_err(|_| field_error("recipients", Some(index), "Invalid public key"))
+        })
+        .collect()
+}
+
+// 複数の公開鍵に暗号化し、テキストで受け渡せるよう ASCII armor 形式で返す
+pub fn encrypt_for_recipients(recipients: &[Recipient], plaintext: &[u8]) -> Result<String> {
+    let encryptor = age::Encryptor::with_recipients(
+        recipients
+            .iter()
+            .map(|recipient| recipient as &dyn age::Recipient),
+    )
+    .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let mut armored = Vec::new();
+    let mut writer = encryptor.wrap_output(ArmoredWriter::wrap_output(
+        &mut armored,
+        Format::AsciiArmor,
+    )?)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?.finish()?;
+
+    String::from_utf8(armored).map_err(|e| AppError::Internal(e.to_string()))
+}
+
+// 自分宛てでない場合は None（保管庫のキーの誤りではない）、壊れている場合はTampered
+pub fn decrypt_shared(identity: &Identity, shared: &str) -> Result<Option<Vec<u8>>> {
+    match age::decrypt(identity, shared.trim().as_bytes()) {
+        Ok(plaintext) => Ok(Some(plaintext)),
+        Err(DecryptError::NoMatchingKeys) => Ok(None),
+        Err(_) => Err(AppError::Tampered),
+    }
+}
+
+pub async fn get_share_public_key(sqlite_pool: &SqlitePool) -> Result<String> {
+    let identity = repository::share::get_share_identity(sqlite_pool).await?;
+
+    Ok(identity.to_public().to_string())
+}
+
+// 選択したアカウントを受け取る人の公開鍵に暗号化
+pub async fn export_shared_accounts(
+    sqlite_pool: &SqlitePool,
+    account_ulids: &[String],
+    recipients: &[String],
+) -> Result<String> {
+    let recipients = parse_recipients(recipients)?;
+    if account_ulids.is_empty() {
+        return Err(field_error(
+            "accountUlids",
+            None,
+            "Select at least one account",
+        ));
+    }
+
+    let mut tx = sqlite_pool.begin().await?;
+    let mut accounts = Vec::new();
+    for account_ulid in account_ulids {
+        let account_info = repository::read::get_account_info(&mut tx, account_ulid)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Account".to_string()))?;
+        accounts.push(FormData::from(account_info));
+    }
+    tx.commit().await?;
+
+    let payload = serde_json::to_vec(&SharePayload {
+        format: SHARE_FORMAT.to_string(),
+        accounts,
+    })?;
+
+    encrypt_for_recipients(&recipients, &payload)
+}
+
+// 自分宛てに共有されたアカウントを復号し、新しいアカウントとして登録（登録したULIDを返す）
+pub async fn import_shared_accounts(sqlite_pool: &SqlitePool, shared: &str) -> Result<Vec<String>> {
+    let identity = repository::share::get_share_identity(sqlite_pool).await?;
+    let plaintext = decrypt_shared(&identity, shared)?
+        .ok_or_else(|| field_error("shared", None, "Not addressed to this vault"))?;
+    let payload: SharePayload =
+        serde_json::from_slice(&plaintext).map_err(|_| AppError::Tampered)?;
+    if payload.format != SHARE_FORMAT {
+        return Err(field_error("shared", None, "Unsupported share format"));
+    }
+
+    // 一部だけ登録されないよう、先にすべての入力値をチェックする
+    let accounts = payload
+        .accounts
+        .into_iter()
+        .map(validate_form_data)
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut tx = sqlite_pool.begin().await?;
+    let mut account_ulids = Vec::new();
+    for form_data in &accounts {
+        account_ulids.push(repository::insert::insert_new_account_entry(&mut tx, form_data).await?);
+    }
+    tx.commit().await?;
+
+    Ok(account_ulids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_pool;
+    use crate::vault::with_test_vault;
+
+    fn form_data(account_name: &str, category_name: &str) -> FormData {
+        FormData {
+            account_name: account_name.to_string(),
+            identifier: "user@example.com".to_string(),
+            passwords: vec!["pass".to_string()],
+            category_name: category_name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_share_round_trip_for_each_recipient() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let eve = Identity::generate();
+
+        let shared =
+            encrypt_for_recipients(&[alice.to_public(), bob.to_public()], b"accounts").unwrap();
+        assert!(shared.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+
+        assert_eq!(
+            decrypt_shared(&alice, &shared).unwrap().unwrap(),
+            b"accounts"
+        );
+        assert_eq!(decrypt_shared(&bob, &shared).unwrap().unwrap(), b"accounts");
+        assert_eq!(decrypt_shared(&eve, &shared).unwrap(), None);
+        assert!(matches!(
+            decrypt_shared(&alice, "not an age file"),
+            Err(AppError::Tampered)
+        ));
+    }
+
+    #[test]
+    fn test_parse_recipients_reports_invalid_index() {
+        let valid = Identity::generate().to_public().to_string();
+
+        match parse_recipients(&[valid, "age1invalid".to_string()]) {
+            Err(AppError::Validation(errors)) => {
+                assert_eq!(errors[0].field, "recipients");
+                assert_eq!(errors[0].index, Some(1));
+            }
+            other => panic!("unexpected result: {:?}", other.map(|r| r.len())),
+        }
+        assert!(parse_recipients(&[]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_shared_accounts_is_all_or_nothing() {
+        with_test_vault(|_| async {
+            let sqlite_pool = test_pool().await;
+            let recipient = get_share_public_key(&sqlite_pool).await.unwrap();
+            let share = |accounts: Vec<FormData>| {
+                let payload = serde_json::to_vec(&SharePayload {
+                    format: SHARE_FORMAT.to_string(),
+                    accounts,
+                })
+                .unwrap();
+                let recipients = parse_recipients(std::slice::from_ref(&recipient)).unwrap();
+                encrypt_for_recipients(&recipients, &payload).unwrap()
+            };
+            let account_count = || async {
+                sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM accounts")
+                    .fetch_one(&sqlite_pool)
+                    .await
+                    .unwrap()
+            };
+
+            // 自分宛てでない場合は入力エラーになることを確認
+            let other = Identity::generate().to_public();
+            let not_addressed = encrypt_for_recipients(&[other], b"{}").unwrap();
+            match import_shared_accounts(&sqlite_pool, &not_addressed).await {
+                Err(AppError::Validation(errors)) => assert_eq!(errors[0].field, "shared"),
+                other => panic!("unexpected result: {:?}", other),
+            }
+
+            let shared = share(vec![form_data("Mail", "Web"), form_data("Bank", "Web")]);
+            let account_ulids = import_shared_accounts(&sqlite_pool, &shared).await.unwrap();
+            assert_eq!(account_ulids.len(), 2);
+            assert_eq!(account_count().await, 2);
+
+            // ロック中のコンパートメントのカテゴリを含む場合は、どのアカウントも登録しないことを確認
+            sqlx::query(
+                r#"
+                INSERT INTO categories (category_name) VALUES ('Private');
+                INSERT INTO compartments (category_id, salt, key_check, relock_minutes)
+                SELECT id, '00', '00:00', 5 FROM categories WHERE category_name = 'Private';
+                "#,
+            )
+            .execute(&sqlite_pool)
+            .await
+            .unwrap();
+            let shared = share(vec![
+                form_data("Shop", "Web"),
+                form_data("Diary", "Private"),
+            ]);
+            assert!(matches!(
+                import_shared_accounts(&sqlite_pool, &shared).await,
+                Err(AppError::CompartmentLocked(_))
+            ));
+            assert_eq!(account_count().await, 2);
+        })
+        .await;
+    }
+}
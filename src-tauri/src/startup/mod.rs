@@ -0,0 +1,194 @@
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::crypto::{get_key_file_path, AES_KEY_ENV_VAR};
+use crate::database::get_database_path;
+use crate::error::{AppError, Result};
+use crate::models::StartupError;
+use crate::paths::get_data_dir;
+use crate::repository::key_check::verify_encryption_key;
+use crate::vault::{current_vault_dir, scope_default_vault, VaultManager, DEFAULT_VAULT_ID};
+
+const STARTUP_LOG_FILE: &str = "startup-error.log";
+
+// 起動時に既定の保管庫を開けなかった原因（保管庫を開き直すまで、復旧画面に表示する）
+pub struct StartupState(Mutex<Option<StartupError>>);
+
+impl StartupState {
+    pub fn new(error: Option<StartupError>) -> Self {
+        StartupState(Mutex::new(error))
+    }
+
+    pub fn error(&self) -> Option<StartupError> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().take();
+    }
+}
+
+// 起動時に既定の保管庫を開き、キーが一致するかまで確認する
+pub async fn open_default_vault(vaults: &VaultManager) -> Result<()> {
+    let vault = vaults.open(DEFAULT_VAULT_ID).await?;
+    for migration in &vault.migration_report.applied {
+        tracing::info!(
+            version = migration.version,
+            description = %migration.description,
+            "Applied migration"
+        );
+    }
+    if let Some(backup_path) = &vault.migration_report.backup_path {
+        tracing::info!(%backup_path, "Database backup before migration");
+    }
+
+    vault.scope(verify_encryption_key(&vault.pool)).await
+}
+
+// 保管庫を開けなかった原因をログファイルに書き込み、復旧画面に表示する内容を返す
+// （保管庫の外で呼ぶため、パスは既定の保管庫のもの）
+pub fn report_startup_error(error: &AppError) -> StartupError {
+    let paths = scope_default_vault(|| -> Result<_> {
+        Ok((
+            current_vault_dir()?,
+            get_database_path()?,
+            get_key_file_path()?,
+        ))
+    });
+    let (vault_dir, database_path, key_file_path) = paths.unwrap_or_default();
+    let mut report = StartupError {
+        code: error.code().to_string(),
+        message: error.to_string(),
+        suggestion: startup_suggestion(error).to_string(),
+        data_dir: display_path(get_data_dir()),
+        vault_dir: display_path(&vault_dir),
+        database_path: display_path(&database_path),
+        key_file_path: display_path(&key_file_path),
+        log_path: None,
+        occurred_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default(),
+    };
+
+    tracing::error!(code = %report.code, error = %report.message, "Cannot open the vault at startup");
+    let text = format_startup_error(&report);
+    report.log_path = write_startup_log(&text).map(|path| display_path(&path));
+
+    report
+}
+
+// アプリ自体を起動できなかった場合（ウィンドウを作成できないなど）も、原因をログファイルに残す
+pub fn report_fatal_error(error: &dyn Display) {
+    let text = format!(
+        "=== Startup failed (unix time {}) ===\nerror: {}\n",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default(),
+        error
+    );
+    tracing::error!(%error, "Startup failed");
+    if let Some(path) = write_startup_log(&text) {
+        tracing::info!(log_path = %path.display(), "Startup error written to the startup log");
+    }
+}
+
+fn startup_suggestion(error: &AppError) -> &'static str {
+    match error {
+        AppError::VaultInUse => {
+            "Another copy of the app or a tool such as jasmify-doctor is using the vault. Close it and restart the app."
+        }
+        AppError::IncompatibleSchema(_) => {
+            "The vault was created by a newer version of the app. Update the app, or restore a backup made with this version."
+        }
+        AppError::Storage(_) => {
+            "The database could not be opened or is damaged. Check that the data directory is accessible and the disk is not full, then check the vault with jasmify-doctor or restore a backup."
+        }
+        AppError::WrongKey | AppError::Locked => {
+            "The key is missing or does not match the vault. Re-enter the key, or set the environment variable JASMIFY_AES_KEY to the correct key."
+        }
+        AppError::Tampered => "The vault data failed verification. Restore a backup.",
+        _ => "Open another vault or restore a backup. The details are in the log file.",
+    }
+}
+
+fn format_startup_error(report: &StartupError) -> String {
+    let key_note = if std::env::var(AES_KEY_ENV_VAR).is_ok() {
+        format!(" (not used: {} is set)", AES_KEY_ENV_VAR)
+    } else {
+        String::new()
+    };
+
+    format!(
+        "=== Startup failed (unix time {}) ===\n\
+         error: [{}] {}\n\
+         suggestion: {}\n\
+         data dir: {}\n\
+         vault dir: {}\n\
+         database: {}\n\
+         key file: {}{}\n",
+        report.occurred_at,
+        report.code,
+        report.message,
+        report.suggestion,
+        report.data_dir,
+        report.vault_dir,
+        report.database_path,
+        report.key_file_path,
+        key_note
+    )
+}
+
+// データディレクトリに書き込めない場合は一時ディレクトリに書き込む（書き込めたファイルのパスを返す）
+fn write_startup_log(text: &str) -> Option<PathBuf> {
+    [get_data_dir().to_path_buf(), std::env::temp_dir()]
+        .into_iter()
+        .map(|dir| dir.join(STARTUP_LOG_FILE))
+        .find(|path| append_log(path, text).is_ok())
+}
+
+fn append_log(path: &Path, text: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(text.as_bytes())
+}
+
+fn display_path(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_startup_error() {
+        let report = StartupError {
+            code: "vault_in_use".to_string(),
+            message: "Vault is in use by another process".to_string(),
+            suggestion: startup_suggestion(&AppError::VaultInUse).to_string(),
+            data_dir: "/data".to_string(),
+            vault_dir: "/data".to_string(),
+            database_path: "/data/database/db.sqlite".to_string(),
+            key_file_path: "/data/encrypted_key.hex".to_string(),
+            log_path: None,
+            occurred_at: 1700000000,
+        };
+
+        let text = format_startup_error(&report);
+        assert!(text.starts_with("=== Startup failed (unix time 1700000000) ===\n"));
+        assert!(text.contains("error: [vault_in_use] Vault is in use by another process\n"));
+        assert!(text.contains("suggestion: Another copy of the app"));
+        assert!(text.contains("database: /data/database/db.sqlite\n"));
+    }
+}
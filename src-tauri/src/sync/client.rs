@@ -0,0 +1,158 @@
+use ed25519_dalek::{Signer, SigningKey};
+use reqwest::{Client, Method, Response, Url};
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::crypto::{device_signing_seed, get_encryption_key, key_fingerprint, sync_server_secret};
+use crate::error::{AppError, Result};
+use crate::models::{PullResponse, PushResponse, RegisterDeviceRequest};
+use crate::server::{
+    signing_message, unix_time, DEVICE_HEADER, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+};
+
+pub const SYNC_SERVER_URL_ENV_VAR: &str = "JASMIFY_SYNC_SERVER_URL";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+// 前回のリクエストの番号（サーバーは前回より大きくない番号のリクエストを拒否する）
+static LAST_NONCE: AtomicU64 = AtomicU64::new(0);
+
+// 次のリクエストの番号（起動し直しても前回より大きくなるよう、現在時刻のマイクロ秒を下限にする）
+fn next_nonce() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_micros() as u64);
+    let next = |last: u64| (last + 1).max(now);
+    let last = LAST_NONCE
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(next(last)))
+        .unwrap_or_default();
+
+    next(last)
+}
+
+pub fn is_configured() -> bool {
+    env::var(SYNC_SERVER_URL_ENV_VAR).is_ok_and(|url| !url.is_empty())
+}
+
+// 同期サーバーのクライアント（保管庫はキーの識別子で指定し、リクエストには端末の鍵で署名する）
+pub struct SyncServerClient {
+    client: Client,
+    pub server_url: String,
+    vault_url: Url,
+    device_id: String,
+    signing_key: SigningKey,
+}
+
+impl SyncServerClient {
+    pub fn from_env(device_id: &str) -> Result<Self> {
+        let server_url = env::var(SYNC_SERVER_URL_ENV_VAR)
+            .ok()
+            .filter(|url| !url.is_empty())
+            .ok_or_else(|| AppError::NotFound("Sync server".to_string()))?;
+        let key = get_encryption_key()?;
+
+        let vault_url = Url::parse(&server_url)
+            .and_then(|url| {
+                url.join(&format!(
+                    "/v1/vaults/{}/",
+                    hex::encode(key_fingerprint(&key))
+                ))
+            })
+            .map_err(|e| AppError::Remote(format!("Invalid {}: {}", SYNC_SERVER_URL_ENV_VAR, e)))?;
+
+        Ok(SyncServerClient {
+            client: Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+            server_url,
+            vault_url,
+            device_id: device_id.to_string(),
+            signing_key: SigningKey::from_bytes(&device_signing_seed(&key, device_id)),
+        })
+    }
+
+    fn url(&self, path: &str) -> Result<Url> {
+        self.vault_url
+            .join(path)
+            .map_err(|e| AppError::Remote(e.to_string()))
+    }
+
+    // 端末を登録（登録済みの場合は何もしない）
+    pub async fn register(&self) -> Result<()> {
+        let key = get_encryption_key()?;
+        let request = RegisterDeviceRequest {
+            device_id: self.device_id.clone(),
+            public_key: hex::encode(self.signing_key.verifying_key().as_bytes()),
+            vault_auth: hex::encode(sync_server_secret(&key)),
+        };
+
+        let response = self
+            .client
+            .post(self.url("devices")?)
+            .json(&request)
+            .send()
+            .await?;
+        check_status(response).await?;
+
+        Ok(())
+    }
+
+    pub async fn pull(&self, since: i64) -> Result<PullResponse> {
+        let mut url = self.url("change-sets")?;
+        url.set_query(Some(&format!("since={}", since)));
+        let response = self.send(Method::GET, url, Vec::new()).await?;
+
+        Ok(response.json().await?)
+    }
+
+    pub async fn push(&self, blob: Vec<u8>) -> Result<PushResponse> {
+        let response = self
+            .send(Method::PUT, self.url("change-sets")?, blob)
+            .await?;
+
+        Ok(response.json().await?)
+    }
+
+    // 署名付きのリクエストを送信
+    async fn send(&self, method: Method, url: Url, body: Vec<u8>) -> Result<Response> {
+        let path_and_query = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let timestamp = unix_time();
+        let nonce = next_nonce();
+        let signature = self.signing_key.sign(&signing_message(
+            method.as_str(),
+            &path_and_query,
+            timestamp,
+            nonce,
+            &body,
+        ));
+
+        let response = self
+            .client
+            .request(method, url)
+            .header(DEVICE_HEADER, &self.device_id)
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(NONCE_HEADER, nonce.to_string())
+            .header(SIGNATURE_HEADER, hex::encode(signature.to_bytes()))
+            .body(body)
+            .send()
+            .await?;
+
+        check_status(response).await
+    }
+}
+
+// 失敗したレスポンスをエラーに変換（サーバーのメッセージを含める）
+async fn check_status(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    Err(AppError::Remote(format!(
+        "{} {}",
+        status,
+        body.chars().take(200).collect::<String>()
+    )))
+}
@@ -0,0 +1,502 @@
+pub mod client;
+
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use std::env;
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+
+use crate::crypto::{get_encryption_key, key_fingerprint, open_archive, run_crypto, seal_archive};
+use crate::error::{AppError, Result};
+use crate::models::{ChangeSet, SyncAccount, SyncReport};
+use crate::repository;
+use crate::travel;
+use crate::vault::current_vault_dir;
+use client::SyncServerClient;
+
+pub const SYNC_DIR_ENV_VAR: &str = "JASMIFY_SYNC_DIR";
+const DEVICE_ID_FILE: &str = "sync_device_id";
+const CHANGE_SET_EXTENSION: &str = "jsync";
+const CHANGE_SET_MAGIC: &[u8; 8] = b"JASMSYN1";
+const FINGERPRINT_LEN: usize = 16;
+const HEADER_LEN: usize = CHANGE_SET_MAGIC.len() + FINGERPRINT_LEN;
+
+// 同じ保管庫の同期を同時に行わないよう直列化する
+static SYNC_LOCK: Mutex<()> = Mutex::const_new(());
+
+// 項目単位でマージする対象（Deleted は片方で完全に削除された場合の競合に使う）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncField {
+    AccountName,
+    Identifier,
+    Passwords,
+    CategoryName,
+    Trashed,
+    Deleted,
+}
+
+const MERGE_FIELDS: [SyncField; 5] = [
+    SyncField::AccountName,
+    SyncField::Identifier,
+    SyncField::Passwords,
+    SyncField::CategoryName,
+    SyncField::Trashed,
+];
+
+impl SyncField {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncField::AccountName => "accountName",
+            SyncField::Identifier => "identifier",
+            SyncField::Passwords => "passwords",
+            SyncField::CategoryName => "categoryName",
+            SyncField::Trashed => "trashed",
+            SyncField::Deleted => "deleted",
+        }
+    }
+
+    pub fn parse(field: &str) -> Result<Self> {
+        [MERGE_FIELDS.as_slice(), &[SyncField::Deleted]]
+            .concat()
+            .into_iter()
+            .find(|f| f.as_str() == field)
+            .ok_or_else(|| AppError::Internal(format!("Unknown sync field: {}", field)))
+    }
+
+    // 項目の値（Deleted の場合はアカウント全体）
+    pub fn get(&self, account: &SyncAccount) -> Value {
+        match self {
+            SyncField::AccountName => json!(account.form_data.account_name),
+            SyncField::Identifier => json!(account.form_data.identifier),
+            SyncField::Passwords => json!(account.form_data.passwords),
+            SyncField::CategoryName => json!(account.form_data.category_name),
+            SyncField::Trashed => json!(account.trashed),
+            SyncField::Deleted => json!(account),
+        }
+    }
+
+    pub fn set(&self, account: &mut SyncAccount, value: Value) -> Result<()> {
+        match self {
+            SyncField::AccountName => {
+                account.form_data.account_name = serde_json::from_value(value)?
+            }
+            SyncField::Identifier => account.form_data.identifier = serde_json::from_value(value)?,
+            SyncField::Passwords => account.form_data.passwords = serde_json::from_value(value)?,
+            SyncField::CategoryName => {
+                account.form_data.category_name = serde_json::from_value(value)?
+            }
+            SyncField::Trashed => account.trashed = serde_json::from_value(value)?,
+            SyncField::Deleted => *account = serde_json::from_value(value)?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MergeAction {
+    Keep,
+    Insert(SyncAccount),
+    Update(SyncAccount),
+    Purge,
+}
+
+// 共通の状態（bases）を基準に、手元（local）と相手側（remote）を三方向マージする
+// - bases: 手元が前回取り込んだ相手側の状態と、相手側が前回取り込んだ手元の状態（None は完全に削除済み）
+// - local_purged: 手元で完全に削除済み（削除の記録が残っている）かどうか
+// 共通の状態から片方だけが変更した項目はその値を採用し、
+// 両方が異なる値に変更した項目は手元の値のまま、相手側の値を競合として返す
+pub fn merge_account(
+    bases: &[&Option<SyncAccount>],
+    local: Option<&SyncAccount>,
+    local_purged: bool,
+    remote: &Option<SyncAccount>,
+) -> (MergeAction, Vec<(SyncField, Value)>) {
+    // 共通の状態から相手側が変わっていない場合は、手元の状態を優先する
+    if bases.contains(&remote) {
+        return (MergeAction::Keep, Vec::new());
+    }
+
+    match (local, remote) {
+        (None, None) => (MergeAction::Keep, Vec::new()),
+        (None, Some(remote)) if local_purged => (
+            MergeAction::Keep,
+            vec![(SyncField::Deleted, SyncField::Deleted.get(remote))],
+        ),
+        (None, Some(remote)) => (MergeAction::Insert(remote.clone()), Vec::new()),
+        (Some(local), None) => {
+            if bases.iter().any(|base| base.as_ref() == Some(local)) {
+                (MergeAction::Purge, Vec::new())
+            } else {
+                (MergeAction::Keep, vec![(SyncField::Deleted, Value::Null)])
+            }
+        }
+        (Some(local), Some(remote)) => {
+            let bases: Vec<&SyncAccount> = bases.iter().filter_map(|base| base.as_ref()).collect();
+            let mut merged = local.clone();
+            let mut conflicts = Vec::new();
+
+            for field in MERGE_FIELDS {
+                let local_value = field.get(local);
+                let remote_value = field.get(remote);
+                if local_value == remote_value {
+                    continue;
+                }
+                let base_values: Vec<Value> = bases.iter().map(|base| field.get(base)).collect();
+                let local_changed = !base_values.contains(&local_value);
+                let remote_changed = !base_values.contains(&remote_value);
+
+                match (local_changed, remote_changed) {
+                    // 相手側だけが変更したため、相手側の値を取り込む
+                    (false, true) => {
+                        // get で得た値のため、同じ項目への設定は失敗しない
+                        field.set(&mut merged, remote_value).ok();
+                    }
+                    // 手元だけが変更したため、手元の値のまま
+                    (true, false) => {}
+                    _ => conflicts.push((field, remote_value)),
+                }
+            }
+
+            if merged == *local {
+                (MergeAction::Keep, conflicts)
+            } else {
+                (MergeAction::Update(merged), conflicts)
+            }
+        }
+    }
+}
+
+// この端末のID（DBではなく保管庫のディレクトリに保存するため、バックアップからDBを復元しても変わらない）
+// （保管庫のディレクトリごとコピーした場合は同じIDになるため、コピー先では sync_device_id を削除する）
+fn get_device_id() -> Result<String> {
+    let device_id_path = current_vault_dir()?.join(DEVICE_ID_FILE);
+    match std::fs::read_to_string(&device_id_path) {
+        Ok(device_id) if !device_id.trim().is_empty() => Ok(device_id.trim().to_string()),
+        Ok(_) => Err(AppError::Internal(
+            "Sync device ID file is empty".to_string(),
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let device_id = ulid::Ulid::new().to_string();
+            std::fs::write(&device_id_path, &device_id)?;
+            Ok(device_id)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// 変更セットを暗号化（同じキーの端末だけが読めるよう、キーの識別子をヘッダーに含める）
+pub async fn encode_change_set(change_set: &ChangeSet) -> Result<Vec<u8>> {
+    let key = get_encryption_key()?;
+    let header = [CHANGE_SET_MAGIC.as_slice(), &key_fingerprint(&key)].concat();
+    let payload = serde_json::to_vec(change_set)?;
+
+    let sealed = run_crypto({
+        let header = header.clone();
+        move || seal_archive(&key, &header, &payload)
+    })
+    .await?;
+
+    Ok([header, sealed].concat())
+}
+
+pub async fn decode_change_set(bytes: Vec<u8>) -> Result<ChangeSet> {
+    if bytes.len() < HEADER_LEN || !bytes.starts_with(CHANGE_SET_MAGIC) {
+        return Err(AppError::Tampered);
+    }
+    let key = get_encryption_key()?;
+    if bytes[CHANGE_SET_MAGIC.len()..HEADER_LEN] != key_fingerprint(&key) {
+        return Err(AppError::WrongKey);
+    }
+
+    let payload = run_crypto(move || {
+        let (header, sealed) = bytes.split_at(HEADER_LEN);
+        open_archive(&key, header, sealed)
+    })
+    .await?;
+
+    serde_json::from_slice(&payload).map_err(|_| AppError::Tampered)
+}
+
+// 共有フォルダ（環境変数 JASMIFY_SYNC_DIR）の下の、キーの識別子ごとのフォルダ
+// （端末ごとに保管庫のIDが異なっても、同じキーの保管庫どうしで同期する）
+fn get_sync_folder() -> Result<PathBuf> {
+    let sync_dir = env::var_os(SYNC_DIR_ENV_VAR)
+        .filter(|sync_dir| !sync_dir.is_empty())
+        .ok_or_else(|| AppError::NotFound("Sync folder".to_string()))?;
+    let key = get_encryption_key()?;
+
+    Ok(PathBuf::from(sync_dir).join(hex::encode(key_fingerprint(&key))))
+}
+
+// 同期サーバーが設定されている場合はサーバーと、そうでなければ共有フォルダを介して同期する
+pub async fn sync_vault(sqlite_pool: &SqlitePool) -> Result<SyncReport> {
+    let _guard = SYNC_LOCK.lock().await;
+
+    repository::key_check::verify_encryption_key(sqlite_pool).await?;
+    // トラベルモード中に同期すると、外したアカウントが他の端末から戻ってしまう
+    travel::ensure_not_travelling(sqlite_pool).await?;
+    let device_id = get_device_id()?;
+
+    if client::is_configured() {
+        sync_with_server(sqlite_pool, &device_id).await
+    } else {
+        sync_with_folder(sqlite_pool, &device_id, &get_sync_folder()?).await
+    }
+}
+
+// 他の端末の変更セットを復号して取り込む（中身の端末が異なる場合は置き換えられたものとみなす）
+// （壊れている・読めない変更セットはその端末だけを飛ばし、他の端末との同期は続ける）
+async fn import_change_set(
+    sqlite_pool: &SqlitePool,
+    device_id: &str,
+    peer_device_id: &str,
+    bytes: Vec<u8>,
+    report: &mut SyncReport,
+) -> Result<()> {
+    let change_set = match decode_change_set(bytes).await {
+        Ok(change_set) if change_set.device_id == peer_device_id => change_set,
+        Ok(_) | Err(AppError::Tampered | AppError::WrongKey) => {
+            tracing::warn!(peer_device_id, "Skipped an unreadable change set");
+            report.skipped_peers.push(peer_device_id.to_string());
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+
+    let (applied, conflicts) =
+        repository::sync::apply_change_set(sqlite_pool, device_id, &change_set).await?;
+    report.peers += 1;
+    report.applied += applied;
+    report.conflicts += conflicts;
+
+    Ok(())
+}
+
+// マージ後の状態を自分の変更セットとして暗号化
+async fn export_change_set(sqlite_pool: &SqlitePool, device_id: &str) -> Result<Vec<u8>> {
+    let change_set = ChangeSet {
+        device_id: device_id.to_string(),
+        records: repository::sync::export_records(sqlite_pool).await?,
+        bases: repository::sync::export_bases(sqlite_pool).await?,
+    };
+
+    encode_change_set(&change_set).await
+}
+
+// 共有フォルダにある他の端末の変更セットを取り込み、自分の変更セットを書き出す
+async fn sync_with_folder(
+    sqlite_pool: &SqlitePool,
+    device_id: &str,
+    sync_folder: &Path,
+) -> Result<SyncReport> {
+    std::fs::create_dir_all(sync_folder)?;
+
+    let mut report = SyncReport::default();
+    for entry in std::fs::read_dir(sync_folder)? {
+        let path = entry?.path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some(CHANGE_SET_EXTENSION) {
+            continue;
+        }
+        let Some(peer_device_id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if peer_device_id == device_id {
+            continue;
+        }
+
+        import_change_set(
+            sqlite_pool,
+            device_id,
+            peer_device_id,
+            std::fs::read(&path)?,
+            &mut report,
+        )
+        .await?;
+    }
+
+    let change_set_path = sync_folder.join(format!("{}.{}", device_id, CHANGE_SET_EXTENSION));
+    let temp_path = change_set_path.with_extension("tmp");
+    std::fs::write(&temp_path, export_change_set(sqlite_pool, device_id).await?)?;
+    std::fs::rename(&temp_path, &change_set_path)?;
+
+    Ok(report)
+}
+
+// 同期サーバーから前回以降に更新された他の端末の変更セットを取得して取り込み、自分の変更セットを送る
+async fn sync_with_server(sqlite_pool: &SqlitePool, device_id: &str) -> Result<SyncReport> {
+    let client = SyncServerClient::from_env(device_id)?;
+    client.register().await?;
+
+    let since = repository::sync::get_server_cursor(sqlite_pool, &client.server_url).await?;
+    let pulled = client.pull(since).await?;
+
+    let mut report = SyncReport::default();
+    for stored in pulled.change_sets {
+        // 16進数でない場合も、壊れた変更セットとして扱う
+        let bytes = hex::decode(&stored.blob).unwrap_or_default();
+        import_change_set(
+            sqlite_pool,
+            device_id,
+            &stored.device_id,
+            bytes,
+            &mut report,
+        )
+        .await?;
+    }
+
+    client
+        .push(export_change_set(sqlite_pool, device_id).await?)
+        .await?;
+    repository::sync::set_server_cursor(sqlite_pool, &client.server_url, pulled.revision).await?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_pool;
+    use crate::models::{FormData, SyncRecord};
+    use crate::vault::with_test_vault;
+    use std::collections::HashMap;
+
+    fn account(account_name: &str, passwords: &[&str]) -> SyncAccount {
+        SyncAccount {
+            identifier_ulid: "01JN0000000000000000000000".to_string(),
+            form_data: FormData {
+                account_name: account_name.to_string(),
+                identifier: "user@example.com".to_string(),
+                passwords: passwords.iter().map(|p| p.to_string()).collect(),
+                category_name: "Web".to_string(),
+            },
+            trashed: false,
+        }
+    }
+
+    #[test]
+    fn test_merge_account_takes_changes_from_both_sides() {
+        let base = Some(account("Mail", &["old"]));
+        let local = account("Mail (personal)", &["old"]);
+        let remote = Some(account("Mail", &["new"]));
+
+        let (action, conflicts) = merge_account(&[&base], Some(&local), false, &remote);
+
+        // 別々の項目の変更は両方とも取り込まれることを確認
+        assert_eq!(
+            action,
+            MergeAction::Update(account("Mail (personal)", &["new"]))
+        );
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_merge_account_uses_base_from_peer() {
+        // 手元は相手側の状態をまだ取り込んでいないが、相手側は手元の状態を取り込み済みの場合
+        let base_from_peer = Some(account("Mail", &["old"]));
+        let mut local = account("Mail", &["old"]);
+        local.form_data.identifier = "new@example.com".to_string();
+        let remote = Some(account("Mail", &["new"]));
+
+        let (action, conflicts) =
+            merge_account(&[&None, &base_from_peer], Some(&local), false, &remote);
+
+        let mut merged = account("Mail", &["new"]);
+        merged.form_data.identifier = "new@example.com".to_string();
+        assert_eq!(action, MergeAction::Update(merged));
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_merge_account_reports_conflicts() {
+        let base = Some(account("Mail", &["old"]));
+        let local = account("Mail", &["local"]);
+        let remote = Some(account("Mail", &["remote"]));
+
+        let (action, conflicts) = merge_account(&[&base], Some(&local), false, &remote);
+
+        // 同じ項目が両方で変更された場合は上書きせずに競合として返すことを確認
+        assert_eq!(action, MergeAction::Keep);
+        assert_eq!(conflicts, vec![(SyncField::Passwords, json!(["remote"]))]);
+
+        // 初めての同期で値が異なる場合も競合になることを確認
+        let (_, conflicts) = merge_account(&[], Some(&local), false, &remote);
+        assert_eq!(conflicts.len(), 1);
+    }
+
+    #[test]
+    fn test_merge_account_unchanged_remote_keeps_local() {
+        let base = Some(account("Mail", &["old"]));
+        let local = account("Mail", &["local"]);
+
+        let (action, conflicts) = merge_account(&[&base], Some(&local), false, &base);
+
+        assert_eq!(action, MergeAction::Keep);
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_merge_account_tombstones() {
+        let base = Some(account("Mail", &["old"]));
+        let local = account("Mail", &["old"]);
+
+        // 手元で変更していないアカウントは、相手側の削除に従うことを確認
+        let (action, _) = merge_account(&[&base], Some(&local), false, &None);
+        assert_eq!(action, MergeAction::Purge);
+
+        // 手元で変更したアカウントの削除は競合になることを確認
+        let changed = account("Mail", &["local"]);
+        let (action, conflicts) = merge_account(&[&base], Some(&changed), false, &None);
+        assert_eq!(action, MergeAction::Keep);
+        assert_eq!(conflicts, vec![(SyncField::Deleted, Value::Null)]);
+
+        // 手元で削除済みのアカウントは、相手側から復活させずに競合にすることを確認
+        let remote = Some(account("Mail", &["remote"]));
+        let (action, conflicts) = merge_account(&[&base], None, true, &remote);
+        assert_eq!(action, MergeAction::Keep);
+        assert_eq!(conflicts[0].0, SyncField::Deleted);
+
+        // 削除の記録がなければ新しいアカウントとして追加することを確認
+        let (action, _) = merge_account(&[], None, false, &remote);
+        assert_eq!(action, MergeAction::Insert(account("Mail", &["remote"])));
+    }
+
+    #[tokio::test]
+    async fn test_sync_with_folder_skips_unreadable_peer() {
+        with_test_vault(|vault_dir| async move {
+            let sync_folder = vault_dir.join("shared");
+            std::fs::create_dir_all(&sync_folder).unwrap();
+            let sqlite_pool = test_pool().await;
+            let change_set = ChangeSet {
+                device_id: "PEER_OK".to_string(),
+                records: vec![SyncRecord {
+                    account_ulid: "01JN0000000000000000000002".to_string(),
+                    state: Some(account("Mail", &["pass"])),
+                }],
+                bases: HashMap::new(),
+            };
+            std::fs::write(
+                sync_folder.join("PEER_OK.jsync"),
+                encode_change_set(&change_set).await.unwrap(),
+            )
+            .unwrap();
+            std::fs::write(sync_folder.join("PEER_BROKEN.jsync"), b"JASMSYN1 broken").unwrap();
+            // 別の端末のファイル名で置かれた変更セットも取り込まない
+            std::fs::write(
+                sync_folder.join("PEER_RENAMED.jsync"),
+                encode_change_set(&change_set).await.unwrap(),
+            )
+            .unwrap();
+
+            // 壊れた変更セットの端末だけを飛ばし、他の端末の変更は取り込むことを確認
+            let mut report = sync_with_folder(&sqlite_pool, "LOCAL", &sync_folder)
+                .await
+                .unwrap();
+            report.skipped_peers.sort();
+            assert_eq!(report.peers, 1);
+            assert_eq!(report.applied, 1);
+            assert_eq!(report.skipped_peers, ["PEER_BROKEN", "PEER_RENAMED"]);
+            assert!(sync_folder.join("LOCAL.jsync").exists());
+        })
+        .await;
+    }
+}
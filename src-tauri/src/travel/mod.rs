@@ -0,0 +1,180 @@
+use sqlx::pool::PoolConnection;
+use sqlx::{Connection, Sqlite, SqlitePool};
+use std::path::Path;
+use tokio::sync::Mutex;
+
+use crate::backup;
+use crate::crypto::{
+    derive_travel_key, generate_salt, get_encryption_key, open_archive, remove_file_securely,
+    run_crypto, seal_archive,
+};
+use crate::database::{remove_database_copies, scrub_database};
+use crate::error::{AppError, Result};
+use crate::models::{FieldError, TravelModeInfo};
+use crate::remote;
+use crate::repository;
+use crate::repository::key_check::verify_encryption_key;
+use crate::validation::{validate_category_names, validate_passphrase};
+use crate::vault::current_vault_dir;
+
+const SNAPSHOT_FILE: &str = "travel-snapshot.sqlite";
+const RESTORE_FILE: &str = "travel-restore.sqlite";
+
+// 封印したスナップショットの認証に含めるヘッダー（識別子 + ソルト）
+const SEAL_MAGIC: &[u8; 8] = b"JASMTRV1";
+
+// スナップショットや復元用のファイルを同時に書き込まないよう、トラベルモードの切り替えを直列化する
+static TRAVEL_LOCK: Mutex<()> = Mutex::const_new(());
+
+fn seal_header(salt: &[u8]) -> Vec<u8> {
+    [SEAL_MAGIC.as_slice(), salt].concat()
+}
+
+pub async fn get_travel_mode(sqlite_pool: &SqlitePool) -> Result<TravelModeInfo> {
+    Ok(
+        match repository::travel::get_travel_seal(sqlite_pool).await? {
+            Some(seal) => TravelModeInfo {
+                enabled: true,
+                safe_categories: seal.safe_categories,
+                enabled_at: Some(seal.created_at),
+            },
+            None => TravelModeInfo {
+                enabled: false,
+                safe_categories: Vec::new(),
+                enabled_at: None,
+            },
+        },
+    )
+}
+
+// トラベルモード中は、他の端末との同期など外したアカウントに関わる操作をさせない
+pub async fn ensure_not_travelling(sqlite_pool: &SqlitePool) -> Result<()> {
+    if repository::travel::is_travel_mode(sqlite_pool).await? {
+        return Err(AppError::TravelMode);
+    }
+
+    Ok(())
+}
+
+// 端末に残すカテゴリ以外のアカウントを、別のパスフレーズで封印したスナップショットに移して端末から削除する
+pub async fn enable_travel_mode(
+    sqlite_pool: &SqlitePool,
+    safe_categories: &[String],
+    passphrase: &str,
+) -> Result<TravelModeInfo> {
+    validate_passphrase(passphrase)?;
+    let mut safe_categories = validate_category_names("safeCategories", safe_categories)?;
+    safe_categories.sort();
+    safe_categories.dedup();
+
+    let _guard = TRAVEL_LOCK.lock().await;
+    ensure_not_travelling(sqlite_pool).await?;
+    // 保管庫と一致しないキーで封印すると戻せなくなるため、先に照合する
+    verify_encryption_key(sqlite_pool).await?;
+    // リモートのバックアップからも保管庫のキーで外したアカウントを復元できるため、残っている間は有効にしない
+    // （リモートのバックアップはこのアプリからは削除せず、保存先で削除してもらう）
+    if remote::is_configured() && !remote::list_remote_backups().await?.is_empty() {
+        return Err(AppError::Validation(vec![FieldError {
+            field: "remoteBackups".to_string(),
+            index: None,
+            message: "Delete the remote backups before turning on travel mode".to_string(),
+        }]));
+    }
+
+    let vault_key = get_encryption_key()?;
+    let salt = generate_salt();
+    let snapshot =
+        backup::take_snapshot(sqlite_pool, &current_vault_dir()?.join(SNAPSHOT_FILE)).await?;
+    let sealed = {
+        let passphrase = passphrase.to_string();
+        run_crypto(move || {
+            let key = derive_travel_key(&vault_key, &passphrase, &salt)?;
+            seal_archive(&key, &seal_header(&salt), &snapshot)
+        })
+        .await?
+    };
+
+    let mut tx = sqlite_pool.begin().await?;
+    repository::travel::insert_travel_seal(&mut tx, &hex::encode(salt), &sealed, &safe_categories)
+        .await?;
+    repository::travel::remove_travel_accounts(&mut tx, &safe_categories).await?;
+    tx.commit().await?;
+    // 削除した内容が空き領域や WAL に残らないようにする
+    scrub_database(sqlite_pool).await?;
+
+    // 以前のバックアップや退避したDBは保管庫のキーだけで外したアカウントを復元できるため削除する
+    backup::remove_all_backups().await?;
+    remove_database_copies()?;
+
+    get_travel_mode(sqlite_pool).await
+}
+
+// 封印したスナップショットから外したアカウントを戻し、トラベルモードを終了する
+pub async fn disable_travel_mode(
+    sqlite_pool: &SqlitePool,
+    passphrase: &str,
+) -> Result<TravelModeInfo> {
+    let _guard = TRAVEL_LOCK.lock().await;
+    let seal = repository::travel::get_travel_seal(sqlite_pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Travel mode".to_string()))?;
+
+    let vault_key = get_encryption_key()?;
+    let salt = hex::decode(&seal.salt).map_err(|_| AppError::Tampered)?;
+    let snapshot = {
+        let passphrase = passphrase.to_string();
+        run_crypto(move || {
+            let key = derive_travel_key(&vault_key, &passphrase, &salt)?;
+            open_archive(&key, &seal_header(&salt), &seal.sealed)
+        })
+        .await
+        .map_err(|_| {
+            AppError::Validation(vec![FieldError {
+                field: "passphrase".to_string(),
+                index: None,
+                message: "Incorrect passphrase".to_string(),
+            }])
+        })?
+    };
+
+    let restore_path = current_vault_dir()?.join(RESTORE_FILE);
+    std::fs::write(&restore_path, snapshot)?;
+
+    let mut conn = sqlite_pool.acquire().await?;
+    let result = restore_from_snapshot(&mut conn, &restore_path, &seal.safe_categories).await;
+    drop(conn);
+    // 平文のスナップショットは、戻せたかどうかにかかわらず削除する
+    remove_file_securely(&restore_path)?;
+    result?;
+
+    scrub_database(sqlite_pool).await?;
+
+    get_travel_mode(sqlite_pool).await
+}
+
+// 復号したスナップショットを sealed として接続し、外したアカウントを戻してトラベルモードの記録を削除する
+async fn restore_from_snapshot(
+    conn: &mut PoolConnection<Sqlite>,
+    restore_path: &Path,
+    safe_categories: &[String],
+) -> Result<()> {
+    sqlx::query("ATTACH DATABASE ? AS sealed")
+        .bind(restore_path.to_string_lossy().as_ref())
+        .execute(&mut **conn)
+        .await?;
+
+    let result = async {
+        let mut tx = conn.begin().await?;
+        repository::travel::restore_travel_accounts(&mut tx, safe_categories).await?;
+        repository::travel::delete_travel_seal(&mut tx).await?;
+        tx.commit().await?;
+        Ok::<(), AppError>(())
+    }
+    .await;
+
+    sqlx::query("DETACH DATABASE sealed")
+        .execute(&mut **conn)
+        .await?;
+
+    result
+}
@@ -0,0 +1,247 @@
+use crate::error::{AppError, Result};
+use crate::models::{FieldError, FormData};
+use unicode_normalization::UnicodeNormalization;
+
+const ACCOUNT_NAME_MAX_LEN: usize = 256;
+const IDENTIFIER_MAX_LEN: usize = 256;
+const CATEGORY_NAME_MAX_LEN: usize = 64;
+const PASSWORD_MAX_LEN: usize = 1024;
+const PASSWORDS_MAX_COUNT: usize = 32;
+const PASSPHRASE_MIN_LEN: usize = 8;
+const PASSPHRASE_MAX_LEN: usize = 1024;
+
+// 入力値を正規化してチェックし、正規化後のFormDataを返す（エラーは項目ごとのメッセージを持つ）
+pub fn validate_form_data(form_data: FormData) -> Result<FormData> {
+    let mut errors = Vec::new();
+
+    let account_name = normalize_text(
+        "accountName",
+        &form_data.account_name,
+        ACCOUNT_NAME_MAX_LEN,
+        &mut errors,
+    );
+    let identifier = normalize_text(
+        "identifier",
+        &form_data.identifier,
+        IDENTIFIER_MAX_LEN,
+        &mut errors,
+    );
+    let category_name = normalize_text(
+        "categoryName",
+        &form_data.category_name,
+        CATEGORY_NAME_MAX_LEN,
+        &mut errors,
+    );
+
+    if form_data.passwords.is_empty() {
+        errors.push(field_error(
+            "passwords",
+            None,
+            "At least one password is required",
+        ));
+    } else if form_data.passwords.len() > PASSWORDS_MAX_COUNT {
+        errors.push(field_error(
+            "passwords",
+            None,
+            &format!("Up to {} passwords can be registered", PASSWORDS_MAX_COUNT),
+        ));
+    }
+
+    // パスワードは入力されたとおりに使われるため、正規化や前後の空白除去はしない
+    for (index, password) in form_data.passwords.iter().enumerate() {
+        if password.is_empty() {
+            errors.push(field_error(
+                "passwords",
+                Some(index),
+                "Password cannot be empty",
+            ));
+        } else if password.chars().count() > PASSWORD_MAX_LEN {
+            errors.push(field_error(
+                "passwords",
+                Some(index),
+                &format!("Must be {} characters or fewer", PASSWORD_MAX_LEN),
+            ));
+        } else if password.chars().any(char::is_control) {
+            errors.push(field_error(
+                "passwords",
+                Some(index),
+                "Control characters are not allowed",
+            ));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(AppError::Validation(errors));
+    }
+
+    Ok(FormData {
+        account_name,
+        identifier,
+        passwords: form_data.passwords,
+        category_name,
+    })
+}
+
+// コンパートメントやトラベルモードのパスフレーズ（入力されたとおりに使うため正規化しない）
+pub fn validate_passphrase(passphrase: &str) -> Result<()> {
+    let len = passphrase.chars().count();
+    if len < PASSPHRASE_MIN_LEN {
+        return Err(AppError::Validation(vec![field_error(
+            "passphrase",
+            None,
+            &format!("Must be at least {} characters", PASSPHRASE_MIN_LEN),
+        )]));
+    }
+    if len > PASSPHRASE_MAX_LEN {
+        return Err(AppError::Validation(vec![field_error(
+            "passphrase",
+            None,
+            &format!("Must be {} characters or fewer", PASSPHRASE_MAX_LEN),
+        )]));
+    }
+
+    Ok(())
+}
+
+// カテゴリ名の一覧を登録時と同じように正規化してチェック
+pub fn validate_category_names(field: &str, category_names: &[String]) -> Result<Vec<String>> {
+    let mut errors = Vec::new();
+    let normalized = category_names
+        .iter()
+        .map(|category_name| {
+            normalize_text(field, category_name, CATEGORY_NAME_MAX_LEN, &mut errors)
+        })
+        .collect();
+
+    if !errors.is_empty() {
+        return Err(AppError::Validation(errors));
+    }
+
+    Ok(normalized)
+}
+
+// 前後の空白を除去してNFCに正規化し、必須・長さ・制御文字をチェック
+fn normalize_text(
+    field: &str,
+    value: &str,
+    max_len: usize,
+    errors: &mut Vec<FieldError>,
+) -> String {
+    let normalized: String = value.trim().nfc().collect();
+
+    if normalized.is_empty() {
+        errors.push(field_error(field, None, "This field is required"));
+    } else if normalized.chars().count() > max_len {
+        errors.push(field_error(
+            field,
+            None,
+            &format!("Must be {} characters or fewer", max_len),
+        ));
+    } else if normalized
+        .chars()
+        .any(|c| c.is_control() || is_bidi_control(c))
+    {
+        errors.push(field_error(
+            field,
+            None,
+            "Control characters are not allowed",
+        ));
+    }
+
+    normalized
+}
+
+// 表示順を入れ替えて別の名前に見せかけられる双方向制御文字
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+fn field_error(field: &str, index: Option<usize>, message: &str) -> FieldError {
+    FieldError {
+        field: field.to_string(),
+        index,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field_errors(form_data: FormData) -> Vec<FieldError> {
+        match validate_form_data(form_data) {
+            Err(AppError::Validation(errors)) => errors,
+            other => panic!("入力エラーになりませんでした: {:?}", other),
+        }
+    }
+
+    fn form_data() -> FormData {
+        FormData {
+            account_name: "Example".to_string(),
+            identifier: "user@example.com".to_string(),
+            passwords: vec!["password".to_string()],
+            category_name: "Web".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_validate_form_data_normalizes_text() {
+        let mut input = form_data();
+        input.account_name = "  Cafe\u{0301}  ".to_string();
+        input.passwords = vec![" pass ".to_string()];
+
+        let validated = validate_form_data(input).expect("検証に失敗しました");
+
+        // 前後の空白が除去され、NFCに正規化されることを確認
+        assert_eq!(validated.account_name, "Caf\u{00E9}");
+        // パスワードは変更されないことを確認
+        assert_eq!(validated.passwords, vec![" pass ".to_string()]);
+    }
+
+    #[test]
+    fn test_validate_form_data_required_fields() {
+        let input = FormData {
+            account_name: " ".to_string(),
+            identifier: String::new(),
+            passwords: Vec::new(),
+            category_name: String::new(),
+        };
+
+        let errors = field_errors(input);
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+
+        assert_eq!(
+            fields,
+            vec!["accountName", "identifier", "categoryName", "passwords"]
+        );
+    }
+
+    #[test]
+    fn test_validate_form_data_password_errors_have_index() {
+        let mut input = form_data();
+        input.passwords = vec![
+            "ok".to_string(),
+            String::new(),
+            "a".repeat(PASSWORD_MAX_LEN + 1),
+        ];
+
+        let errors = field_errors(input);
+        let indexes: Vec<Option<usize>> = errors.iter().map(|e| e.index).collect();
+
+        assert_eq!(indexes, vec![Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn test_validate_form_data_rejects_control_characters() {
+        let mut input = form_data();
+        input.identifier = "user\u{0000}@example.com".to_string();
+        input.category_name = "Web\u{202E}".to_string();
+
+        let errors = field_errors(input);
+
+        assert_eq!(errors.len(), 2);
+        assert!(errors
+            .iter()
+            .all(|e| e.message == "Control characters are not allowed"));
+    }
+}
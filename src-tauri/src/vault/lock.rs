@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::Duration;
+
+use crate::error::{AppError, Result};
+
+const LOCK_FILE: &str = "vault.lock";
+// アプリの再起動などで、前のプロセスが終了するのを待つ時間
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+const LOCK_RETRIES: u32 = 20;
+
+// 保管庫のディレクトリの排他ロック（OS のアドバイザリロックのため、プロセスが異常終了しても解放される）
+pub struct VaultLock {
+    _file: File,
+}
+
+// このプロセスで保持しているロック（保管庫を開き直す間も同じロックを使い続ける）
+fn held_locks() -> &'static Mutex<HashMap<PathBuf, Weak<VaultLock>>> {
+    static HELD_LOCKS: OnceLock<Mutex<HashMap<PathBuf, Weak<VaultLock>>>> = OnceLock::new();
+    HELD_LOCKS.get_or_init(Default::default)
+}
+
+// 保管庫のディレクトリをロックする（他のプロセスが使っている場合はVaultInUse）
+// キーファイルの作成や DB への書き込みが、他のアプリやツールと混ざらないようにする
+pub async fn lock_vault_dir(dir: &Path) -> Result<Arc<VaultLock>> {
+    std::fs::create_dir_all(dir)?;
+    let dir = dunce::canonicalize(dir)?;
+
+    let mut retries = 0;
+    loop {
+        match try_lock_vault_dir(&dir) {
+            Err(AppError::VaultInUse) if retries < LOCK_RETRIES => {
+                retries += 1;
+                tokio::time::sleep(LOCK_RETRY_INTERVAL).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn try_lock_vault_dir(dir: &Path) -> Result<Arc<VaultLock>> {
+    let mut held = held_locks().lock().unwrap();
+    if let Some(lock) = held.get(dir).and_then(Weak::upgrade) {
+        return Ok(lock);
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(LOCK_FILE))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => return Err(AppError::VaultInUse),
+        Err(TryLockError::Error(e)) => return Err(e.into()),
+    }
+
+    let lock = Arc::new(VaultLock { _file: file });
+    held.insert(dir.to_path_buf(), Arc::downgrade(&lock));
+    Ok(lock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_lock_vault_dir() {
+        let dir = std::env::temp_dir().join(format!("jasmify-lock-{}", ulid::Ulid::new()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dunce::canonicalize(&dir).unwrap();
+
+        // 同じプロセスでは同じロックを共有する
+        let lock = try_lock_vault_dir(&dir).unwrap();
+        let shared = try_lock_vault_dir(&dir).unwrap();
+        assert!(Arc::ptr_eq(&lock, &shared));
+        drop((lock, shared));
+
+        // 他のプロセスがロックしている場合（別に開いたファイルでロックして再現）
+        let other = File::options()
+            .write(true)
+            .open(dir.join(LOCK_FILE))
+            .unwrap();
+        other.lock().unwrap();
+        assert!(matches!(
+            try_lock_vault_dir(&dir),
+            Err(AppError::VaultInUse)
+        ));
+
+        other.unlock().unwrap();
+        assert!(try_lock_vault_dir(&dir).is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,584 @@
+pub mod lock;
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::Instrument;
+
+use crate::backup;
+use crate::compartment::lock_vault_compartments;
+use crate::crypto::{create_key_file, decode_key, get_key_file_path, AES_KEY_ENV_VAR};
+use crate::database;
+use crate::error::{AppError, Result};
+use crate::escrow;
+use crate::models::{FieldError, KeySource, MigrationReport, VaultInfo};
+use crate::paths::get_data_dir;
+use crate::remote;
+use crate::repository;
+use crate::settings;
+use lock::{lock_vault_dir, VaultLock};
+
+pub const DEFAULT_VAULT_ID: &str = "default";
+const DEFAULT_VAULT_NAME: &str = "Default";
+const VAULTS_DIR: &str = "vaults";
+const REGISTRY_FILE: &str = "vaults.json";
+const VAULT_NAME_MAX_LEN: usize = 64;
+// 自動バックアップが必要か確認する間隔
+const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+tokio::task_local! {
+    // コマンドの処理中に対象となっている保管庫（キーやDBの場所の解決に使う）
+    static CURRENT_VAULT: VaultContext;
+}
+
+#[derive(Debug, Clone)]
+struct VaultContext {
+    id: String,
+    dir: PathBuf,
+    key_source: KeySource,
+}
+
+// 保管庫一覧に保存する内容（dir はデータディレクトリからの相対パスも可）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct VaultEntry {
+    id: String,
+    name: String,
+    dir: PathBuf,
+    key_source: KeySource,
+}
+
+impl VaultEntry {
+    fn default_vault() -> Self {
+        VaultEntry {
+            id: DEFAULT_VAULT_ID.to_string(),
+            name: DEFAULT_VAULT_NAME.to_string(),
+            dir: PathBuf::new(),
+            key_source: KeySource::Default,
+        }
+    }
+
+    fn resolved_dir(&self) -> PathBuf {
+        get_data_dir().join(&self.dir)
+    }
+
+    fn context(&self) -> VaultContext {
+        VaultContext {
+            id: self.id.clone(),
+            dir: self.resolved_dir(),
+            key_source: self.key_source.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VaultRegistry {
+    vaults: Vec<VaultEntry>,
+}
+
+// 処理中の保管庫の情報（スコープの外で呼ばれた場合は、別の保管庫を対象にしないようエラーにする）
+fn current_vault<T>(f: impl FnOnce(&VaultContext) -> T) -> Result<T> {
+    CURRENT_VAULT
+        .try_with(f)
+        .map_err(|_| AppError::Internal("No vault is in scope".to_string()))
+}
+
+pub fn current_vault_id() -> Result<String> {
+    current_vault(|vault| vault.id.clone())
+}
+
+pub fn current_vault_dir() -> Result<PathBuf> {
+    current_vault(|vault| vault.dir.clone())
+}
+
+pub fn current_key_source() -> Result<KeySource> {
+    current_vault(|vault| vault.key_source.clone())
+}
+
+pub struct OpenVault {
+    pub id: String,
+    pub pool: SqlitePool,
+    pub migration_report: MigrationReport,
+    context: VaultContext,
+    // 開いている間（処理中のコマンドが参照している間も）ディレクトリのロックを保持する
+    _lock: Arc<VaultLock>,
+}
+
+impl OpenVault {
+    // この保管庫を対象として処理を実行（ログには保管庫のIDを付ける）
+    pub async fn scope<F: Future>(&self, f: F) -> F::Output {
+        CURRENT_VAULT
+            .scope(
+                self.context.clone(),
+                f.instrument(tracing::info_span!("vault", id = %self.id)),
+            )
+            .await
+    }
+}
+
+// 保管庫を開く前（起動時の失敗の報告など）に、既定の保管庫を対象として処理を実行
+pub fn scope_default_vault<R>(f: impl FnOnce() -> R) -> R {
+    CURRENT_VAULT.sync_scope(VaultEntry::default_vault().context(), f)
+}
+
+// 管理用のツールから、アプリで開いていない保管庫のディレクトリを対象として処理を実行
+// （キーは既定の取得元から取得する: 環境変数 JASMIFY_AES_KEY、なければ保管庫のキーファイル）
+pub async fn scope_dir<F: Future>(dir: &Path, f: F) -> F::Output {
+    let context = VaultContext {
+        id: dir.to_string_lossy().into_owned(),
+        dir: dir.to_path_buf(),
+        key_source: KeySource::Default,
+    };
+    CURRENT_VAULT.scope(context, f).await
+}
+
+// テスト用に、キーファイルを使う保管庫のディレクトリを対象として処理を実行（環境変数のキーは使わない）
+#[cfg(test)]
+pub async fn scope_test_dir<F: Future>(dir: &Path, f: F) -> F::Output {
+    let context = VaultContext {
+        id: dir.to_string_lossy().into_owned(),
+        dir: dir.to_path_buf(),
+        key_source: KeySource::File,
+    };
+    CURRENT_VAULT.scope(context, f).await
+}
+
+// テスト用に、キーファイルを作成した一時的な保管庫のディレクトリを対象として処理を実行し、終わったら削除する
+#[cfg(test)]
+pub async fn with_test_vault<F, Fut>(f: F) -> Fut::Output
+where
+    F: FnOnce(PathBuf) -> Fut,
+    Fut: Future,
+{
+    let dir = std::env::temp_dir().join(format!("vault_{}", ulid::Ulid::new()));
+    std::fs::create_dir_all(&dir).unwrap();
+    create_key_file(dir.join(crate::crypto::KEY_FILE)).unwrap();
+
+    let output = scope_test_dir(&dir, f(dir.clone())).await;
+    std::fs::remove_dir_all(&dir).ok();
+
+    output
+}
+
+// 開いている保管庫と、コマンドの対象となる保管庫を管理
+#[derive(Default)]
+pub struct VaultManager {
+    open_vaults: RwLock<HashMap<String, Arc<OpenVault>>>,
+    current: RwLock<Option<String>>,
+    registry_lock: Mutex<()>,
+}
+
+impl VaultManager {
+    pub fn current(&self) -> Result<Arc<OpenVault>> {
+        let current = self.current.read().unwrap();
+        current
+            .as_ref()
+            .and_then(|id| self.open_vaults.read().unwrap().get(id).cloned())
+            .ok_or_else(|| AppError::NotFound("Open vault".to_string()))
+    }
+
+    pub fn get(&self, vault_id: &str) -> Option<Arc<OpenVault>> {
+        self.open_vaults.read().unwrap().get(vault_id).cloned()
+    }
+
+    pub fn is_open(&self, vault_id: &str) -> bool {
+        self.open_vaults.read().unwrap().contains_key(vault_id)
+    }
+
+    pub async fn list(&self) -> Result<Vec<VaultInfo>> {
+        let registry = load_registry()?;
+        let current = self.current.read().unwrap().clone();
+
+        Ok(registry
+            .vaults
+            .into_iter()
+            .map(|entry| VaultInfo {
+                is_open: self.is_open(&entry.id),
+                is_current: current.as_deref() == Some(entry.id.as_str()),
+                dir: entry.resolved_dir().to_string_lossy().into_owned(),
+                id: entry.id,
+                name: entry.name,
+                key_source: entry.key_source,
+            })
+            .collect())
+    }
+
+    // 新しい保管庫を作成（開くのは open で行う）
+    pub async fn create(&self, name: &str, key_source: KeySource) -> Result<String> {
+        let name = validate_vault_name(name)?;
+        if let KeySource::Env { name } = &key_source {
+            if name.trim().is_empty() {
+                return Err(AppError::Validation(vec![FieldError {
+                    field: "keySource".to_string(),
+                    index: None,
+                    message: "Environment variable name is required".to_string(),
+                }]));
+            }
+        }
+
+        let _guard = self.registry_lock.lock().await;
+        let mut registry = load_registry()?;
+
+        let id = ulid::Ulid::new().to_string();
+        let entry = VaultEntry {
+            dir: Path::new(VAULTS_DIR).join(&id),
+            id: id.clone(),
+            name,
+            key_source,
+        };
+        std::fs::create_dir_all(entry.resolved_dir())?;
+        registry.vaults.push(entry);
+        save_registry(&registry)?;
+
+        Ok(id)
+    }
+
+    pub async fn rename(&self, vault_id: &str, name: &str) -> Result<()> {
+        let name = validate_vault_name(name)?;
+
+        let _guard = self.registry_lock.lock().await;
+        let mut registry = load_registry()?;
+
+        let entry = registry
+            .vaults
+            .iter_mut()
+            .find(|entry| entry.id == vault_id)
+            .ok_or_else(|| AppError::NotFound("Vault".to_string()))?;
+        entry.name = name;
+        save_registry(&registry)
+    }
+
+    // 保管庫を開いて対象に切り替える（開いている場合は切り替えのみ）
+    pub async fn open(&self, vault_id: &str) -> Result<Arc<OpenVault>> {
+        let _guard = self.registry_lock.lock().await;
+
+        let vault = match self.get(vault_id) {
+            Some(vault) => vault,
+            None => self.insert(open_vault(find_entry(vault_id)?).await?),
+        };
+
+        *self.current.write().unwrap() = Some(vault_id.to_string());
+
+        Ok(vault)
+    }
+
+    // 保管庫を閉じる（対象の保管庫を閉じた場合は、他に開いている保管庫に切り替える）
+    pub async fn close(&self, vault_id: &str) -> Result<()> {
+        let vault = self
+            .open_vaults
+            .write()
+            .unwrap()
+            .remove(vault_id)
+            .ok_or_else(|| AppError::NotFound("Open vault".to_string()))?;
+        self.switch_from(vault_id);
+
+        lock_vault_compartments(vault_id);
+        vault.pool.close().await;
+
+        Ok(())
+    }
+
+    // 保管庫を一度閉じてファイルを入れ替え、開き直す
+    // （入れ替えに失敗した場合は、途中まで入れ替えたファイルを開かないよう閉じたままにする）
+    pub async fn reopen_with<F>(&self, vault_id: &str, replace: F) -> Result<Arc<OpenVault>>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let _guard = self.registry_lock.lock().await;
+
+        let entry = find_entry(vault_id)?;
+        let vault = self
+            .open_vaults
+            .write()
+            .unwrap()
+            .remove(vault_id)
+            .ok_or_else(|| AppError::NotFound("Open vault".to_string()))?;
+        lock_vault_compartments(vault_id);
+        vault.pool.close().await;
+
+        let reopened = match CURRENT_VAULT.sync_scope(vault.context.clone(), replace) {
+            Ok(()) => open_vault(entry).await,
+            Err(e) => Err(e),
+        };
+        match reopened {
+            Ok(vault) => Ok(self.insert(vault)),
+            Err(e) => {
+                self.switch_from(vault_id);
+                Err(e)
+            }
+        }
+    }
+
+    // 開いているかに関わらず、一覧にある保管庫を対象として処理を実行
+    // （開けなかった保管庫のバックアップの一覧など、復旧用）
+    pub async fn scope_vault<T, F>(&self, vault_id: &str, f: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let context = find_entry(vault_id)?.context();
+        let _lock = lock_vault_dir(&context.dir).await?;
+
+        CURRENT_VAULT.scope(context, f).await
+    }
+
+    // 保管庫のファイルを入れ替えてから開き、対象に切り替える（開いている場合は reopen_with と同じ）
+    // 開けなかった保管庫をバックアップから復元する場合に使う
+    pub async fn open_replacing<F>(&self, vault_id: &str, replace: F) -> Result<Arc<OpenVault>>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let vault = if self.is_open(vault_id) {
+            self.reopen_with(vault_id, replace).await?
+        } else {
+            let _guard = self.registry_lock.lock().await;
+
+            let entry = find_entry(vault_id)?;
+            let context = entry.context();
+            let _lock = lock_vault_dir(&context.dir).await?;
+            CURRENT_VAULT.sync_scope(context, replace)?;
+            self.insert(open_vault(entry).await?)
+        };
+
+        *self.current.write().unwrap() = Some(vault_id.to_string());
+
+        Ok(vault)
+    }
+
+    // キーを入力し直してキーファイルを置き換え、保管庫を開いて対象に切り替える
+    // （キーが保管庫と一致しない場合はWrongKeyで、キーファイルは変更しない）
+    pub async fn replace_key(&self, vault_id: &str, hex_key: &str) -> Result<Arc<OpenVault>> {
+        let key = decode_key(hex_key)
+            .map_err(|_| key_error("Must be 64 hexadecimal characters".to_string()))?;
+
+        {
+            let _guard = self.registry_lock.lock().await;
+
+            let entry = find_entry(vault_id)?;
+            // 環境変数から取得する場合は、キーファイルを置き換えても使われない
+            let env_var_name = match &entry.key_source {
+                KeySource::Env { name } => Some(name.clone()),
+                KeySource::Default if env::var(AES_KEY_ENV_VAR).is_ok() => {
+                    Some(AES_KEY_ENV_VAR.to_string())
+                }
+                _ => None,
+            };
+            if let Some(name) = env_var_name {
+                return Err(key_error(format!(
+                    "The key is read from the environment variable {}. Set it and restart the app",
+                    name
+                )));
+            }
+
+            let context = entry.context();
+            let _lock = lock_vault_dir(&context.dir).await?;
+            let open_pool = self.get(vault_id).map(|vault| vault.pool.clone());
+            CURRENT_VAULT
+                .scope(context.clone(), async {
+                    let matches = match open_pool {
+                        Some(pool) => repository::key_check::key_matches(&pool, &key).await?,
+                        // DBがまだない場合は、入力したキーで作成する
+                        None if !database::get_database_path()?.exists() => true,
+                        None => {
+                            let pool = database::open_read_only(&context.dir).await?;
+                            let matches = repository::key_check::key_matches(&pool, &key).await;
+                            pool.close().await;
+                            matches?
+                        }
+                    };
+                    if !matches {
+                        return Err(AppError::WrongKey);
+                    }
+                    database::replace_key_file(hex_key)
+                })
+                .await?;
+        }
+
+        let vault = self.open(vault_id).await?;
+        vault
+            .scope(repository::key_check::verify_encryption_key(&vault.pool))
+            .await?;
+
+        Ok(vault)
+    }
+
+    // 閉じた保管庫が対象の場合は、他に開いている保管庫に切り替える
+    fn switch_from(&self, vault_id: &str) {
+        let mut current = self.current.write().unwrap();
+        if current.as_deref() == Some(vault_id) {
+            *current = self.open_vaults.read().unwrap().keys().next().cloned();
+        }
+    }
+
+    fn insert(&self, vault: OpenVault) -> Arc<OpenVault> {
+        let vault = Arc::new(vault);
+        self.open_vaults
+            .write()
+            .unwrap()
+            .insert(vault.id.clone(), vault.clone());
+        spawn_backup_scheduler(Arc::downgrade(&vault));
+        vault
+    }
+}
+
+// 保管庫を開いている間、定期的に自動バックアップが必要か確認する
+// （保管庫を閉じると DB も閉じられるため、そこで終了する）
+fn spawn_backup_scheduler(vault: Weak<OpenVault>) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(BACKUP_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(vault) = vault.upgrade() else {
+                break;
+            };
+            if vault.pool.is_closed() {
+                break;
+            }
+            // キーがない場合は次の確認まで待つ（各操作の際にエラーとして通知される）
+            let result = vault
+                .scope(async {
+                    // 自動でバックアップした場合は、リモートの保存先にもアップロード
+                    if backup::run_scheduled_backup(&vault.pool).await?.is_some()
+                        && remote::is_configured()
+                    {
+                        remote::push_backups(&vault.pool).await?;
+                    }
+                    Ok(())
+                })
+                .await;
+            match result {
+                Ok(_) | Err(AppError::Locked) | Err(AppError::WrongKey) => {}
+                Err(e) => tracing::error!(vault = %vault.id, error = %e, "Scheduled backup failed"),
+            }
+        }
+    });
+}
+
+fn find_entry(vault_id: &str) -> Result<VaultEntry> {
+    load_registry()?
+        .vaults
+        .into_iter()
+        .find(|entry| entry.id == vault_id)
+        .ok_or_else(|| AppError::NotFound("Vault".to_string()))
+}
+
+// DBを開いてマイグレーションを適用し、キーの作成と照合まで行う
+async fn open_vault(entry: VaultEntry) -> Result<OpenVault> {
+    let context = entry.context();
+    // 他のアプリやツールとキーの作成や書き込みが混ざらないよう、最初にロックする
+    let lock = lock_vault_dir(&context.dir).await?;
+
+    let (pool, migration_report) = CURRENT_VAULT
+        .scope(context.clone(), async {
+            let (pool, migration_report) = database::setup_database().await?;
+            // 以前のバージョンで環境変数に設定していた値を設定に移す（ゴミ箱の保持期間などに使うため先に行う）
+            settings::import_env_settings(&pool).await?;
+            // 保持期間を過ぎたゴミ箱のアカウントを完全に削除（失敗しても保管庫は開き、次に開く際に再度削除する）
+            let purged = async {
+                let settings = settings::load_vault_settings(&pool).await?;
+                repository::trash::purge_expired_trash(&pool, settings.trash_retention_days).await
+            }
+            .await;
+            if let Err(e) = purged {
+                tracing::warn!(error = %e, "Purging the expired trash failed");
+            }
+            database::truncate_wal(&pool).await?;
+
+            // キーの取得元がファイルで、キーファイルが存在しない場合、キーを作成
+            let key_file_path = get_key_file_path()?;
+            let create_key = match &entry.key_source {
+                KeySource::Default => env::var(AES_KEY_ENV_VAR).is_err(),
+                KeySource::File => true,
+                KeySource::Env { .. } => false,
+            };
+            if create_key && !key_file_path.exists() {
+                create_key_file(key_file_path)?;
+            }
+
+            // キーが一致しない場合も開きはし、各操作でWrongKeyを返す
+            match repository::key_check::create_key_check_if_missing(&pool).await {
+                Ok(()) => {
+                    // 管理者の公開鍵の設定が誤っていても、保管庫は開けるようにする
+                    if let Err(e) = escrow::enroll_from_env(&pool).await {
+                        tracing::warn!(error = %e, "Key escrow enrollment failed");
+                    }
+                }
+                Err(AppError::WrongKey) | Err(AppError::Locked) => {}
+                Err(e) => return Err(e),
+            }
+
+            Ok((pool, migration_report))
+        })
+        .await?;
+
+    Ok(OpenVault {
+        id: entry.id,
+        pool,
+        migration_report,
+        context,
+        _lock: lock,
+    })
+}
+
+fn key_error(message: String) -> AppError {
+    AppError::Validation(vec![FieldError {
+        field: "key".to_string(),
+        index: None,
+        message,
+    }])
+}
+
+fn validate_vault_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    let message = if name.is_empty() {
+        "This field is required".to_string()
+    } else if name.chars().count() > VAULT_NAME_MAX_LEN {
+        format!("Must be {} characters or fewer", VAULT_NAME_MAX_LEN)
+    } else if name.chars().any(char::is_control) {
+        "Control characters are not allowed".to_string()
+    } else {
+        return Ok(name.to_string());
+    };
+
+    Err(AppError::Validation(vec![FieldError {
+        field: "name".to_string(),
+        index: None,
+        message,
+    }]))
+}
+
+fn get_registry_path() -> PathBuf {
+    get_data_dir().join(REGISTRY_FILE)
+}
+
+// 保管庫一覧を読み込む（既定の保管庫は一覧になくても常に含める）
+fn load_registry() -> Result<VaultRegistry> {
+    let mut registry: VaultRegistry = match std::fs::read_to_string(get_registry_path()) {
+        Ok(registry) => serde_json::from_str(&registry)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => VaultRegistry::default(),
+        Err(e) => return Err(e.into()),
+    };
+
+    if !registry
+        .vaults
+        .iter()
+        .any(|entry| entry.id == DEFAULT_VAULT_ID)
+    {
+        registry.vaults.insert(0, VaultEntry::default_vault());
+    }
+
+    Ok(registry)
+}
+
+fn save_registry(registry: &VaultRegistry) -> Result<()> {
+    std::fs::create_dir_all(get_data_dir())?;
+    std::fs::write(get_registry_path(), serde_json::to_string_pretty(registry)?)?;
+
+    Ok(())
+}
@@ -1,12 +1,27 @@
 import { z } from "zod";
 
 export const formSchema = z.object({
-  accountName: z.string().min(1, "This field is required"),
-  identifier: z.string().min(1, "This field is required"),
+  accountName: z
+    .string()
+    .min(1, "This field is required")
+    .max(256, "Must be 256 characters or fewer"),
+  identifier: z
+    .string()
+    .min(1, "This field is required")
+    .max(256, "Must be 256 characters or fewer"),
   passwords: z
-    .array(z.string().min(1, "Password cannot be empty"))
-    .min(1, "At least one password is required"),
-  categoryName: z.string().min(1, "This field is required"),
+    .array(
+      z
+        .string()
+        .min(1, "Password cannot be empty")
+        .max(1024, "Must be 1024 characters or fewer")
+    )
+    .min(1, "At least one password is required")
+    .max(32, "Up to 32 passwords can be registered"),
+  categoryName: z
+    .string()
+    .min(1, "This field is required")
+    .max(64, "Must be 64 characters or fewer"),
 });
 
 export type FormData = z.infer<typeof formSchema>;
@@ -17,6 +32,7 @@ export interface AccountSummary {
   identifierUlid: string;
   identifier: string;
   categoryName: string;
+  version: number;
 }
 
 export interface PasswordInfo {
@@ -37,4 +53,264 @@ export interface AccountInfo {
   identifier: string;
   passwords: PasswordInfo[];
   categoryName: string;
+  version: number;
+}
+
+export interface PasswordHistory {
+  id: number;
+  passwordId: number;
+  setAt: string | null;
+  replacedAt: string;
+}
+
+export interface AccountRevision {
+  id: number;
+  accountUlid: string;
+  operation: "insert" | "update" | "delete" | "restore" | "revert" | "sync";
+  accountName: string | null;
+  undone: boolean;
+  createdAt: string;
+}
+
+export interface TrashedAccount {
+  accountUlid: string;
+  accountName: string;
+  identifier: string;
+  categoryName: string;
+  deletedAt: string;
+}
+
+export interface AuditLogEntry {
+  id: number;
+  operation: string;
+  accountUlid: string | null;
+  interface: string;
+  succeeded: boolean;
+  createdAt: string;
+  hash: string;
+  // バックアップから復元した記録の場合、復元前の最新の記録（"ID ハッシュ"）
+  replacedHead: string | null;
+}
+
+export interface AuditLogFilter {
+  operation: string;
+  accountUlid: string;
+  interface: string;
+  since: string;
+  until: string;
+  limit?: number;
+}
+
+export interface AuditLogVerification {
+  valid: boolean;
+  recordCount: number;
+  brokenAt: number | null;
+  reason: string | null;
+}
+
+export interface FieldError {
+  field: keyof FormData;
+  index: number | null;
+  message: string;
+}
+
+// コマンドが返すエラー（code で分岐し、details は code ごとに内容が異なる）
+export type AppError =
+  | { code: "locked"; message: string; details: null }
+  | { code: "wrong_key"; message: string; details: null }
+  | { code: "tampered"; message: string; details: null }
+  | {
+      code: "compartment_locked";
+      message: string;
+      details: { categoryName: string };
+    }
+  | { code: "travel_mode"; message: string; details: null }
+  | { code: "vault_in_use"; message: string; details: null }
+  | { code: "not_found"; message: string; details: { resource: string } }
+  | {
+      code: "conflict";
+      message: string;
+      details: { current: AccountInfo | null };
+    }
+  | { code: "validation"; message: string; details: { errors: FieldError[] } }
+  | { code: "storage"; message: string; details: null }
+  | { code: "remote"; message: string; details: null }
+  | {
+      code: "incompatible_schema";
+      message: string;
+      details: { version: number };
+    }
+  | { code: "internal"; message: string; details: null };
+
+export type FormErrors = Partial<Record<keyof FormData, string>>;
+
+// サーバー側の入力エラーを項目ごとのメッセージに変換
+export function toFormErrors(fieldErrors: FieldError[]): FormErrors {
+  const errors: FormErrors = {};
+  for (const fieldError of fieldErrors) {
+    errors[fieldError.field] ??= fieldError.message;
+  }
+  return errors;
+}
+
+export interface AppliedMigration {
+  version: number;
+  description: string;
+}
+
+export interface MigrationReport {
+  applied: AppliedMigration[];
+  backupPath: string | null;
+}
+
+export interface DataLocation {
+  dataDir: string;
+  source: "commandLine" | "environment" | "portable" | "config" | "default";
+  legacyVault: string | null;
+}
+
+export type KeySource =
+  | { type: "default" }
+  | { type: "file" }
+  | { type: "env"; name: string };
+
+export interface VaultInfo {
+  id: string;
+  name: string;
+  dir: string;
+  keySource: KeySource;
+  isOpen: boolean;
+  isCurrent: boolean;
+}
+
+export interface BackupInfo {
+  fileName: string;
+  createdAt: string;
+  size: number;
+}
+
+export interface RemoteBackupInfo {
+  fileName: string;
+  size: number;
+}
+
+export interface SyncReport {
+  peers: number;
+  applied: number;
+  conflicts: number;
+  // 変更セットが壊れている・読めないため取り込まなかった端末のID
+  skippedPeers: string[];
+}
+
+// 同期で自動的に統合できなかった変更（値は field ごとに形が異なる）
+export interface SyncConflict {
+  id: number;
+  accountUlid: string;
+  accountName: string | null;
+  field:
+    | "accountName"
+    | "identifier"
+    | "passwords"
+    | "categoryName"
+    | "trashed"
+    | "deleted";
+  localValue: unknown;
+  remoteValue: unknown;
+  createdAt: string;
+}
+
+// 保管庫のキーを預けている管理者の公開鍵
+export interface KeyEscrowInfo {
+  recipient: string;
+  keyFingerprint: string;
+  createdAt: string;
+}
+
+export interface ScrubReport {
+  freePages: number;
+  sizeBefore: number;
+  sizeAfter: number;
+}
+
+export interface TravelModeInfo {
+  enabled: boolean;
+  safeCategories: string[];
+  enabledAt: string | null;
+}
+
+export interface CompartmentInfo {
+  categoryName: string;
+  accountCount: number;
+  relockMinutes: number;
+  relocksInSecs: number | null;
+}
+
+export type DoctorIssueKind =
+  | "integrity"
+  | "foreignKey"
+  | "undecryptablePassword"
+  | "orphanIdentifier"
+  | "accountWithoutIdentifier"
+  | "accountWithoutCategory"
+  | "duplicateCategoryLink"
+  | "orphanCategory";
+
+export interface DoctorIssue {
+  kind: DoctorIssueKind;
+  target: string;
+  detail: string;
+  repairable: boolean;
+}
+
+// 修復した場合、repaired は修復した問題、issues は修復後に残っている問題
+export interface DoctorReport {
+  issues: DoctorIssue[];
+  repaired: DoctorIssue[];
+  checkedPasswords: number;
+  skippedPasswords: number;
+  backupFileName: string | null;
+}
+
+// 起動時に保管庫を開けなかった原因（occurredAt はUNIX時刻の秒）
+export interface StartupError {
+  code: AppError["code"];
+  message: string;
+  suggestion: string;
+  dataDir: string;
+  vaultDir: string;
+  databasePath: string;
+  keyFilePath: string;
+  logPath: string | null;
+  occurredAt: number;
+}
+
+// 保管庫ごとの設定（時間や件数の0は無効または無制限を表す）
+export interface VaultSettings {
+  autoLockMinutes: number;
+  clipboardClearSeconds: number;
+  generator: GeneratorSettings;
+  backup: BackupSettings;
+  trashRetentionDays: number;
+  passwordHistoryLimit: number;
+}
+
+export interface GeneratorSettings {
+  length: number;
+  lowercase: boolean;
+  uppercase: boolean;
+  digits: boolean;
+  symbols: boolean;
+}
+
+// keep と remoteKeep は残すバックアップの数
+export interface BackupSettings {
+  keep: number;
+  everyChanges: number;
+  intervalHours: number;
+  remoteKeep: number;
+}
+
+// 保管庫を開く前から使うアプリ全体の設定
+export interface AppSettings {
+  uiLanguage: "en" | "ja";
 }
@@ -8,3 +8,6 @@ export const searchCriteriaStore = writable<SearchCriteria>({
 });
 
 export const accountInfoStore = writable<AccountInfo | null>(null);
+
+// 共有用に暗号化したアカウント（共有画面で表示してコピーする）
+export const sharedAccountsStore = writable<string>("");